@@ -0,0 +1,275 @@
+//! Directory archiving
+//! a directory is packed into a single zip or tar.gz temp file which is then uploaded as a document
+
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use tempfile::NamedTempFile;
+use walkdir::{DirEntry, WalkDir};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::output;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ArchiveFormat {
+    #[default]
+    Zip,
+    #[value(name = "tar.gz", alias = "tgz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+}
+
+/// what to do with symlinks in the directory
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Links {
+    // links to files are archived with the contents of the target, links to directories are not walked into
+    #[default]
+    Files,
+    // walk into linked directories too, loops are skipped
+    Follow,
+    // leave out every link
+    Skip,
+}
+
+/// what a walk through the directory leaves out
+#[derive(Debug, Default, Clone)]
+pub struct Walk {
+    pub include_hidden: bool,
+    pub links: Links,
+    // --exclude, in the syntax of .gitignore
+    pub excludes: Vec<String>,
+    // follow .gitignore and .ignore files, and leave out .git
+    pub gitignore: bool,
+}
+
+/// .gitignore and .ignore of the directories above the entry, the innermost last
+struct Ignores {
+    excludes: Gitignore,
+    files: Vec<(usize, Gitignore)>,
+}
+
+impl Ignores {
+    fn new(dir: &Path, excludes: &[String]) -> Self {
+        let mut builder = GitignoreBuilder::new(dir);
+        for exclude in excludes {
+            if let Err(err) = builder.add_line(None, exclude) {
+                output::print_warning(&format!("Ignoring --exclude {}: {}", exclude, err));
+            }
+        }
+        let excludes = builder.build().unwrap_or_else(|_| Gitignore::empty());
+        Ignores { excludes, files: Vec::new() }
+    }
+
+    /// read the ignore files of a directory that is walked into at `depth`
+    fn enter(&mut self, dir: &Path, depth: usize) {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut any = false;
+        for name in [".gitignore", ".ignore"] {
+            let path = dir.join(name);
+            if path.is_file() {
+                if let Some(err) = builder.add(&path) {
+                    output::print_warning(&format!("Ignoring a line of {}: {}", path.display(), err));
+                }
+                any = true;
+            }
+        }
+        if let (true, Ok(gitignore)) = (any, builder.build()) {
+            self.files.push((depth, gitignore));
+        }
+    }
+
+    fn is_excluded(&mut self, entry: &DirEntry, gitignore: bool) -> bool {
+        let is_dir = entry.file_type().is_dir();
+        if self.excludes.matched(entry.path(), is_dir).is_ignore() {
+            return true;
+        }
+        if !gitignore {
+            return false;
+        }
+        if is_dir && entry.file_name() == ".git" {
+            return true;
+        }
+        // the walk is depth first, the files of directories that aren't above this entry are done
+        self.files.retain(|(depth, _)| *depth < entry.depth());
+        let matched = self.files.iter().rev().map(|(_, gitignore)| gitignore.matched(entry.path(), is_dir)).find(|m| !m.is_none());
+        matched.is_some_and(|m| m.is_ignore())
+    }
+}
+
+/// how many files an excluded directory held, for the summary
+fn count_files(dir: &Path) -> usize {
+    WalkDir::new(dir).into_iter().filter_map(Result::ok).filter(|entry| entry.file_type().is_file()).count()
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+/// collect the files and directories to put into the archive, sorted so archives are reproducible
+/// each entry has the path on disk and the path inside of the archive, rooted at the directory name
+/// excludes only apply below the directory, it's never left out itself
+fn collect_entries(dir: &Path, walk: &Walk) -> Result<Vec<(PathBuf, PathBuf, bool)>, walkdir::Error> {
+    let root = dir.file_name().map(PathBuf::from).unwrap_or_default();
+    let links = walk.links;
+    let include_hidden = walk.include_hidden;
+    let mut entries = Vec::new();
+    let mut ignores = Ignores::new(dir, &walk.excludes);
+    let mut excluded = 0;
+    let mut walker = WalkDir::new(dir)
+        .follow_links(links == Links::Follow)
+        .sort_by_file_name()
+        .into_iter()
+        // depth 0 is the directory itself, it is allowed to be hidden
+        .filter_entry(|e| include_hidden || e.depth() == 0 || !is_hidden(e));
+    while let Some(entry) = walker.next() {
+        let entry = match entry {
+            // a link back to a directory above it would be walked forever
+            Err(err) if err.loop_ancestor().is_some() => {
+                let path = err.path().map(|path| path.display().to_string()).unwrap_or_default();
+                output::print_warning(&format!("Skipping {}, the link loops back to a directory above it", path));
+                continue;
+            }
+            // following a dangling link fails, without following it is only an entry to skip below
+            Err(err) if err.path().is_some_and(|path| path.is_symlink() && !path.exists()) => {
+                let path = err.path().map(|path| path.display().to_string()).unwrap_or_default();
+                output::print_warning(&format!("Skipping {}, the link points to nothing", path));
+                continue;
+            }
+            entry => entry?,
+        };
+        if entry.depth() > 0 && ignores.is_excluded(&entry, walk.gitignore) {
+            if entry.file_type().is_dir() {
+                excluded += count_files(entry.path());
+                walker.skip_current_dir();
+            } else {
+                excluded += 1;
+            }
+            continue;
+        }
+        if walk.gitignore && entry.file_type().is_dir() {
+            ignores.enter(entry.path(), entry.depth());
+        }
+        let relative = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let archive_path = root.join(relative);
+        if entry.path_is_symlink() && entry.depth() > 0 {
+            if links == Links::Skip {
+                eprintln!("Skipping the link {} (--no-follow)", entry.path().display());
+                continue;
+            }
+            if !entry.path().exists() {
+                output::print_warning(&format!("Skipping {}, the link points to nothing", entry.path().display()));
+                continue;
+            }
+        }
+        // path.is_dir/is_file follow symlinks, anything else (sockets, fifos) is skipped
+        if entry.path().is_dir() {
+            entries.push((entry.path().to_path_buf(), archive_path, true));
+        } else if entry.path().is_file() {
+            entries.push((entry.path().to_path_buf(), archive_path, false));
+        }
+    }
+    if excluded > 0 {
+        eprintln!("Skipped {} files matching excludes in {}", excluded, dir.display());
+    }
+    Ok(entries)
+}
+
+/// the files in the directory and below in the order of the archive, for --recursive
+/// each with the path relative to the parent of the directory, e.g. reports/2024/q1.pdf
+pub fn files(dir: &Path, walk: &Walk) -> Result<Vec<(PathBuf, PathBuf)>, walkdir::Error> {
+    let entries = collect_entries(dir, walk)?;
+    Ok(entries.into_iter().filter(|(_, _, is_dir)| !is_dir).map(|(path, relative, _)| (path, relative)).collect())
+}
+
+/// a file below the directory would be left out of a walk, for files that turn up while watching it
+pub fn is_excluded(dir: &Path, path: &Path, walk: &Walk) -> bool {
+    let Ok(relative) = path.strip_prefix(dir) else {
+        return false;
+    };
+    if !walk.include_hidden && relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.')) {
+        return true;
+    }
+    let mut ignores = Ignores::new(dir, &walk.excludes);
+    if ignores.excludes.matched_path_or_any_parents(path, false).is_ignore() {
+        return true;
+    }
+    if !walk.gitignore {
+        return false;
+    }
+    if relative.components().any(|c| c.as_os_str() == ".git") {
+        return true;
+    }
+    // the ignore files of the directory and every one between it and the file
+    let mut current = dir.to_path_buf();
+    ignores.enter(&current, 0);
+    for (depth, component) in relative.parent().into_iter().flat_map(Path::components).enumerate() {
+        current.push(component);
+        ignores.enter(&current, depth + 1);
+    }
+    let matched = ignores.files.iter().rev().map(|(_, gitignore)| gitignore.matched_path_or_any_parents(path, false)).find(|m| !m.is_none());
+    matched.is_some_and(|m| m.is_ignore())
+}
+
+/// zip entry names always use forward slashes
+fn zip_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn write_zip<W: Write + Seek>(writer: W, entries: &[(PathBuf, PathBuf, bool)]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut zip = ZipWriter::new(writer);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (path, archive_path, is_dir) in entries {
+        if *is_dir {
+            zip.add_directory(zip_name(archive_path), options)?;
+        } else {
+            zip.start_file(zip_name(archive_path), options)?;
+            let mut file = File::open(path)?;
+            std::io::copy(&mut file, &mut zip)?;
+        }
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn write_tar_gz<W: Write>(writer: W, entries: &[(PathBuf, PathBuf, bool)]) -> Result<(), Box<dyn std::error::Error>> {
+    let encoder = GzEncoder::new(writer, Compression::default());
+    let mut tar = tar::Builder::new(encoder);
+    for (path, archive_path, is_dir) in entries {
+        if *is_dir {
+            tar.append_dir(archive_path, path)?;
+        } else {
+            tar.append_path_with_name(path, archive_path)?;
+        }
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// archive the directory into a temp file
+/// the file is deleted when the returned handle is dropped
+pub fn archive_dir(dir: &Path, format: ArchiveFormat, walk: &Walk) -> Result<NamedTempFile, Box<dyn std::error::Error>> {
+    let entries = collect_entries(dir, walk)?;
+    let tmp = NamedTempFile::new()?;
+    let file = tmp.reopen()?;
+    match format {
+        ArchiveFormat::Zip => write_zip(file, &entries)?,
+        ArchiveFormat::TarGz => write_tar_gz(file, &entries)?,
+    }
+    Ok(tmp)
+}
@@ -0,0 +1,72 @@
+//! --button, a row of inline keyboard buttons under the sent message that open a URL
+//! "Label|URL" is a button, "Label|URL||Label|URL" puts two next to each other, every --button is a row of its own
+//! https://core.telegram.org/bots/api#inlinekeyboardmarkup
+
+use serde::Serialize;
+
+// telegram takes up to 8 buttons in a row and 100 in a keyboard
+const ROW_LIMIT: usize = 8;
+const KEYBOARD_LIMIT: usize = 100;
+// the schemes telegram opens, anything else is refused with BUTTON_URL_INVALID
+const SCHEMES: &[&str] = &["http://", "https://", "tg://"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Button {
+    text: String,
+    url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct Row(Vec<Button>);
+
+/// a row of buttons from --button
+pub fn parse(s: &str) -> Result<Row, String> {
+    let buttons = s.split("||").map(parse_button).collect::<Result<Vec<_>, _>>()?;
+    if buttons.len() > ROW_LIMIT {
+        return Err(format!("{} buttons in a row, Telegram takes up to {}", buttons.len(), ROW_LIMIT));
+    }
+    Ok(Row(buttons))
+}
+
+/// the rows of all --button flags fit in one keyboard
+pub fn check(rows: &[Row]) -> Result<(), String> {
+    let count: usize = rows.iter().map(|row| row.0.len()).sum();
+    if count > KEYBOARD_LIMIT {
+        return Err(format!("{} buttons, Telegram takes up to {} under a message", count, KEYBOARD_LIMIT));
+    }
+    Ok(())
+}
+
+fn parse_button(s: &str) -> Result<Button, String> {
+    let Some((text, url)) = s.split_once('|') else {
+        return Err(format!("Expected LABEL|URL, not {:?}", s));
+    };
+    let (text, url) = (text.trim(), url.trim());
+    if text.is_empty() {
+        return Err(format!("The button for {} has no label", url));
+    }
+    let known = SCHEMES.iter().any(|scheme| url.len() > scheme.len() && url.get(..scheme.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme)));
+    if !known {
+        return Err(format!("{} is not a URL Telegram opens, it takes http://, https:// and tg:// links", url));
+    }
+    Ok(Button {
+        text: text.to_string(),
+        url: url.to_string(),
+    })
+}
+
+#[derive(Serialize)]
+struct Markup<'a> {
+    inline_keyboard: &'a [Row],
+}
+
+/// the reply_markup of a send call, serialized as an object in a json body
+pub fn serialize<S: serde::Serializer>(rows: &[Row], serializer: S) -> Result<S::Ok, S::Error> {
+    Markup { inline_keyboard: rows }.serialize(serializer)
+}
+
+/// the reply_markup as text, for a multipart form
+pub fn markup(rows: &[Row]) -> String {
+    serde_json::to_string(&Markup { inline_keyboard: rows }).expect("buttons serialize")
+}
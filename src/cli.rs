@@ -0,0 +1,926 @@
+//! Command line definitions
+//! uploading is the default, `teledrop file.bin` is the same as `teledrop upload file.bin`
+//! every other subcommand gets its own args struct and a variant in `Command`
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueHint};
+
+use crate::archive::{ArchiveFormat, Links, Walk};
+use crate::compress::Compression;
+use crate::error::Error;
+use crate::history;
+use crate::output::{self, ColorChoice, OutputMode};
+use crate::state::ResumeCheck;
+use crate::{parse_size, template, validate_caption, validate_name, ParseMode, SendOptions, CAPTION_PLACEHOLDERS, STDIN_FILENAME};
+
+const EXIT_CODES: &str = "Exit codes:
+  0  all files uploaded
+  1  other error
+  2  invalid arguments or config
+  3  file error: missing, unreadable or too big
+  4  Telegram API error
+  5  network error
+  6  message not found, e.g. already deleted
+  7  nothing changed, e.g. the caption is the same
+  8  the bot token was refused, it is wrong or was revoked
+  9  chat not found, e.g. a wrong chat_id
+  10 the bot was removed from the chat or blocked
+  130 interrupted with Ctrl+C";
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "teledrop",
+    version,
+    propagate_version = true,
+    about = "Upload files to Telegram with a bot",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub upload: Args,
+}
+
+// parsed once per run, boxing Args wouldn't save anything
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Upload files (the default when no subcommand is given)
+    Upload(Args),
+    /// Watch directories and upload every new file once it's written, until SIGTERM
+    Watch(WatchArgs),
+    /// Send the uploads that were queued while the network was down
+    Flush(FlushArgs),
+    /// List or remove the uploads waiting for `teledrop flush`
+    #[command(subcommand)]
+    Queue(QueueCommand),
+    /// Put the parts of a file sent with --split back together
+    Join(JoinArgs),
+    /// Decrypt a file sent with --encrypt
+    Decrypt(DecryptArgs),
+    /// Download a file by its file ID
+    Download(DownloadArgs),
+    /// Print a fresh download URL for a file ID
+    Url(UrlArgs),
+    /// Show the size and path of a file ID without downloading it
+    Info(InfoArgs),
+    /// Send a file that is already on Telegram again, by its file ID
+    Reshare(ReshareArgs),
+    /// Delete a sent message
+    Delete(DeleteArgs),
+    /// Change the caption of a sent message
+    EditCaption(EditCaptionArgs),
+    /// Forward a sent message to another chat
+    Forward(ForwardArgs),
+    /// List the uploaded files, newest first
+    #[command(args_conflicts_with_subcommands = true)]
+    History(HistoryArgs),
+    /// List the chat names from the config with their titles
+    Chats(ChatsArgs),
+    /// Show the title, type and members of a chat
+    ChatInfo(ChatInfoArgs),
+    /// Set up the bot token and find the chat ID, for the first run
+    Setup,
+    /// Check the config, the token, the chat and the connection to Telegram
+    Doctor(DoctorArgs),
+    /// Send a short text message to check that sending works, e.g. before a cron job
+    Test(TestArgs),
+    /// Show or change the config file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Print the completion script for a shell
+    Completions(CompletionsArgs),
+    /// Print the man page, for packaging
+    #[command(hide = true)]
+    Man(ManArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Files to upload, in order. Use - to read from stdin (requires --name),
+    /// directories are archived and sent as a single file, http(s) URLs are fetched by Telegram
+    #[arg(value_name = "FILE", required_unless_present_any = ["files_from", "files_from0"], value_hint = ValueHint::AnyPath)]
+    pub filenames: Vec<PathBuf>,
+    /// Also upload the files listed in PATH, one per line, - reads the list from stdin
+    #[arg(long, value_name = "PATH", conflicts_with = "files_from0", value_hint = ValueHint::FilePath)]
+    pub files_from: Option<PathBuf>,
+    /// Same as --files-from with the paths separated by NUL, like find -print0 writes them
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub files_from0: Option<PathBuf>,
+    /// Download http(s) URLs and upload them when Telegram can't fetch them itself, or an option needs the file
+    #[arg(long)]
+    pub fetch: bool,
+    /// Filename to show in Telegram (default: basename of FILE)
+    #[arg(short, long, value_parser = |s: &str| validate_name(s).map(|_| s.to_string()))]
+    pub name: Option<String>,
+    /// Stop at the first file that fails to upload
+    #[arg(long)]
+    pub fail_fast: bool,
+    /// Upload up to N files at once
+    #[arg(short, long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub jobs: u16,
+    /// Group photos and videos into albums of up to 10, documents and audio into albums of their own
+    #[arg(long)]
+    pub album: bool,
+    /// Skip the files an interrupted run of the same batch uploaded already
+    #[arg(long)]
+    pub resume: bool,
+    /// How --resume tells a file didn't change since it was uploaded
+    #[arg(long, value_enum, value_name = "CHECK", default_value_t)]
+    pub resume_check: ResumeCheck,
+    /// Keep what a batch uploaded in PATH (default: a file per batch in the data dir)
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub state: Option<PathBuf>,
+    /// Keep the state file when every file of the batch was uploaded
+    #[arg(long)]
+    pub keep_state: bool,
+    /// Print what would be sent where, with names, sizes and captions, without sending anything
+    #[arg(long)]
+    pub dry_run: bool,
+    /// With --dry-run, check the token and that the bot can see the chats (getMe and getChat only)
+    #[arg(long, requires = "dry_run")]
+    pub verify: bool,
+    /// Shell command to run after each uploaded file, with TELEDROP_FILE, TELEDROP_FILE_ID, TELEDROP_URL,
+    /// TELEDROP_MESSAGE_ID, TELEDROP_NAME, TELEDROP_SIZE and TELEDROP_SHA256 set (default: on_success from the config)
+    #[arg(long, value_name = "CMD")]
+    pub on_success: Option<String>,
+    /// Shell command to run after each file that failed, with TELEDROP_FILE and TELEDROP_ERROR set (default: on_failure)
+    #[arg(long, value_name = "CMD")]
+    pub on_failure: Option<String>,
+    /// Delete each file once it's uploaded and Telegram answered with its file_id, never stdin or a directory
+    #[arg(long, conflicts_with = "dry_run")]
+    pub rm: bool,
+    /// Same as --rm, only when the size Telegram reports is the size that was sent
+    #[arg(long, conflicts_with = "dry_run")]
+    pub rm_verify: bool,
+    /// Move each file into DIR once it's uploaded, created when it's missing. A file of the same name there gets a number
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["dry_run", "rm", "rm_verify"], value_hint = ValueHint::DirPath)]
+    pub move_to: Option<PathBuf>,
+    /// Queue the files that fail because the network is down without asking, `teledrop flush` sends them later
+    #[arg(long, conflicts_with = "no_queue")]
+    pub queue: bool,
+    /// Don't offer to queue the files that fail because the network is down
+    #[arg(long)]
+    pub no_queue: bool,
+    /// Archive format for directories
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t)]
+    pub archive_format: ArchiveFormat,
+    /// Leave out hidden files when archiving a directory
+    #[arg(long)]
+    pub no_hidden: bool,
+    /// Leave out files and directories matching the pattern when walking a directory, like a line of .gitignore
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    /// Leave out what .gitignore and .ignore files in the directory exclude, and .git
+    #[arg(long)]
+    pub gitignore: bool,
+    /// Walk into linked directories when archiving, links that loop back are skipped
+    #[arg(long, conflicts_with = "no_follow")]
+    pub follow_symlinks: bool,
+    /// Skip symlinks, given as FILE or found in a directory
+    #[arg(long)]
+    pub no_follow: bool,
+    /// Send each file in a directory as its own message, captioned with its path
+    #[arg(short, long, conflicts_with = "name")]
+    pub recursive: bool,
+    /// With --recursive, put the path into the name (reports_2024_q1.pdf) instead of the caption
+    #[arg(long, requires = "recursive")]
+    pub prefix_name: bool,
+    #[command(flatten)]
+    pub send: SendOptions,
+    /// Caption with placeholders: {name} {size} {size_human} {sha256} {md5} {mtime} {hostname}
+    #[arg(long, value_name = "TEXT", conflicts_with = "caption", value_parser = |s: &str| {
+        template::validate(s, CAPTION_PLACEHOLDERS).map(|_| s.to_string())
+    })]
+    pub caption_template: Option<String>,
+    /// Add the SHA-256 of the file to the caption
+    #[arg(long)]
+    pub checksum_caption: bool,
+    /// Print extra details like the detected MIME type and trace the requests, -vv for headers and timings
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// Content type to send instead of the detected one
+    #[arg(long, value_name = "TYPE", value_parser = parse_mime)]
+    pub mime: Option<String>,
+    /// Send photos, videos, audio and GIFs as documents too, photos keep their full quality
+    #[arg(long, conflicts_with_all = ["as_photo", "as_video"])]
+    pub as_document: bool,
+    /// Send with sendPhoto whatever the type of the file
+    #[arg(long, conflicts_with = "as_video")]
+    pub as_photo: bool,
+    /// Send with sendVideo whatever the type of the file, streamable in the clients
+    #[arg(long)]
+    pub as_video: bool,
+    /// JPEG shown for documents, videos and audio instead of the generic icon, up to 200 kB and 320x320
+    #[arg(long, value_name = "PATH", value_parser = crate::thumb::parse, conflicts_with = "album", value_hint = ValueHint::FilePath)]
+    pub thumb: Option<PathBuf>,
+    /// Upload size limit, e.g. 2G with a local Bot API server (default: 50M, or max_upload_size in the config)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub upload_limit: Option<u64>,
+    /// With several files, skip the ones bigger than this, e.g. 10M, one over the upload limit is still an error
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_size: Option<u64>,
+    /// With several files, skip the ones smaller than this, e.g. 10K
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub min_size: Option<u64>,
+    /// Send empty files as a single newline, Telegram refuses files without content
+    #[arg(long)]
+    pub allow_empty: bool,
+    /// Compress before uploading, auto skips files that are already compressed
+    #[arg(long, value_enum, value_name = "METHOD")]
+    pub compress: Option<Compression>,
+    /// Encrypt with a passphrase before uploading (AES-256-GCM)
+    #[arg(long)]
+    pub encrypt: bool,
+    /// Read the passphrase from a file instead of prompting
+    #[arg(long, value_name = "PATH", requires = "encrypt", value_hint = ValueHint::FilePath)]
+    pub passphrase_file: Option<String>,
+    /// Split files over the limit into parts with a manifest
+    #[arg(long)]
+    pub split: bool,
+    /// Size of the parts (default: just under the limit)
+    #[arg(long, value_name = "SIZE", requires = "split", value_parser = parse_size)]
+    pub split_size: Option<u64>,
+    /// Only upload these parts of a split file, e.g. 3,5
+    #[arg(long, value_name = "LIST", requires = "split", value_delimiter = ',')]
+    pub parts: Option<Vec<usize>>,
+    #[command(flatten)]
+    pub print: OutputArgs,
+    /// Send files uploaded before (same SHA-256 in the history) by their file ID without asking
+    #[arg(long, conflicts_with = "no_dedupe")]
+    pub dedupe: bool,
+    /// Always upload, don't look for the file in the history
+    #[arg(long)]
+    pub no_dedupe: bool,
+    /// Don't check that the bot may send to the chat before uploading big files
+    #[arg(long)]
+    pub no_precheck: bool,
+    /// Pin the message in the chat after uploading (not in private chats)
+    #[arg(long)]
+    pub pin: bool,
+    /// Pin without notifying the members of the chat, implies --pin
+    #[arg(long)]
+    pub pin_silent: bool,
+    /// Copy the download URL to the clipboard
+    #[arg(long)]
+    pub copy: bool,
+    /// Show the download URL as a QR code (on stderr with --quiet, --json or --format)
+    #[arg(long)]
+    pub qr: bool,
+    /// Colored output, auto turns it off when not writing to a terminal or NO_COLOR is set
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t)]
+    pub color: ColorChoice,
+    /// Chat to send the files to instead of chat_id from the config, e.g. -1001234567890, @channelname
+    /// or a name from the [chats] table of the config. Repeat it or separate chats with commas to send
+    /// to several, the file is uploaded once and sent to the others by its file ID
+    #[arg(long, value_name = "CHAT", allow_hyphen_values = true, value_delimiter = ',')]
+    pub chat: Vec<String>,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+    // how results are printed, from --json, --format and --quiet
+    #[arg(skip)]
+    pub output: OutputMode,
+    // how many files --files-from or --files-from0 listed
+    #[arg(skip)]
+    pub listed: Option<usize>,
+    // the options as they were given, without the files, for the queue
+    #[arg(skip)]
+    pub replay: Vec<String>,
+}
+
+/// config values that can be given on the command line for a single run
+/// they win over the config file, which is never written
+#[derive(Debug, clap::Args)]
+pub struct ConfigArgs {
+    /// Use the keys of [profiles.NAME] from the config instead of default_profile
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// Don't look for a .teledrop.toml in the working directory and its parents
+    #[arg(long)]
+    pub no_local_config: bool,
+    /// Bot token to use instead of bot_token from the config
+    #[arg(long, value_name = "TOKEN")]
+    pub token: Option<String>,
+    /// Seconds to wait for the connection to the server (default: 30)
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub connect_timeout: Option<u64>,
+    /// Seconds a request may take, uploads and downloads get more by their size unless this is set
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: Option<u64>,
+    /// How often a failed upload or getFile call is tried again, 0 for never (default: 3)
+    #[arg(long, value_name = "N")]
+    pub retries: Option<u32>,
+    /// Seconds before the first retry, doubled for every next one (default: 1)
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub retry_delay: Option<u64>,
+    /// Longest wait in seconds a 429 (Too Many Requests) may ask for, longer ones fail right away (default: 300)
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_retry_after: Option<u64>,
+    /// Messages a minute sent to a chat, every chat is paced on its own (default: 60, 20 for groups and channels)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub rate: Option<u32>,
+    /// Append a log of this run to the file, like log_file in the config
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub log_file: Option<String>,
+}
+
+/// how upload results are printed, shared by the subcommands that send a document
+#[derive(Debug, clap::Args)]
+pub struct OutputArgs {
+    /// Print results as JSON, one object per file
+    #[arg(long, group = "output_mode")]
+    pub json: bool,
+    /// Print results with a template instead, e.g. '{file_id}\t{url}'
+    /// placeholders: {file_id} {file_unique_id} {message_id} {chat_id} {url} {name} {size} {sha256}
+    #[arg(long, value_name = "TEMPLATE", group = "output_mode", verbatim_doc_comment, value_parser = |s: &str| {
+        template::validate(s, output::FORMAT_PLACEHOLDERS).map(|_| output::unescape(s))
+    })]
+    pub format: Option<String>,
+    /// Print only the download URL
+    #[arg(short, long, group = "output_mode")]
+    pub quiet: bool,
+}
+
+impl OutputArgs {
+    pub fn mode(&self) -> OutputMode {
+        match (&self.format, self.json, self.quiet) {
+            (Some(format), _, _) => OutputMode::Format(format.clone()),
+            (_, true, _) => OutputMode::Json,
+            (_, _, true) => OutputMode::Quiet,
+            _ => OutputMode::Human,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct JoinArgs {
+    /// The manifest, or the part files
+    #[arg(value_name = "MANIFEST | PARTS", required = true, value_hint = ValueHint::FilePath)]
+    pub inputs: Vec<String>,
+    /// Where to write the joined file (default: the original name)
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DecryptArgs {
+    /// The encrypted file
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub input: String,
+    /// Where to write the decrypted file (default: FILE without .enc)
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<String>,
+    /// Read the passphrase from a file instead of prompting
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub passphrase_file: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DownloadArgs {
+    /// File ID printed by the upload, or #ID from the history
+    #[arg(value_name = "FILE_ID")]
+    pub file_id: String,
+    /// Where to write the file (default: the name Telegram stores it under)
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<String>,
+    /// Overwrite the output file if it exists
+    #[arg(short, long)]
+    pub force: bool,
+    /// Start over instead of resuming from a left over OUTPUT.part file
+    #[arg(long)]
+    pub no_resume: bool,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct UrlArgs {
+    /// File ID printed by the upload, or #ID from the history
+    #[arg(value_name = "FILE_ID")]
+    pub file_id: String,
+    /// Print the file as JSON
+    #[arg(long, group = "output_mode")]
+    pub json: bool,
+    /// Print the file with a template instead, e.g. '{size}\t{url}'
+    /// placeholders: {file_id} {file_unique_id} {size} {file_path} {url}
+    #[arg(long, value_name = "TEMPLATE", group = "output_mode", verbatim_doc_comment, value_parser = |s: &str| {
+        template::validate(s, output::FILE_FORMAT_PLACEHOLDERS).map(|_| output::unescape(s))
+    })]
+    pub format: Option<String>,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+impl UrlArgs {
+    pub fn output(&self) -> OutputMode {
+        match (&self.format, self.json) {
+            (Some(format), _) => OutputMode::Format(format.clone()),
+            (_, true) => OutputMode::Json,
+            _ => OutputMode::Human,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct InfoArgs {
+    /// File ID printed by the upload, or #ID from the history
+    #[arg(value_name = "FILE_ID")]
+    pub file_id: String,
+    /// Print the file as JSON
+    #[arg(long)]
+    pub json: bool,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ReshareArgs {
+    /// File ID printed by the upload, or #ID from the history
+    #[arg(value_name = "FILE_ID")]
+    pub file_id: String,
+    /// Chat to send it to (default: chat_id from the config)
+    #[arg(long, value_name = "CHAT", allow_hyphen_values = true)]
+    pub chat: Option<String>,
+    #[command(flatten)]
+    pub send: SendOptions,
+    #[command(flatten)]
+    pub print: OutputArgs,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DeleteArgs {
+    /// Message ID printed by the upload, #ID or a file ID from the history
+    #[arg(value_name = "MESSAGE_ID")]
+    pub message: String,
+    /// Chat the message is in (default: chat_id from the config)
+    #[arg(long, value_name = "CHAT", allow_hyphen_values = true)]
+    pub chat: Option<String>,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct EditCaptionArgs {
+    /// Message ID printed by the upload, #ID or a file ID from the history
+    #[arg(value_name = "MESSAGE_ID")]
+    pub message: String,
+    /// The new caption (up to 1024 characters), empty to remove it
+    #[arg(value_name = "TEXT", value_parser = |s: &str| validate_caption(s).map(|_| s.to_string()))]
+    pub caption: String,
+    /// Caption formatting
+    #[arg(long, value_enum, value_name = "MODE", ignore_case = true)]
+    pub parse_mode: Option<ParseMode>,
+    /// Chat the message is in (default: chat_id from the config)
+    #[arg(long, value_name = "CHAT", allow_hyphen_values = true)]
+    pub chat: Option<String>,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ForwardArgs {
+    /// Message ID printed by the upload, #ID or a file ID from the history
+    #[arg(value_name = "MESSAGE_ID")]
+    pub message: String,
+    /// Chat to forward the message to
+    #[arg(long, value_name = "CHAT", allow_hyphen_values = true)]
+    pub to: String,
+    /// Chat the message is in (default: chat_id from the config)
+    #[arg(long, value_name = "CHAT", allow_hyphen_values = true)]
+    pub from: Option<String>,
+    /// Forward without a notification
+    #[arg(long)]
+    pub silent: bool,
+    /// Protect the forwarded message from forwarding and saving
+    #[arg(long)]
+    pub protect: bool,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+#[command(mut_arg("filenames", |arg| arg.value_name("DIR").help("Directories to watch, with --recursive the ones below too")))]
+pub struct WatchArgs {
+    /// How long a new file has to keep its size and modification time before it's sent
+    #[arg(long, value_name = "SECS", default_value_t = 2)]
+    pub settle: u64,
+    #[command(flatten)]
+    pub upload: Args,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct FlushArgs {
+    /// Also send the files that changed since they were queued, as they are now
+    #[arg(long)]
+    pub include_changed: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum QueueCommand {
+    /// List the queued uploads, oldest first
+    List(QueueListArgs),
+    /// Take uploads out of the queue, the files stay where they are
+    Rm(QueueRmArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct QueueListArgs {
+    /// Print the queued uploads as JSON, one object per line
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct QueueRmArgs {
+    /// IDs from `teledrop queue list`, like 3 or #3
+    #[arg(value_name = "ID", required = true, value_parser = |s: &str| s.trim_start_matches('#').parse::<u64>().map_err(|_| format!("Invalid ID {}", s)))]
+    pub ids: Vec<u64>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TestArgs {
+    /// Chat to send it to (default: chat_id from the config)
+    #[arg(long, value_name = "CHAT", allow_hyphen_values = true)]
+    pub chat: Option<String>,
+    /// Delete the test message again afterwards
+    #[arg(long)]
+    pub delete: bool,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DoctorArgs {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ChatInfoArgs {
+    /// Chat ID, @username or a name from [chats] (default: chat_id from the config)
+    #[arg(value_name = "CHAT", allow_hyphen_values = true)]
+    pub chat: Option<String>,
+    /// Print the chat as JSON
+    #[arg(long)]
+    pub json: bool,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ChatsArgs {
+    /// Print the chats as JSON, one object per line
+    #[arg(long)]
+    pub json: bool,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct HistoryArgs {
+    /// How many uploads to show
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 20)]
+    pub limit: usize,
+    /// Print the uploads as JSON, one object per line
+    #[arg(long, conflicts_with = "count")]
+    pub json: bool,
+    /// Print only the number of matching uploads
+    #[arg(long)]
+    pub count: bool,
+    #[command(flatten)]
+    pub filter: history::Filter,
+    #[command(subcommand)]
+    pub command: Option<HistoryCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum HistoryCommand {
+    /// Write the history as CSV or JSON lines, oldest first
+    Export(ExportArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ExportArgs {
+    #[arg(long, value_enum, default_value_t)]
+    pub format: history::ExportFormat,
+    /// File to write to (default: stdout)
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub output: Option<String>,
+    #[command(flatten)]
+    pub filter: history::Filter,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the location of the config file
+    Path,
+    /// Print a key from the config file, the token is masked
+    Get(ConfigGetArgs),
+    /// Change a key in the config file, an empty value removes optional keys
+    Set(ConfigSetArgs),
+    /// Make the config file readable only by you (chmod 600)
+    FixPerms,
+    /// Open the config file in $VISUAL or $EDITOR
+    Edit,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ConfigGetArgs {
+    #[arg(value_parser = clap::builder::PossibleValuesParser::new(crate::CONFIG_FIELDS))]
+    pub key: String,
+    /// Print the bot token in full
+    #[arg(long)]
+    pub reveal: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ConfigSetArgs {
+    #[arg(value_parser = clap::builder::PossibleValuesParser::new(crate::CONFIG_FIELDS))]
+    pub key: String,
+    /// New value, broadcast takes chats separated by commas
+    #[arg(allow_hyphen_values = true)]
+    pub value: String,
+    /// Store the bot token in the keyring of the system instead of the file (needs the keyring feature)
+    #[arg(long)]
+    pub keyring: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ManArgs {
+    /// Print the page of this subcommand instead, e.g. join for teledrop-join(1)
+    pub subcommand: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CompletionsArgs {
+    #[arg(value_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+impl Command {
+    /// how results and errors of the subcommand are printed
+    pub fn output(&self) -> OutputMode {
+        match self {
+            Command::Upload(args) => args.output.clone(),
+            Command::Watch(args) => args.upload.output.clone(),
+            Command::Queue(QueueCommand::List(args)) if args.json => OutputMode::Json,
+            Command::Url(args) => args.output(),
+            Command::Info(args) if args.json => OutputMode::Json,
+            Command::History(args) if args.json => OutputMode::Json,
+            Command::Chats(args) if args.json => OutputMode::Json,
+            Command::ChatInfo(args) if args.json => OutputMode::Json,
+            Command::Reshare(args) => args.print.mode(),
+            _ => OutputMode::Human,
+        }
+    }
+}
+
+/// same check reqwest does in mime_str, but before any file is read
+fn parse_mime(s: &str) -> Result<String, String> {
+    s.parse::<mime_guess::mime::Mime>()
+        .map(|_| s.to_string())
+        .map_err(|err| format!("Invalid MIME type {}: {}", s, err))
+}
+
+/// expand patterns like *.log that the shell left alone, e.g. cmd and PowerShell on Windows
+/// a path that exists is taken as it is, even with * or ? in its name, so what a unix shell expanded stays the same
+fn expand_globs(filenames: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut expanded = Vec::with_capacity(filenames.len());
+    for filename in filenames {
+        let pattern = match filename.to_str() {
+            Some(pattern) if pattern.contains(['*', '?', '[']) && filename.symlink_metadata().is_err() && crate::http_url(filename).is_none() => pattern,
+            _ => {
+                expanded.push(filename.clone());
+                continue;
+            }
+        };
+        let paths = glob::glob(pattern).map_err(|err| format!("Invalid pattern {}: {}", pattern, err))?;
+        let mut matches = paths.filter_map(Result::ok).collect::<Vec<_>>();
+        if matches.is_empty() {
+            return Err(format!("No files match {}", pattern));
+        }
+        matches.sort();
+        expanded.extend(matches);
+    }
+    Ok(expanded)
+}
+
+/// the paths of --files-from (one per line, blank lines and # comments are left out)
+/// or --files-from0 (separated by NUL, taken as they are)
+/// relative paths are relative to the working directory, the entries aren't checked here so a missing one only fails itself
+fn read_file_list(list: &Path, separator: u8) -> Result<Vec<PathBuf>, String> {
+    let contents = if list.as_os_str() == STDIN_FILENAME {
+        let mut contents = Vec::new();
+        std::io::stdin().read_to_end(&mut contents).map(|_| contents)
+    } else {
+        std::fs::read(list)
+    };
+    let contents = contents.map_err(|err| Error::io(list.display(), &err).to_string())?;
+    let mut paths = Vec::new();
+    for entry in contents.split(|b| *b == separator) {
+        let entry = if separator == b'\n' {
+            let entry = entry.strip_suffix(b"\r").unwrap_or(entry);
+            if entry.trim_ascii().is_empty() || entry.trim_ascii_start().starts_with(b"#") {
+                continue;
+            }
+            entry
+        } else if entry.is_empty() {
+            continue;
+        } else {
+            entry
+        };
+        paths.push(path_from_bytes(entry));
+    }
+    Ok(paths)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+// paths on windows are utf-16, a list holds them as utf-8
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+impl Args {
+    /// what archiving and --recursive leave out
+    pub fn walk(&self) -> Walk {
+        let links = match (self.follow_symlinks, self.no_follow) {
+            (_, true) => Links::Skip,
+            (true, _) => Links::Follow,
+            _ => Links::Files,
+        };
+        Walk {
+            include_hidden: !self.no_hidden,
+            links,
+            excludes: self.exclude.clone(),
+            gitignore: self.gitignore,
+        }
+    }
+
+    /// checks clap can't express, and the output mode from the flags
+    fn finish(&mut self) -> Result<(), String> {
+        self.filenames = expand_globs(&self.filenames)?;
+        let list = match (&self.files_from, &self.files_from0) {
+            (Some(list), _) => Some((list, b'\n')),
+            (_, Some(list)) => Some((list, b'\0')),
+            _ => None,
+        };
+        if let Some((list, separator)) = list {
+            if list.as_os_str() == STDIN_FILENAME && self.filenames.iter().any(|f| f.as_os_str() == STDIN_FILENAME) {
+                return Err("stdin can't be both the file list and a file to upload".to_string());
+            }
+            let listed = read_file_list(list, separator)?;
+            self.listed = Some(listed.len());
+            self.filenames.extend(listed);
+            if self.filenames.is_empty() {
+                let list = if list.as_os_str() == STDIN_FILENAME { "stdin".into() } else { list.display().to_string() };
+                return Err(format!("{} lists no files", list));
+            }
+        }
+        let stdin = self.filenames.iter().any(|f| f.as_os_str() == STDIN_FILENAME);
+        if self.name.is_some() && self.filenames.len() > 1 {
+            return Err("--name can only be used with a single file".to_string());
+        }
+        if stdin && self.name.is_none() {
+            return Err("Reading from stdin requires --name to set the filename".to_string());
+        }
+        if stdin && self.split {
+            return Err("--split can't be used with stdin".to_string());
+        }
+        if self.album && self.jobs > 1 {
+            return Err("--album sends one album at a time, it can't be used with --jobs".to_string());
+        }
+        if self.album && !self.send.buttons.is_empty() {
+            return Err("Albums can't have buttons, --button can't be used with --album".to_string());
+        }
+        crate::button::check(&self.send.buttons)?;
+        self.output = self.print.mode();
+        Ok(())
+    }
+}
+
+impl WatchArgs {
+    /// only directories are watched, and what only makes sense for a given set of files is left out
+    fn finish(&mut self) -> Result<(), String> {
+        self.upload.finish()?;
+        if self.upload.filenames.is_empty() {
+            return Err("watch needs a directory to watch".to_string());
+        }
+        if let Some(path) = self.upload.filenames.iter().find(|path| !path.is_dir()) {
+            return Err(format!("{} is not a directory, watch takes directories", path.display()));
+        }
+        let upload = &self.upload;
+        let unsupported = [
+            (upload.name.is_some(), "--name"),
+            (upload.dry_run, "--dry-run"),
+            (upload.resume, "--resume"),
+            (upload.state.is_some(), "--state"),
+            (upload.jobs > 1, "--jobs"),
+            (upload.album, "--album"),
+        ];
+        match unsupported.iter().find(|(given, _)| *given) {
+            Some((_, flag)) => Err(format!("{} can't be used with watch", flag)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the upload options as they were given, without the files, so `teledrop flush` can run the upload again
+/// what only concerns the batch is left out, and the token, it isn't written to the queue
+fn replay_args(command: &clap::Command, matches: &ArgMatches) -> Vec<String> {
+    const LEFT_OUT: &[&str] = &[
+        "filenames", "files_from", "files_from0", "fail_fast", "jobs", "album", "resume", "resume_check", "state", "keep_state", "queue", "no_queue",
+        "token",
+    ];
+    let mut replay = Vec::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if LEFT_OUT.contains(&id) || matches.value_source(id) != Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        match arg.get_action() {
+            ArgAction::SetTrue => replay.push(format!("--{}", long)),
+            ArgAction::Count => replay.extend((0..matches.get_count(id)).map(|_| format!("--{}", long))),
+            _ => {
+                let values = matches.get_raw(id).into_iter().flatten();
+                replay.extend(values.map(|value| format!("--{}={}", long, value.to_string_lossy())));
+            }
+        }
+    }
+    replay
+}
+
+/// the help ends with the config file location, its keys and the exit codes
+fn command_with_config(config_path: &str) -> clap::Command {
+    Cli::command().after_help(format!("Config file: {}\n{}\n\n{}", config_path, crate::CONFIG_KEYS, EXIT_CODES))
+}
+
+fn command() -> clap::Command {
+    let config_path = confy::get_configuration_file_path(crate::APP_NAME, crate::CONFIG_NAME)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|err| format!("unknown ({})", err));
+    command_with_config(&config_path)
+}
+
+/// write the man page as roff to stdout
+/// the page doesn't show the config path of the machine it was generated on
+pub fn print_man(subcommand: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut command = command_with_config("~/.config/teledrop/config.toml (Linux), ~/Library/Application Support/rs.teledrop/config.toml (macOS)");
+    // building sets the display names of the subcommand pages, like teledrop-join
+    command.build();
+    let page = match subcommand {
+        Some(name) => command
+            .find_subcommand(name)
+            .filter(|sub| !sub.is_hide_set())
+            .ok_or_else(|| Error::Usage(format!("No such subcommand: {}", name)))?
+            .clone(),
+        None => command,
+    };
+    clap_mangen::Man::new(page).render(&mut std::io::stdout())?;
+    Ok(())
+}
+
+/// write the completion script for the shell to stdout
+pub fn print_completions(shell: clap_complete::Shell) {
+    clap_complete::generate(shell, &mut command(), "teledrop", &mut std::io::stdout());
+}
+
+/// parse the command line, exits with code 2 on invalid arguments
+pub fn parse() -> Cli {
+    let mut command = command();
+    let matches = command.get_matches_mut();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.format(&mut command).exit());
+    let res = match &mut cli.command {
+        Some(Command::Upload(args)) => {
+            let upload = command.find_subcommand("upload").expect("upload is a subcommand");
+            args.replay = replay_args(upload, matches.subcommand_matches("upload").expect("upload was given"));
+            args.finish()
+        }
+        Some(Command::Watch(args)) => args.finish(),
+        Some(_) => Ok(()),
+        None => {
+            cli.upload.replay = replay_args(&command, &matches);
+            cli.upload.finish()
+        }
+    };
+    if let Err(msg) = res {
+        command.error(ErrorKind::ArgumentConflict, msg).exit();
+    }
+    cli
+}
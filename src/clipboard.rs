@@ -0,0 +1,13 @@
+//! Copying the download url to the clipboard for --copy
+//! needs the `clipboard` feature, without it copying always fails with a warning
+
+#[cfg(feature = "clipboard")]
+pub fn copy(text: &str) -> Result<(), String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|err| format!("No clipboard available: {}", err))?;
+    clipboard.set_text(text).map_err(|err| format!("Copying to the clipboard failed: {}", err))
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn copy(_text: &str) -> Result<(), String> {
+    Err("teledrop was built without clipboard support".to_string())
+}
@@ -0,0 +1,60 @@
+//! Compression before upload
+//! the input is compressed into a temp file, so the size limit applies to the compressed size
+
+use std::io::{Read, Write};
+
+use flate2::write::GzEncoder;
+use tempfile::NamedTempFile;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Compression {
+    #[value(alias = "gz")]
+    Gzip,
+    #[value(alias = "zst")]
+    Zstd,
+    // gzip, but files that are already compressed are sent as is
+    Auto,
+}
+
+// extensions of formats that are compressed already, compressing them again only wastes time
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "jpg", "jpeg", "png", "gif", "webp", "heic", "mp4", "mkv",
+    "mov", "webm", "mp3", "ogg", "m4a", "flac", "apk", "jar", "docx", "xlsx", "pptx", "epub",
+];
+
+impl Compression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip | Compression::Auto => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// true if the file extension belongs to an already compressed format
+pub fn is_compressed_name(name: &str) -> bool {
+    match name.rsplit_once('.') {
+        Some((_, ext)) => COMPRESSED_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// compress everything from the reader into a temp file
+/// the file is deleted when the returned handle is dropped
+pub fn compress<R: Read>(mut reader: R, method: Compression) -> std::io::Result<NamedTempFile> {
+    let tmp = NamedTempFile::new()?;
+    let file = tmp.reopen()?;
+    match method {
+        Compression::Gzip | Compression::Auto => {
+            let mut encoder = GzEncoder::new(file, flate2::Compression::default());
+            std::io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(file, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+            std::io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+    }
+    Ok(tmp)
+}
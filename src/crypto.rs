@@ -0,0 +1,217 @@
+//! Client-side encryption with a passphrase
+//! the key is derived with scrypt, the data is encrypted with AES-256-GCM in 64 KiB chunks
+//!
+//! file layout:
+//! "TDENC" | version u8 | scrypt log_n u8 | r u32 | p u32 | salt [16] | nonce prefix [8] | chunks...
+//!
+//! every chunk is encrypted with the nonce prefix and a chunk counter as nonce, the header and a
+//! "last chunk" flag are authenticated as associated data, so tampering with the header,
+//! reordering chunks or cutting the file short all fail the auth tag check.
+//! full chunks are never the last one, a file always ends with a shorter (possibly empty) chunk
+
+use std::io::{Read, Write};
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, OsRng, Payload as AeadPayload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+const MAGIC: &[u8; 5] = b"TDENC";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 5 + 1 + 1 + 4 + 4 + 16 + 8;
+const CHUNK_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+// scrypt cost, 2^17 * 8 * 128 bytes = 128 MiB of memory
+const SCRYPT_LOG_N: u8 = 17;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+// the most a header may ask for, a little over what encrypt writes
+// the parameters come from the file, without a cap a crafted one makes decrypt allocate terabytes
+const MAX_LOG_N: u8 = 20;
+const MAX_R: u32 = 16;
+const MAX_P: u32 = 4;
+const _: () = assert!(SCRYPT_LOG_N <= MAX_LOG_N && SCRYPT_R <= MAX_R && SCRYPT_P <= MAX_P);
+
+#[derive(Debug)]
+pub enum CryptoError {
+    Io(std::io::Error),
+    NotEncrypted,
+    UnsupportedVersion(u8),
+    // wrong passphrase or modified data, the two can't be told apart
+    Auth,
+    Truncated,
+    // scrypt parameters in the header over the MAX_* ones
+    Params { log_n: u8, r: u32, p: u32 },
+    Kdf(String),
+}
+
+impl std::fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CryptoError::Io(err) => write!(f, "{}", err),
+            CryptoError::NotEncrypted => write!(f, "not a teledrop encrypted file"),
+            CryptoError::UnsupportedVersion(v) => write!(f, "unsupported encryption format version {}", v),
+            CryptoError::Auth => write!(f, "wrong passphrase or the file was modified"),
+            CryptoError::Truncated => write!(f, "the file is truncated"),
+            CryptoError::Params { log_n, r, p } => write!(
+                f,
+                "the header asks for scrypt log_n={} r={} p={}, over the most teledrop allows (log_n={} r={} p={}), the file was not made by teledrop or was modified",
+                log_n, r, p, MAX_LOG_N, MAX_R, MAX_P
+            ),
+            CryptoError::Kdf(err) => write!(f, "key derivation failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+impl From<std::io::Error> for CryptoError {
+    fn from(err: std::io::Error) -> Self {
+        CryptoError::Io(err)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<Aes256Gcm, CryptoError> {
+    let params = scrypt::Params::new(log_n, r, p, 32).map_err(|err| CryptoError::Kdf(err.to_string()))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key).map_err(|err| CryptoError::Kdf(err.to_string()))?;
+    Ok(Aes256Gcm::new(&key.into()))
+}
+
+fn chunk_nonce(prefix: &[u8], counter: u32) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..8].copy_from_slice(prefix);
+    nonce[8..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+fn chunk_aad(header: &[u8], last: bool) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.push(last as u8);
+    aad
+}
+
+/// read until the buffer is full or the reader is done
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+pub fn encrypt<R: Read, W: Write>(mut reader: R, mut writer: W, passphrase: &str) -> Result<(), CryptoError> {
+    let mut salt = [0u8; 16];
+    let mut prefix = [0u8; 8];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut prefix);
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    header.push(SCRYPT_LOG_N);
+    header.extend_from_slice(&SCRYPT_R.to_be_bytes());
+    header.extend_from_slice(&SCRYPT_P.to_be_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&prefix);
+    writer.write_all(&header)?;
+
+    let cipher = derive_key(passphrase, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+    let mut buf = vec![0u8; CHUNK_LEN];
+    let mut counter: u32 = 0;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        let last = n < CHUNK_LEN;
+        let nonce = chunk_nonce(&prefix, counter);
+        let aad = chunk_aad(&header, last);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), AeadPayload { msg: &buf[..n], aad: &aad })
+            .map_err(|_| CryptoError::Auth)?;
+        writer.write_all(&ciphertext)?;
+        if last {
+            break;
+        }
+        // 2^32 chunks is 256 TiB, way over any upload limit
+        counter += 1;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn decrypt<R: Read, W: Write>(mut reader: R, mut writer: W, passphrase: &str) -> Result<(), CryptoError> {
+    let mut header = [0u8; HEADER_LEN];
+    if read_full(&mut reader, &mut header)? < HEADER_LEN || &header[..5] != MAGIC {
+        return Err(CryptoError::NotEncrypted);
+    }
+    if header[5] != VERSION {
+        return Err(CryptoError::UnsupportedVersion(header[5]));
+    }
+    let log_n = header[6];
+    let r = u32::from_be_bytes(header[7..11].try_into().unwrap());
+    let p = u32::from_be_bytes(header[11..15].try_into().unwrap());
+    let salt = &header[15..31];
+    let prefix = &header[31..39];
+    if log_n > MAX_LOG_N || r > MAX_R || p > MAX_P {
+        return Err(CryptoError::Params { log_n, r, p });
+    }
+
+    let cipher = derive_key(passphrase, salt, log_n, r, p)?;
+    let mut buf = vec![0u8; CHUNK_LEN + TAG_LEN];
+    let mut counter: u32 = 0;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        if n < TAG_LEN {
+            // the stream stopped before the final short chunk
+            return Err(CryptoError::Truncated);
+        }
+        let last = n < CHUNK_LEN + TAG_LEN;
+        let nonce = chunk_nonce(prefix, counter);
+        let aad = chunk_aad(&header, last);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), AeadPayload { msg: &buf[..n], aad: &aad })
+            .map_err(|_| CryptoError::Auth)?;
+        writer.write_all(&plaintext)?;
+        if last {
+            break;
+        }
+        counter += 1;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a header like encrypt writes it, with the scrypt parameters of the test
+    fn header(log_n: u8, r: u32, p: u32) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.extend([VERSION, log_n]);
+        header.extend(r.to_be_bytes());
+        header.extend(p.to_be_bytes());
+        header.extend([0u8; 16 + 8]);
+        // the final chunk, only its tag
+        header.extend([0u8; TAG_LEN]);
+        header
+    }
+
+    #[test]
+    fn tampered_scrypt_parameters_are_refused_before_deriving_the_key() {
+        for (log_n, r, p) in [(40, SCRYPT_R, SCRYPT_P), (SCRYPT_LOG_N, 1 << 20, SCRYPT_P), (SCRYPT_LOG_N, SCRYPT_R, 1000)] {
+            let err = decrypt(header(log_n, r, p).as_slice(), Vec::new(), "secret").unwrap_err();
+            assert!(matches!(err, CryptoError::Params { .. }), "{}", err);
+        }
+    }
+
+    #[test]
+    fn parameters_up_to_the_caps_are_allowed() {
+        // small enough to derive quickly here, the tag check is what fails
+        let err = decrypt(header(4, SCRYPT_R, SCRYPT_P).as_slice(), Vec::new(), "secret").unwrap_err();
+        assert!(matches!(err, CryptoError::Auth), "{}", err);
+    }
+}
@@ -0,0 +1,130 @@
+//! Errors with the exit code they map to
+//! scripts can tell a bad invocation from a missing file or a failed upload
+
+use std::fmt;
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_FILE: i32 = 3;
+pub const EXIT_API: i32 = 4;
+pub const EXIT_NETWORK: i32 = 5;
+pub const EXIT_NOT_FOUND: i32 = 6;
+pub const EXIT_UNCHANGED: i32 = 7;
+pub const EXIT_AUTH: i32 = 8;
+pub const EXIT_CHAT_NOT_FOUND: i32 = 9;
+pub const EXIT_FORBIDDEN: i32 = 10;
+// like a shell reports a process ended by SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+#[derive(Debug)]
+pub enum Error {
+    // bad arguments or config
+    Usage(String),
+    // missing, unreadable or oversized file
+    File(String),
+    // telegram answered with ok=false or something unexpected
+    Api(String),
+    // the request didn't complete
+    Network(String),
+    // the message is gone, e.g. already deleted
+    NotFound(String),
+    // an edit that wouldn't change anything
+    Unchanged(String),
+    // telegram refused the bot token
+    Auth(String),
+    // the chat doesn't exist or the bot can't see it
+    ChatNotFound(String),
+    // the bot was removed from the chat or blocked by the user
+    Forbidden(String),
+    Other(String),
+}
+
+impl Error {
+    /// error of the kind that exits with the code
+    pub fn with_code(code: i32, msg: String) -> Self {
+        match code {
+            EXIT_USAGE => Error::Usage(msg),
+            EXIT_FILE => Error::File(msg),
+            EXIT_API => Error::Api(msg),
+            EXIT_NETWORK => Error::Network(msg),
+            EXIT_NOT_FOUND => Error::NotFound(msg),
+            EXIT_UNCHANGED => Error::Unchanged(msg),
+            EXIT_AUTH => Error::Auth(msg),
+            EXIT_CHAT_NOT_FOUND => Error::ChatNotFound(msg),
+            EXIT_FORBIDDEN => Error::Forbidden(msg),
+            _ => Error::Other(msg),
+        }
+    }
+
+    /// prefix the message of any error, keeping its exit code
+    pub fn context(err: &(dyn std::error::Error + 'static), msg: &str) -> Self {
+        Error::with_code(exit_code(err), format!("{}: {}", msg, err))
+    }
+
+    /// the same message and exit code as one of these, which can be sent to another thread
+    pub fn from_boxed(err: &(dyn std::error::Error + 'static)) -> Self {
+        Error::with_code(exit_code(err), err.to_string())
+    }
+
+    /// opening or reading the file at `path` failed, the message says why
+    pub fn io(path: impl fmt::Display, err: &std::io::Error) -> Self {
+        use std::io::ErrorKind;
+        let msg = match err.kind() {
+            ErrorKind::NotFound => format!("{} not found", path),
+            ErrorKind::PermissionDenied => format!("Permission denied reading {}, check its owner and mode with ls -l", path),
+            ErrorKind::IsADirectory => format!("{} is a directory", path),
+            _ => format!("Can't read {}: {}", path, err),
+        };
+        Error::File(msg)
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => EXIT_USAGE,
+            Error::File(_) => EXIT_FILE,
+            Error::Api(_) => EXIT_API,
+            Error::Network(_) => EXIT_NETWORK,
+            Error::NotFound(_) => EXIT_NOT_FOUND,
+            Error::Unchanged(_) => EXIT_UNCHANGED,
+            Error::Auth(_) => EXIT_AUTH,
+            Error::ChatNotFound(_) => EXIT_CHAT_NOT_FOUND,
+            Error::Forbidden(_) => EXIT_FORBIDDEN,
+            Error::Other(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Usage(msg)
+            | Error::File(msg)
+            | Error::Api(msg)
+            | Error::Network(msg)
+            | Error::NotFound(msg)
+            | Error::Unchanged(msg)
+            | Error::Auth(msg)
+            | Error::ChatNotFound(msg)
+            | Error::Forbidden(msg)
+            | Error::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// exit code for any error that ended up boxed
+/// io errors come from reading the files, reqwest errors from the connection
+pub fn exit_code(err: &(dyn std::error::Error + 'static)) -> i32 {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        err.exit_code()
+    } else if err.is::<crate::retry::ApiStatus>() {
+        EXIT_API
+    } else if err.is::<reqwest::Error>() {
+        EXIT_NETWORK
+    } else if err.is::<std::io::Error>() || err.is::<crate::crypto::CryptoError>() {
+        EXIT_FILE
+    } else {
+        EXIT_FAILURE
+    }
+}
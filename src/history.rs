@@ -0,0 +1,242 @@
+//! Local history of the uploads, so files can be found again without keeping the file IDs around
+//! stored as a json array in the data dir, e.g. ~/.local/share/teledrop/history.json
+//! every entry gets an id that never changes, other subcommands take it as #ID instead of a file or message ID
+//! `teledrop history export` writes it as csv or json lines for reports, the columns are the fields of `Entry`
+//! a file sent to several chats is one entry, the messages in the other chats are in its broadcast list
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::output::{SentMessage, UploadResult};
+
+const FILE_NAME: &str = "history.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub uploaded_at: DateTime<Local>,
+    // path as given on the command line, none for stdin and reshared files
+    pub path: Option<String>,
+    pub name: String,
+    pub size: u64,
+    pub sha256: Option<String>,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub file_id: String,
+    pub file_unique_id: String,
+    // photos, videos... only documents were sent before there was a choice
+    #[serde(default, skip_serializing_if = "crate::SendMethod::is_document")]
+    pub sent_as: crate::SendMethod,
+    // the other chats of a broadcast, chat_id and message_id are the first one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub broadcast: Vec<SentMessage>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+// csv header, in the order of the fields of Entry
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "uploaded_at",
+    "path",
+    "name",
+    "size",
+    "sha256",
+    "chat_id",
+    "message_id",
+    "file_id",
+    "file_unique_id",
+    "sent_as",
+    "broadcast",
+];
+
+/// which entries to list, all the conditions have to match
+#[derive(Debug, Default, clap::Args)]
+pub struct Filter {
+    /// Only files with this in their name, ignoring case
+    #[arg(long, value_name = "TEXT")]
+    pub name: Option<String>,
+    /// Only uploads on or after this day, e.g. 2024-05-01
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    pub since: Option<NaiveDate>,
+    /// Only uploads on or before this day
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    pub until: Option<NaiveDate>,
+    /// Only uploads to this chat
+    #[arg(long, value_name = "CHAT_ID", allow_negative_numbers = true)]
+    pub chat: Option<i64>,
+    /// Only files of at least this size, e.g. 1M
+    #[arg(long, value_name = "SIZE", value_parser = crate::parse_size)]
+    pub min_size: Option<u64>,
+    /// Only files of at most this size
+    #[arg(long, value_name = "SIZE", value_parser = crate::parse_size)]
+    pub max_size: Option<u64>,
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|err| format!("Invalid date {} (expected YYYY-MM-DD): {}", s, err))
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let day = entry.uploaded_at.date_naive();
+        self.name.as_ref().is_none_or(|name| entry.name.to_lowercase().contains(&name.to_lowercase()))
+            && self.since.is_none_or(|since| day >= since)
+            && self.until.is_none_or(|until| day <= until)
+            && self.chat.is_none_or(|chat| entry.chat_id == chat || entry.broadcast.iter().any(|sent| sent.chat_id == chat))
+            && self.min_size.is_none_or(|size| entry.size >= size)
+            && self.max_size.is_none_or(|size| entry.size <= size)
+    }
+}
+
+pub fn path() -> Result<PathBuf, Error> {
+    let dir = dirs::data_dir().ok_or_else(|| Error::Other("No data directory to keep the history in".into()))?;
+    Ok(dir.join(crate::APP_NAME).join(FILE_NAME))
+}
+
+/// all entries, oldest first, empty when nothing was uploaded yet
+pub fn load() -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+    let path = path()?;
+    let contents = match std::fs::read(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::File(format!("{}: {}", path.display(), err)).into()),
+    };
+    serde_json::from_slice(&contents)
+        .map_err(|err| Error::File(format!("History {} is damaged: {}", path.display(), err)).into())
+}
+
+/// write to a temp file next to the history and rename it over the old one
+/// a crash halfway leaves the old history as it was
+fn save(entries: &[Entry]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path()?;
+    let dir = path.parent().expect("history path has a parent");
+    std::fs::create_dir_all(dir)?;
+    let _writing = crate::interrupt::writing();
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    serde_json::to_writer_pretty(&mut tmp, entries)?;
+    tmp.flush()?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(&path)?;
+    Ok(())
+}
+
+/// add an uploaded file to the history
+pub fn record(result: &UploadResult, path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut entries = load()?;
+    let id = entries.last().map_or(1, |last| last.id + 1);
+    entries.push(Entry {
+        id,
+        uploaded_at: Local::now(),
+        // absolute, the working directory is long forgotten when the history is looked at
+        path: path.map(|path| {
+            std::fs::canonicalize(path)
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| path.to_string())
+        }),
+        name: result.name.clone(),
+        size: result.size,
+        sha256: result.sha256.clone(),
+        chat_id: result.chat_id,
+        message_id: result.message_id,
+        file_id: result.file_id.clone(),
+        file_unique_id: result.file_unique_id.clone(),
+        sent_as: result.sent_as,
+        broadcast: result.broadcast.clone(),
+    });
+    save(&entries)
+}
+
+/// the entry for #ID
+fn find_id(handle: &str) -> Result<Option<Entry>, Box<dyn std::error::Error>> {
+    let Some(id) = handle.strip_prefix('#') else {
+        return Ok(None);
+    };
+    let id: u64 = id.parse().map_err(|_| Error::Usage(format!("Invalid history ID: {}", handle)))?;
+    match load()?.into_iter().find(|entry| entry.id == id) {
+        Some(entry) => Ok(Some(entry)),
+        None => Err(Error::Usage(format!("No upload {} in the history", handle)).into()),
+    }
+}
+
+/// the most recent upload of the file
+pub fn find_file_id(file_id: &str) -> Option<Entry> {
+    load().ok()?.into_iter().rev().find(|entry| entry.file_id == file_id)
+}
+
+/// a file ID, or #ID for the file of a history entry
+pub fn resolve_file_id(handle: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(match find_id(handle)? {
+        Some(entry) => entry.file_id,
+        None => handle.to_string(),
+    })
+}
+
+/// a message ID, #ID for the message of a history entry or a file ID that was uploaded before
+/// history entries also know the chat the message is in
+pub fn resolve_message(handle: &str) -> Result<(i64, Option<i64>), Box<dyn std::error::Error>> {
+    if let Some(entry) = find_id(handle)? {
+        return Ok((entry.message_id, Some(entry.chat_id)));
+    }
+    if let Ok(message_id) = handle.parse() {
+        return Ok((message_id, None));
+    }
+    match find_file_id(handle) {
+        Some(entry) => Ok((entry.message_id, Some(entry.chat_id))),
+        None => Err(Error::Usage(format!("{} is not a message ID, #ID or file ID from the history", handle)).into()),
+    }
+}
+
+/// quote a csv field when it has to be, quotes inside are doubled
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// write the entries in the format, oldest first
+pub fn export(entries: &[&Entry], format: ExportFormat, mut out: impl Write) -> std::io::Result<()> {
+    match format {
+        ExportFormat::Csv => {
+            // crlf line ends like rfc 4180
+            write!(out, "{}\r\n", CSV_COLUMNS.join(","))?;
+            for entry in entries {
+                let fields = [
+                    entry.id.to_string(),
+                    entry.uploaded_at.to_rfc3339(),
+                    entry.path.clone().unwrap_or_default(),
+                    entry.name.clone(),
+                    entry.size.to_string(),
+                    entry.sha256.clone().unwrap_or_default(),
+                    entry.chat_id.to_string(),
+                    entry.message_id.to_string(),
+                    entry.file_id.clone(),
+                    entry.file_unique_id.clone(),
+                    entry.sent_as.field().to_string(),
+                    // chat_id:message_id of the other chats, separated by spaces
+                    entry.broadcast.iter().map(|sent| format!("{}:{}", sent.chat_id, sent.message_id)).collect::<Vec<_>>().join(" "),
+                ];
+                let line: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                write!(out, "{}\r\n", line.join(","))?;
+            }
+        }
+        ExportFormat::Jsonl => {
+            for entry in entries {
+                serde_json::to_writer(&mut out, entry)?;
+                writeln!(out)?;
+            }
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,31 @@
+//! Commands run after each file, --on-success and --on-failure or on_success and on_failure in the config
+//! the command goes to the shell (sh -c, cmd /C on windows) with the result in TELEDROP_ environment variables
+//! its output goes to stderr, stdout stays for the results, and a failing hook doesn't change how the upload went
+
+use std::process::{Command, Stdio};
+
+/// run the command with the variables set, the error says how it failed
+pub fn run(command: &str, vars: &[(&str, String)]) -> Result<(), String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    // stdin may be the list of --files-from -
+    let output = shell
+        .arg(command)
+        .envs(vars.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| format!("can't run {:?}: {}", command, err))?;
+    eprint!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    if !output.status.success() {
+        return Err(format!("{:?} failed with {}", command, output.status));
+    }
+    Ok(())
+}
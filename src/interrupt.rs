@@ -0,0 +1,85 @@
+//! Ctrl+C during an upload, the terminal gets its cursor back and the run ends with what it uploaded
+//! the history and the state of the batch are written after every file, a write that is under way is let finish
+//! a second Ctrl+C exits right away
+
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use tokio::runtime::Runtime;
+
+use crate::error;
+use crate::output::{self, OutputMode};
+
+struct Progress {
+    // none for teledrop watch, there is no end to it
+    total: Option<usize>,
+    // name and file_id of every uploaded file
+    uploaded: Vec<(String, String)>,
+    // where the state of the batch is, for --resume
+    state: Option<PathBuf>,
+}
+
+static PROGRESS: Mutex<Progress> = Mutex::new(Progress {
+    total: None,
+    uploaded: Vec::new(),
+    state: None,
+});
+static WRITING: Mutex<()> = Mutex::new(());
+
+/// handle Ctrl+C from now on, for an upload of `total` files
+pub fn install(rt: &Runtime, total: Option<usize>, mode: &OutputMode) {
+    PROGRESS.lock().unwrap().total = total;
+    let mode = mode.clone();
+    rt.spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        tokio::spawn(async {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(error::EXIT_INTERRUPTED);
+            }
+        });
+        interrupted(&mode);
+    });
+}
+
+/// hold this while writing a file Ctrl+C shouldn't cut in half
+pub fn writing() -> MutexGuard<'static, ()> {
+    WRITING.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// a file is uploaded, for the message when the run is interrupted
+pub fn uploaded(name: &str, file_id: &str) {
+    PROGRESS.lock().unwrap().uploaded.push((name.to_string(), file_id.to_string()));
+}
+
+/// the batch keeps its state in the file
+pub fn state(path: &Path) {
+    PROGRESS.lock().unwrap().state = Some(path.to_path_buf());
+}
+
+fn interrupted(mode: &OutputMode) -> ! {
+    let _writing = WRITING.lock();
+    if std::io::stderr().is_terminal() {
+        // a spinner or progress bar hides the cursor and leaves its line half drawn
+        eprint!("\r\x1b[2K\x1b[?25h");
+    }
+    let progress = PROGRESS.lock().unwrap_or_else(|err| err.into_inner());
+    let msg = match progress.total {
+        Some(total) => format!("Interrupted, {} of {} files uploaded", progress.uploaded.len(), total),
+        None => format!("Interrupted, {} files uploaded", progress.uploaded.len()),
+    };
+    output::print_error(mode, None, &msg, error::EXIT_INTERRUPTED);
+    // scripts got the results on stdout already
+    if mode.is_human() {
+        for (name, file_id) in &progress.uploaded {
+            eprintln!("  {}: {}", name, file_id);
+        }
+    }
+    if let (Some(path), false) = (&progress.state, progress.uploaded.is_empty()) {
+        eprintln!("What the batch uploaded is kept in {}, --resume skips it next time", path.display());
+    }
+    let _ = std::io::stdout().flush();
+    std::process::exit(error::EXIT_INTERRUPTED);
+}
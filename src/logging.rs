@@ -0,0 +1,81 @@
+//! Structured logging with tracing, for cron jobs and timers that need a record of what happened
+//! off unless RUST_LOG is set (logs to stderr) or a log file is given with --log-file or log_file
+//! the log file is appended to, its filter is RUST_LOG too and teledrop=info without it
+//! the spans read, upload and getFile are logged with their durations when they close
+
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::output;
+
+const DEFAULT_FILTER: &str = "teledrop=info";
+
+/// a writer that redacts the bot token, every log line is written in one go so it's never cut in half
+struct Redacted<W>(W);
+
+impl<W: Write> Write for Redacted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(output::redact(&String::from_utf8_lossy(buf)).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// writes on &File don't need a lock, the file is opened for appending so lines don't overwrite each other
+struct LogFile(std::fs::File);
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = Redacted<&'a std::fs::File>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Redacted(&self.0)
+    }
+}
+
+fn filter() -> Option<EnvFilter> {
+    std::env::var_os(EnvFilter::DEFAULT_ENV)?;
+    Some(EnvFilter::from_default_env())
+}
+
+/// start logging, a second call does nothing
+pub fn init(log_file: Option<&Path>) -> Result<(), String> {
+    let stderr = filter().map(|filter| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(|| Redacted(io::stderr()))
+            .with_ansi(io::stderr().is_terminal())
+            .with_span_events(FmtSpan::CLOSE)
+            .with_filter(filter)
+    });
+    let file = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|err| format!("Can't open the log file {}: {}", path.display(), err))?;
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(LogFile(file))
+                .with_ansi(false)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_filter(filter().unwrap_or_else(|| EnvFilter::new(DEFAULT_FILTER)));
+            Some(layer)
+        }
+        None => None,
+    };
+    if stderr.is_none() && file.is_none() {
+        return Ok(());
+    }
+    // fails when there is a subscriber already, that one stays
+    let _ = tracing_subscriber::registry().with(stderr).with(file).try_init();
+    Ok(())
+}
@@ -1,317 +1,4891 @@
-///
-///
-///       _       _          _
-///      | |     | |        | |
-///      | |_ ___| | ___  __| |_ __ ___  _ __
-///      | __/ _ \ |/ _ \/ _` | '__/ _ \| '_ \
-///      | ||  __/ |  __/ (_| | | | (_) | |_) |
-///       \__\___|_|\___|\__,_|_|  \___/| .__/
-///                                     | |
-///                                     |_|
-///
-///
-///
-/// Description: CLI for Uploading files via telegram bot API
-/// Usage: teledrop filename
-///
-/// config file should be found at:
-/// MacOS: "/Users/user/Library/Application Support/rs.teledrop/config.toml"
-/// config example:
-///
-/// bot_token = '123456789:ABC-DEF1234ghIkl-zyx57W2v1u123ew11'
-/// chat_id = '123456789'
-///
-
-
-use mime_guess;
-use std::env;
+//!
+//!
+//!       _       _          _
+//!      | |     | |        | |
+//!      | |_ ___| | ___  __| |_ __ ___  _ __
+//!      | __/ _ \ |/ _ \/ _` | '__/ _ \| '_ \
+//!      | ||  __/ |  __/ (_| | | | (_) | |_) |
+//!       \__\___|_|\___|\__,_|_|  \___/| .__/
+//!                                     | |
+//!                                     |_|
+//!
+//!
+//!
+//! Description: CLI for Uploading files via telegram bot API
+//! Usage: teledrop filename
+//!
+//! config file should be found at:
+//! MacOS: "/Users/user/Library/Application Support/rs.teledrop/config.toml"
+//! config example:
+//!
+//! bot_token = '123456789:ABC-DEF1234ghIkl-zyx57W2v1u123ew11'
+//! chat_id = '123456789'
+//!
+
+mod archive;
+mod button;
+mod cli;
+mod clipboard;
+mod compress;
+mod crypto;
+mod error;
+mod history;
+mod hook;
+mod interrupt;
+mod logging;
+mod output;
+mod pace;
+mod qr;
+mod queue;
+mod retry;
+mod secrets;
+mod split;
+mod state;
+mod template;
+mod thumb;
+mod trace;
+mod watch;
+
+use std::collections::BTreeMap;
 use std::fmt::Write;
-use std::path::Path;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use reqwest::{multipart, Body, Client};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use reqwest::{multipart, Body, Client, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
 use tokio::fs::File;
-use tokio::io;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::runtime::Runtime;
 use tokio_util::codec::{BytesCodec, FramedRead};
 
 use futures::stream::TryStreamExt;
 
 // loaders
+use cli::{
+    Args, ChatInfoArgs, ChatsArgs, Command, ConfigArgs, ConfigCommand, DecryptArgs, DoctorArgs, TestArgs, DeleteArgs, DownloadArgs, EditCaptionArgs, ExportArgs, ForwardArgs, HistoryArgs, HistoryCommand,
+    FlushArgs, InfoArgs, JoinArgs, QueueCommand, ReshareArgs, UrlArgs,
+};
+use compress::Compression;
+use error::Error;
+use output::{FileResult, OutputMode, SentMessage, UploadResult};
+use state::State;
+use chrono::{DateTime, Local};
 use colored::Colorize;
-use spinners::{Spinner, Spinners};
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use spinners::{Spinner, Spinners, Stream};
+use indicatif::{DecimalBytes, ProgressBar, ProgressState, ProgressStyle};
 
 
 const APP_NAME: &str = "teledrop";
 const CONFIG_NAME: &str = "config";
-const API_URL_BASE: &str = "https://api.telegram.org/bot";
+// project config, found by looking in the working directory and its parents
+const LOCAL_CONFIG_NAME: &str = ".teledrop.toml";
+const DEFAULT_API_BASE_URL: &str = "https://api.telegram.org";
 const API_SEND_DOCUMENT: &str = "/sendDocument";
+const API_SEND_PHOTO: &str = "/sendPhoto";
+const API_SEND_VIDEO: &str = "/sendVideo";
+const API_SEND_AUDIO: &str = "/sendAudio";
+const API_SEND_ANIMATION: &str = "/sendAnimation";
+const API_SEND_MEDIA_GROUP: &str = "/sendMediaGroup";
 const API_GET_FILE: &str = "/getFile";
-const FILE_SIZE_LIMIT: u64 = 20_000_000;
+const API_DELETE_MESSAGE: &str = "/deleteMessage";
+const API_EDIT_MESSAGE_CAPTION: &str = "/editMessageCaption";
+const API_FORWARD_MESSAGE: &str = "/forwardMessage";
+const API_PIN_CHAT_MESSAGE: &str = "/pinChatMessage";
+const API_GET_CHAT: &str = "/getChat";
+const API_GET_ME: &str = "/getMe";
+const API_GET_UPDATES: &str = "/getUpdates";
+const API_SEND_CHAT_ACTION: &str = "/sendChatAction";
+const API_SEND_MESSAGE: &str = "/sendMessage";
+const API_GET_CHAT_MEMBER: &str = "/getChatMember";
+const API_GET_CHAT_MEMBER_COUNT: &str = "/getChatMemberCount";
+// bots can upload files up to 50 MB via sendDocument
+const UPLOAD_SIZE_LIMIT: u64 = 50_000_000;
+// sendMediaGroup takes albums of 2 to 10 files
+const ALBUM_SIZE: usize = 10;
+// sendPhoto only takes photos up to 10 MB, bigger ones are sent as documents
+const PHOTO_SIZE_LIMIT: u64 = 10_000_000;
+// but only files up to 20 MB can be downloaded via getFile
+const DOWNLOAD_SIZE_LIMIT: u64 = 20_000_000;
+// a self-hosted bot api server accepts uploads up to 2000 MB
+const LOCAL_SERVER_UPLOAD_SIZE_LIMIT: u64 = 2_000_000_000;
+// files bigger than this are only uploaded after checking the bot may send to the chat
+const PRECHECK_SIZE: u64 = 5_000_000;
+// seconds to wait for the connection to the api server
+const CONNECT_TIMEOUT: u64 = 30;
+// seconds for a call without a file, e.g. getFile, unless timeout is set
+const API_TIMEOUT: u64 = 60;
+// uploads and downloads get a timeout from their size instead, at this throughput in bytes per second
+// slow enough for a bad mobile connection, a stuck transfer still ends eventually
+const MIN_THROUGHPUT: u64 = 25_000;
+// filename argument meaning "read from stdin"
+const STDIN_FILENAME: &str = "-";
+// telegram limit for media captions, in characters
+const CAPTION_LIMIT: usize = 1024;
+// longest filename in bytes, what file systems allow and telegram keeps
+const NAME_LIMIT: usize = 255;
 
 // ===== CONFIG
+// shown in --help and the man page, keep in sync with the fields below and CONFIG_ENV
+const CONFIG_KEYS: &str = "  bot_token                    token of the bot that sends the files (required)
+  chat_id                      chat the files are sent to (required)
+  max_upload_size              upload limit, e.g. '50M' (default: 50M, 2000M for a self-hosted server)
+  api_base_url                 self-hosted telegram-bot-api server (default: https://api.telegram.org)
+  default_profile              profile used when --profile is not given
+  broadcast                    chats every upload is sent to when --chat is not given, e.g. ['family', '-100123']
+  allow_local_token            accept bot_token and api_base_url from a .teledrop.toml (default: false)
+  token_source                 'keyring' when the token is in the keyring of the system, see config set --keyring
+  suppress_permission_warning  no warning when other users can read this file (default: false)
+  precheck_size                check the bot may send to the chat before uploading files over this (default: 5M)
+  connect_timeout              seconds to wait for the connection to the server (default: 30)
+  timeout                      seconds a request may take (default: 60, uploads and downloads get more by their size)
+  retries                      how often failed uploads and getFile calls are tried again (default: 3)
+  retry_delay                  seconds before the first retry, doubled for every next one (default: 1)
+  max_retry_after              longest wait in seconds a 429 may ask for before teledrop gives up (default: 300)
+  rate                         messages a minute sent to a chat (default: 60, 20 for groups and channels)
+  on_success                   shell command run after each uploaded file, like --on-success
+  on_failure                   shell command run after each file that failed, like --on-failure
+  log_file                     append a log of every run to this file, RUST_LOG sets what is logged (default: info)
+  disable_notification         send every file without a notification, like --silent (default: false)
+  protect_content              send every file protected from forwarding and saving, like --protect (default: false)
+  topic_id                     message_thread_id of the forum topic of chat_id the files go to, like --topic
+A .teledrop.toml in the working directory or one of its parents is merged over this file, --no-local-config skips it.
+A [chats] table maps names to chat IDs, e.g. family = '-1001234567890', the names work wherever a chat is expected.
+A [topics] table maps names to forum topics, e.g. incidents = 42, for --topic.
+Each [profiles.NAME] table can set the same keys, --profile NAME uses them instead of the ones above.
+The first four keys can also be set with an environment variable, which wins over the file:
+  TELEDROP_BOT_TOKEN, TELEDROP_CHAT_ID, TELEDROP_MAX_UPLOAD_SIZE, TELEDROP_API_BASE";
+
+// keys `teledrop config get` and `set` know, the tables are edited with `teledrop config edit`
+const CONFIG_FIELDS: &[&str] = &[
+    "bot_token",
+    "chat_id",
+    "max_upload_size",
+    "api_base_url",
+    "default_profile",
+    "broadcast",
+    "allow_local_token",
+    "suppress_permission_warning",
+    "precheck_size",
+    "connect_timeout",
+    "timeout",
+    "retries",
+    "retry_delay",
+    "max_retry_after",
+    "rate",
+    "on_success",
+    "on_failure",
+    "log_file",
+    "disable_notification",
+    "protect_content",
+    "topic_id",
+];
+
+// environment variables for the config keys
+const CONFIG_ENV: &[(&str, &str)] = &[
+    ("bot_token", "TELEDROP_BOT_TOKEN"),
+    ("chat_id", "TELEDROP_CHAT_ID"),
+    ("max_upload_size", "TELEDROP_MAX_UPLOAD_SIZE"),
+    ("api_base_url", "TELEDROP_API_BASE"),
+];
+
 #[derive(Default, Debug, Serialize, Deserialize)]
 struct Config {
+    // empty when it's in the keyring or only set in the environment
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     bot_token: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     chat_id: String,
+    // upload limit override, bytes or a size like "50M"
+    #[serde(default, deserialize_with = "deserialize_size", skip_serializing_if = "Option::is_none")]
+    max_upload_size: Option<u64>,
+    // self-hosted telegram-bot-api server, defaults to the public api
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_profile: Option<String>,
+    // [profiles.NAME] tables, keys left out there are taken from the top level
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, Profile>,
+    // uploads go to all of these without --chat, chat_id defaults to the first one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    broadcast: Vec<String>,
+    // [chats] table, names for chat IDs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    chats: BTreeMap<String, String>,
+    // a repo could send the files to its own bot or server otherwise, only honored in the global config
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    allow_local_token: bool,
+    // where bot_token is kept, the file unless it says keyring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token_source: Option<TokenSource>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    suppress_permission_warning: bool,
+    #[serde(default, deserialize_with = "deserialize_size", skip_serializing_if = "Option::is_none")]
+    precheck_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    connect_timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retries: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_delay: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_retry_after: Option<u64>,
+    // messages per minute to a chat, see pace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rate: Option<u32>,
+    // hooks, see hook
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    on_success: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    on_failure: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log_file: Option<String>,
+    // --silent for every upload
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    disable_notification: bool,
+    // --protect for every upload, a profile can set it for its chat
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    protect_content: bool,
+    // --topic in chat_id, a profile can set it for its chat
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    topic_id: Option<i64>,
+    // [topics] table, names for the message_thread_id of forum topics
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    topics: BTreeMap<String, i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TokenSource {
+    File,
+    Keyring,
+}
+
+/// a .teledrop.toml, the same keys as the config except allow_local_token
+#[derive(Default, Debug, Serialize, Deserialize)]
+struct LocalConfig {
+    #[serde(flatten)]
+    keys: Profile,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    broadcast: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    chats: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    topics: BTreeMap<String, i64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// the closest .teledrop.toml, like git looks for .git
+fn find_local_config() -> Option<std::path::PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors().map(|dir| dir.join(LOCAL_CONFIG_NAME)).find(|path| path.is_file())
+}
+
+/// a named set of config keys, e.g. another chat for the same bot
+#[derive(Default, Debug, Serialize, Deserialize)]
+struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bot_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chat_id: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_size", skip_serializing_if = "Option::is_none")]
+    max_upload_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protect_content: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    topic_id: Option<i64>,
+}
+
+/// sizes in the config can be written as a number of bytes or with units
+fn deserialize_size<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    match Option::<Size>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::Text(text)) => parse_size(&text).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// parse a size like "1024", "500K", "50M", "1.5G" or "10MiB"
+/// K/M/G are decimal like the limits in the bot api docs, KiB/MiB/GiB are binary
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("Invalid size: {}", s))?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => return Err(format!("Invalid size unit in {} (expected K, M, G, KiB, MiB or GiB)", s)),
+    };
+    Ok((number * multiplier as f64) as u64)
 }
 // get api url with token
 impl Config {
-    fn api_url_send_document(&self) -> String {
+    /// override the keys the profile sets, `name` is from --profile and falls back to default_profile
+    fn apply_profile(&mut self, name: Option<&str>) -> Result<(), String> {
+        let Some(name) = name.map(str::to_string).or_else(|| self.default_profile.clone()) else {
+            return Ok(());
+        };
+        let Some(profile) = self.profiles.remove(&name) else {
+            if self.profiles.is_empty() {
+                return Err(format!("Unknown profile {}, there are no [profiles] in the config", name));
+            }
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(format!("Unknown profile {}, available: {}", name, names.join(", ")));
+        };
+        self.apply_keys(profile);
+        Ok(())
+    }
+    /// a key from CONFIG_FIELDS as text, none when it's not set
+    fn get_key(&self, key: &str) -> Option<String> {
+        match key {
+            "bot_token" => Some(self.bot_token.clone()).filter(|v| !v.is_empty()),
+            "chat_id" => Some(self.chat_id.clone()).filter(|v| !v.is_empty()),
+            "max_upload_size" => self.max_upload_size.map(|size| size.to_string()),
+            "api_base_url" => self.api_base_url.clone(),
+            "default_profile" => self.default_profile.clone(),
+            "broadcast" => Some(self.broadcast.join(",")).filter(|v| !v.is_empty()),
+            "allow_local_token" => Some(self.allow_local_token.to_string()),
+            "suppress_permission_warning" => Some(self.suppress_permission_warning.to_string()),
+            "precheck_size" => self.precheck_size.map(|size| size.to_string()),
+            "connect_timeout" => self.connect_timeout.map(|secs| secs.to_string()),
+            "timeout" => self.timeout.map(|secs| secs.to_string()),
+            "retries" => self.retries.map(|retries| retries.to_string()),
+            "retry_delay" => self.retry_delay.map(|secs| secs.to_string()),
+            "max_retry_after" => self.max_retry_after.map(|secs| secs.to_string()),
+            "rate" => self.rate.map(|rate| rate.to_string()),
+            "on_success" => self.on_success.clone(),
+            "on_failure" => self.on_failure.clone(),
+            "log_file" => self.log_file.clone(),
+            "disable_notification" => Some(self.disable_notification.to_string()),
+            "protect_content" => Some(self.protect_content.to_string()),
+            "topic_id" => self.topic_id.map(|id| id.to_string()),
+            _ => unreachable!("keys are validated when parsing args"),
+        }
+    }
+    /// change a key from CONFIG_FIELDS, the value is checked like when the config is loaded
+    fn set_key(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let optional = Some(value.to_string()).filter(|v| !v.is_empty());
+        match key {
+            "bot_token" | "chat_id" if value.is_empty() => return Err(format!("{} can't be empty", key)),
+            "bot_token" => self.bot_token = value.to_string(),
+            "chat_id" => self.chat_id = value.to_string(),
+            "max_upload_size" => self.max_upload_size = optional.map(|v| parse_size(&v)).transpose()?,
+            "api_base_url" => {
+                self.api_base_url = optional;
+                self.validate_api_base_url()?;
+            }
+            "default_profile" => {
+                if let Some(name) = optional.as_deref().filter(|name| !self.profiles.contains_key(*name)) {
+                    return Err(format!("There is no [profiles.{}] in the config", name));
+                }
+                self.default_profile = optional;
+            }
+            "broadcast" => self.broadcast = value.split(',').map(str::trim).filter(|v| !v.is_empty()).map(String::from).collect(),
+            "allow_local_token" => {
+                self.allow_local_token = value.parse().map_err(|_| format!("allow_local_token is true or false, not {}", value))?
+            }
+            "suppress_permission_warning" => {
+                self.suppress_permission_warning =
+                    value.parse().map_err(|_| format!("suppress_permission_warning is true or false, not {}", value))?
+            }
+            "precheck_size" => self.precheck_size = optional.map(|v| parse_size(&v)).transpose()?,
+            "connect_timeout" => self.connect_timeout = optional.map(|v| parse_seconds(key, &v)).transpose()?,
+            "timeout" => self.timeout = optional.map(|v| parse_seconds(key, &v)).transpose()?,
+            "retries" => {
+                self.retries = optional.map(|v| v.parse().map_err(|_| format!("retries is a number, not {}", v))).transpose()?
+            }
+            "retry_delay" => self.retry_delay = optional.map(|v| parse_seconds(key, &v)).transpose()?,
+            "max_retry_after" => self.max_retry_after = optional.map(|v| parse_seconds(key, &v)).transpose()?,
+            "rate" => {
+                self.rate = optional
+                    .map(|v| v.parse().ok().filter(|rate| *rate > 0).ok_or_else(|| format!("rate is a number of messages a minute, not {}", v)))
+                    .transpose()?
+            }
+            "on_success" => self.on_success = optional,
+            "on_failure" => self.on_failure = optional,
+            "log_file" => self.log_file = optional,
+            "disable_notification" => {
+                self.disable_notification =
+                    value.parse().map_err(|_| format!("disable_notification is true or false, not {}", value))?
+            }
+            "protect_content" => {
+                self.protect_content = value.parse().map_err(|_| format!("protect_content is true or false, not {}", value))?
+            }
+            "topic_id" => {
+                self.topic_id = optional
+                    .map(|v| v.parse().ok().filter(|id| *id > 0).ok_or_else(|| format!("topic_id is the message_thread_id of a topic, not {}", v)))
+                    .transpose()?
+            }
+            _ => unreachable!("keys are validated when parsing args"),
+        }
+        Ok(())
+    }
+    /// override the keys that are set
+    fn apply_keys(&mut self, keys: Profile) {
+        if let Some(token) = keys.bot_token {
+            self.bot_token = token;
+        }
+        if let Some(chat_id) = keys.chat_id {
+            self.chat_id = chat_id;
+        }
+        if keys.max_upload_size.is_some() {
+            self.max_upload_size = keys.max_upload_size;
+        }
+        if keys.api_base_url.is_some() {
+            self.api_base_url = keys.api_base_url;
+        }
+        if let Some(protect_content) = keys.protect_content {
+            self.protect_content = protect_content;
+        }
+        if keys.topic_id.is_some() {
+            self.topic_id = keys.topic_id;
+        }
+    }
+    /// merge a .teledrop.toml over the config, the local file wins
+    /// bot_token and api_base_url are dropped with a warning unless allow_local_token is set
+    fn apply_local(&mut self, mut local: LocalConfig, path: &Path) {
+        if !self.allow_local_token {
+            let mut ignored = Vec::new();
+            for keys in std::iter::once(&mut local.keys).chain(local.profiles.values_mut()) {
+                if keys.bot_token.take().is_some() && !ignored.contains(&"bot_token") {
+                    ignored.push("bot_token");
+                }
+                if keys.api_base_url.take().is_some() && !ignored.contains(&"api_base_url") {
+                    ignored.push("api_base_url");
+                }
+            }
+            if !ignored.is_empty() {
+                let msg = format!(
+                    "Warning: ignoring {} from {}, set allow_local_token = true in the global config to use it",
+                    ignored.join(" and "),
+                    path.display()
+                );
+                eprintln!("{}", msg.yellow());
+            }
+        }
+        self.apply_keys(local.keys);
+        if local.default_profile.is_some() {
+            self.default_profile = local.default_profile;
+        }
+        if !local.broadcast.is_empty() {
+            self.broadcast = local.broadcast;
+        }
+        self.chats.extend(local.chats);
+        self.topics.extend(local.topics);
+        self.profiles.extend(local.profiles);
+    }
+    /// the chat ID for a name from [chats], numeric IDs and @usernames are taken as they are
+    fn resolve_chat(&self, chat: &str) -> Result<String, Error> {
+        if chat.starts_with('@') || chat.parse::<i64>().is_ok() {
+            return Ok(chat.to_string());
+        }
+        match self.chats.get(chat) {
+            Some(chat_id) => Ok(chat_id.clone()),
+            None if self.chats.is_empty() => Err(Error::Usage(format!(
+                "Unknown chat {}, expected a chat ID, @username or a name from the [chats] table of the config",
+                chat
+            ))),
+            None => {
+                let names: Vec<&str> = self.chats.keys().map(String::as_str).collect();
+                Err(Error::Usage(format!("Unknown chat {}, known names: {}", chat, names.join(", "))))
+            }
+        }
+    }
+    /// the message_thread_id for a name from [topics], numbers are taken as they are
+    fn resolve_topic(&self, topic: &str) -> Result<i64, Error> {
+        if let Ok(id) = topic.parse::<i64>() {
+            return Ok(id);
+        }
+        match self.topics.get(topic) {
+            Some(id) => Ok(*id),
+            None if self.topics.is_empty() => Err(Error::Usage(format!(
+                "Unknown topic {}, expected a message_thread_id or a name from the [topics] table of the config",
+                topic
+            ))),
+            None => {
+                let names: Vec<&str> = self.topics.keys().map(String::as_str).collect();
+                Err(Error::Usage(format!("Unknown topic {}, known names: {}", topic, names.join(", "))))
+            }
+        }
+    }
+    /// the topic of --topic, or topic_id when the files go to chat_id, resolved on the options
+    fn apply_topic(&self, send: &mut SendOptions) -> Result<(), Error> {
+        match &send.topic {
+            Some(topic) => send.thread_id = Some(self.resolve_topic(topic)?),
+            None => send.thread_id = self.topic_id,
+        }
+        Ok(())
+    }
+    /// override the keys that have their variable set, empty ones are ignored
+    fn apply_env(&mut self) -> Result<(), String> {
+        for (key, env) in CONFIG_ENV {
+            let Some(value) = std::env::var(env).ok().filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            match *key {
+                "bot_token" => self.bot_token = value,
+                "chat_id" => self.chat_id = value,
+                "max_upload_size" => self.max_upload_size = Some(parse_size(&value).map_err(|err| format!("{}: {}", env, err))?),
+                "api_base_url" => self.api_base_url = Some(value),
+                _ => unreachable!("every config key is handled"),
+            }
+        }
+        Ok(())
+    }
+    /// api base url without trailing slashes
+    fn api_base_url(&self) -> &str {
+        match &self.api_base_url {
+            Some(url) => url.trim_end_matches('/'),
+            None => DEFAULT_API_BASE_URL,
+        }
+    }
+    /// true when a self-hosted bot api server is used
+    fn is_local_server(&self) -> bool {
+        self.api_base_url() != DEFAULT_API_BASE_URL
+    }
+    /// the public api is https only, plain http is fine for a server on the same machine
+    fn validate_api_base_url(&self) -> Result<(), String> {
+        let url = reqwest::Url::parse(self.api_base_url())
+            .map_err(|err| format!("Invalid api_base_url {}: {}", self.api_base_url(), err))?;
+        let is_localhost = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+        match url.scheme() {
+            "https" => Ok(()),
+            "http" if is_localhost => Ok(()),
+            "http" => Err(format!("api_base_url {} must use https, plain http is only allowed for localhost", url)),
+            scheme => Err(format!("api_base_url has unsupported scheme {}", scheme)),
+        }
+    }
+    /// default upload limit, unless overridden by max_upload_size
+    fn upload_size_limit(&self) -> u64 {
+        match self.max_upload_size {
+            Some(limit) => limit,
+            None if self.is_local_server() => LOCAL_SERVER_UPLOAD_SIZE_LIMIT,
+            None => UPLOAD_SIZE_LIMIT,
+        }
+    }
+    fn api_url_send(&self, method: SendMethod) -> String {
         format!(
-            "{}{}{}?chat_id={}",
-            API_URL_BASE, self.bot_token, API_SEND_DOCUMENT, self.chat_id
+            "{}/bot{}{}?chat_id={}",
+            self.api_base_url(), self.bot_token, method.path(), self.chat_id
         )
     }
+    /// url of a bot api method, for requests with a json body
+    fn api_url(&self, method: &str) -> String {
+        format!("{}/bot{}{}", self.api_base_url(), self.bot_token, method)
+    }
     fn api_url_get_file(&self) -> String {
-        format!("{}{}{}", API_URL_BASE, self.bot_token, API_GET_FILE)
+        self.api_url(API_GET_FILE)
     }
     fn api_url_file_url(&self, file_path: String) -> String {
-        format!("{}/file/{}/{}", API_URL_BASE, self.bot_token, file_path)
+        format!("{}/file/bot{}/{}", self.api_base_url(), self.bot_token, file_path)
+    }
+    /// http client with the timeouts of the config
+    fn client(&self) -> Result<Client, Box<dyn std::error::Error>> {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout.unwrap_or(CONNECT_TIMEOUT)))
+            .timeout(Duration::from_secs(self.timeout.unwrap_or(API_TIMEOUT)))
+            .build()?;
+        Ok(client)
+    }
+    /// how the calls that upload and get files are retried
+    fn retry_policy(&self) -> retry::Policy {
+        retry::Policy {
+            retries: self.retries.unwrap_or(retry::RETRIES),
+            delay: Duration::from_secs(self.retry_delay.unwrap_or(retry::RETRY_DELAY)),
+            max_retry_after: Duration::from_secs(self.max_retry_after.unwrap_or(retry::MAX_RETRY_AFTER)),
+        }
+    }
+    /// wait for the turn of the next message to the chat
+    fn pace(&self, chat_id: &str) {
+        pace::wait(chat_id, self.rate.unwrap_or_else(|| pace::default_rate(chat_id)));
+    }
+    /// timeout for uploading or downloading a file of the size, timeout from the config wins
+    fn transfer_timeout(&self, size: u64) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(API_TIMEOUT + size / MIN_THROUGHPUT))
     }
 }
 
-// ===== API document upload structs
+fn parse_seconds(key: &str, value: &str) -> Result<u64, String> {
+    match value.parse() {
+        Ok(secs) if secs > 0 => Ok(secs),
+        _ => Err(format!("{} is a number of seconds, not {}", key, value)),
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct RequestDocumentUpload {
-    document: Vec<u8>,
+// ===== ARGS
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[value(rename_all = "verbatim")]
+enum ParseMode {
+    MarkdownV2,
+    #[value(name = "HTML")]
+    #[serde(rename = "HTML")]
+    Html,
+    Markdown,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct TelegramResponseDocument {
-    ok: bool,
-    result: Option<TelegramResult>,
+impl ParseMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ParseMode::MarkdownV2 => "MarkdownV2",
+            ParseMode::Html => "HTML",
+            ParseMode::Markdown => "Markdown",
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct TelegramResult {
-    document: TelegramDocument,
+/// optional params of the sendDocument call, added to the multipart form next to the document
+/// or serialized into the json body when sending by file_id
+#[derive(Default, Debug, Clone, Serialize, clap::Args)]
+struct SendOptions {
+    /// Caption for the sent document (up to 1024 characters)
+    #[arg(long, value_name = "TEXT", value_parser = |s: &str| validate_caption(s).map(|_| s.to_string()))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    caption: Option<String>,
+    /// Caption formatting
+    #[arg(long, value_enum, value_name = "MODE", ignore_case = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<ParseMode>,
+    /// Send without a notification, the message arrives without a sound
+    #[arg(long)]
+    #[serde(rename = "disable_notification", skip_serializing_if = "std::ops::Not::not")]
+    silent: bool,
+    /// Protect the message from forwarding and saving
+    #[arg(long)]
+    #[serde(rename = "protect_content", skip_serializing_if = "std::ops::Not::not")]
+    protect: bool,
+    /// Send it as a reply to this message of the chat
+    #[arg(long, value_name = "MESSAGE_ID")]
+    #[serde(rename = "reply_to_message_id", skip_serializing_if = "Option::is_none")]
+    reply_to: Option<i64>,
+    /// Send it anyway when the message to reply to is gone
+    #[arg(long, requires = "reply_to")]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    allow_sending_without_reply: bool,
+    /// Forum topic to send it to, its message_thread_id or a name from [topics] (default: topic_id from the config)
+    #[arg(long, value_name = "TOPIC")]
+    #[serde(skip)]
+    topic: Option<String>,
+    // --topic or topic_id once the config is loaded, see Config::apply_topic
+    #[arg(skip)]
+    #[serde(rename = "message_thread_id", skip_serializing_if = "Option::is_none")]
+    thread_id: Option<i64>,
+    /// Row of buttons under the message, LABEL|URL, several in the row separated by || (repeatable)
+    #[arg(long = "button", value_name = "LABEL|URL", value_parser = button::parse)]
+    #[serde(rename = "reply_markup", skip_serializing_if = "Vec::is_empty", serialize_with = "button::serialize")]
+    buttons: Vec<button::Row>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct TelegramDocument {
-    file_id: String,
+impl SendOptions {
+    fn apply(&self, mut form: multipart::Form) -> multipart::Form {
+        if let Some(caption) = &self.caption {
+            form = form.text("caption", caption.clone());
+        }
+        if let Some(parse_mode) = self.parse_mode {
+            form = form.text("parse_mode", parse_mode.as_str());
+        }
+        if self.silent {
+            form = form.text("disable_notification", "true");
+        }
+        if self.protect {
+            form = form.text("protect_content", "true");
+        }
+        if let Some(reply_to) = self.reply_to {
+            form = form.text("reply_to_message_id", reply_to.to_string());
+        }
+        if self.allow_sending_without_reply {
+            form = form.text("allow_sending_without_reply", "true");
+        }
+        if let Some(thread_id) = self.thread_id {
+            form = form.text("message_thread_id", thread_id.to_string());
+        }
+        if !self.buttons.is_empty() {
+            form = form.text("reply_markup", button::markup(&self.buttons));
+        }
+        form
+    }
+
+    /// the options without the caption, for a sendMediaGroup call whose files have their own and the parts of --split
+    fn without_caption(&self) -> Self {
+        SendOptions {
+            caption: None,
+            parse_mode: None,
+            ..self.clone()
+        }
+    }
+
+    /// the options for the other chats of a broadcast, the message replied to and the topic are only in the first one
+    fn for_other_chats(&self) -> Self {
+        SendOptions {
+            reply_to: None,
+            allow_sending_without_reply: false,
+            topic: None,
+            thread_id: None,
+            ..self.clone()
+        }
+    }
 }
 
-// ===== API file path structs
+/// the bot api method a file is sent with, photos, videos, audio and animations are shown inline by the clients
+/// a document is sent as it is, photos are recompressed by telegram
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SendMethod {
+    #[default]
+    Document,
+    Photo,
+    Video,
+    Audio,
+    Animation,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct RequestGetFile {
-    file_id: String,
+impl SendMethod {
+    /// the method for the MIME type, only the formats the clients play inline, e.g. video/webm stays a document
+    /// https://core.telegram.org/bots/api#sendvideo
+    fn for_mime(mime_type: &str) -> Self {
+        match mime_type {
+            "image/jpeg" | "image/png" | "image/webp" => SendMethod::Photo,
+            "image/gif" => SendMethod::Animation,
+            "video/mp4" => SendMethod::Video,
+            "audio/mpeg" | "audio/mp4" | "audio/m4a" | "audio/x-m4a" => SendMethod::Audio,
+            _ => SendMethod::Document,
+        }
+    }
+    fn path(self) -> &'static str {
+        match self {
+            SendMethod::Document => API_SEND_DOCUMENT,
+            SendMethod::Photo => API_SEND_PHOTO,
+            SendMethod::Video => API_SEND_VIDEO,
+            SendMethod::Audio => API_SEND_AUDIO,
+            SendMethod::Animation => API_SEND_ANIMATION,
+        }
+    }
+    /// the field of the method the file goes into, also what it's called in messages
+    fn field(self) -> &'static str {
+        match self {
+            SendMethod::Document => "document",
+            SendMethod::Photo => "photo",
+            SendMethod::Video => "video",
+            SendMethod::Audio => "audio",
+            SendMethod::Animation => "animation",
+        }
+    }
+    /// for messages, e.g. Sent as a photo
+    fn describe(self) -> &'static str {
+        match self {
+            SendMethod::Document => "a document",
+            SendMethod::Photo => "a photo",
+            SendMethod::Video => "a video",
+            SendMethod::Audio => "audio",
+            SendMethod::Animation => "an animation",
+        }
+    }
+    fn is_document(&self) -> bool {
+        *self == SendMethod::Document
+    }
+    /// the files it can share an album with, photos and videos go together, animations can't be in one
+    fn album(self) -> Option<&'static str> {
+        match self {
+            SendMethod::Photo | SendMethod::Video => Some("photos and videos"),
+            SendMethod::Document => Some("documents"),
+            SendMethod::Audio => Some("audio"),
+            SendMethod::Animation => None,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct FileUploadResponse {
-    ok: bool,
-    result: FileUploadResult,
+/// the method to send a file with, --as-* or the one of its MIME type
+/// photos over the limit of sendPhoto are sent as documents unless --as-photo asks for it
+fn send_method(args: &Args, mime_type: &str, size: u64) -> SendMethod {
+    if args.as_document {
+        return SendMethod::Document;
+    }
+    if args.as_photo {
+        return SendMethod::Photo;
+    }
+    if args.as_video {
+        return SendMethod::Video;
+    }
+    match SendMethod::for_mime(mime_type) {
+        SendMethod::Photo if size > PHOTO_SIZE_LIMIT => SendMethod::Document,
+        method => method,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct FileUploadResult {
-    file_path: String,
+/// telegram rejects captions over the limit, so check before uploading anything
+fn validate_caption(caption: &str) -> Result<(), String> {
+    let len = caption.chars().count();
+    if len > CAPTION_LIMIT {
+        return Err(format!("Caption is too long: {} characters, max is {}", len, CAPTION_LIMIT));
+    }
+    Ok(())
 }
 
-/// sendDocument telegram bot api
-/// https://core.telegram.org/bots/api#senddocument
-/// Use this method to send general files. On success, the sent Message is returned. 
-/// Bots can currently send files of any type of up to 50 MB in size, this limit may be changed in the future.
-/// Because id getFile limit is 20 MB, this is set as the limit for the file size
-async fn api_upload_document(client: &Client, filename: &str, url: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let file = File::open(filename).await?;
+/// --name is sent as given, so it has to be a name telegram keeps
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("The name is empty".into());
+    }
+    if name.contains(['/', '\\']) {
+        return Err(format!("The name {:?} contains a path separator, give only the file name", name));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(format!("The name {:?} contains control characters", name));
+    }
+    if name.len() > NAME_LIMIT {
+        return Err(format!("The name is too long: {} bytes, max is {}", name.len(), NAME_LIMIT));
+    }
+    Ok(())
+}
 
-    let file_path = Path::new(filename);
-    let file_size = std::fs::metadata(file_path)?.len();
+/// a name telegram keeps as it is: no path separators or control characters, single spaces
+/// and at most NAME_LIMIT bytes, cut before the extension so the type of the file stays
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() || c.is_whitespace())
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect();
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return "file".to_string();
+    }
+    if name.len() <= NAME_LIMIT {
+        return name;
+    }
+    // an extension that long is more likely part of the name
+    let extension = match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot <= 16 => &name[dot..],
+        _ => "",
+    };
+    let stem = &name[..name.len() - extension.len()];
+    let mut end = NAME_LIMIT - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", stem[..end].trim_end(), extension)
+}
 
-    // check filesize
-    if file_size > FILE_SIZE_LIMIT {
-        let limit_mb =  FILE_SIZE_LIMIT / 1000000;
-        let msg = format!("Filesize is too big. Max size is {} MB", limit_mb);
-        println!("{}", msg.red());
-        std::process::exit(1);
+/// filename to use for the multipart part
+/// takes the basename of the path, so "../docs/a.pdf" is sent as "a.pdf"
+/// --name is validated already, the basename is sanitized
+fn upload_name(filename: &Path, name_override: Option<&str>) -> String {
+    if let Some(name) = name_override {
+        return name.to_string();
     }
-    // progress bar init
-    // let mut downloaded = 0;
-    let mut bytes_uploaded: u64 = 0;
+    let name = filename.file_name().unwrap_or(filename.as_os_str());
+    let lossy = name.to_string_lossy();
+    // telegram needs utf-8, the invalid bytes become U+FFFD
+    if name.to_str().is_none() {
+        output::print_warning(&format!("{} is not valid UTF-8, sending it as {}", filename.display(), lossy));
+    }
+    let sanitized = sanitize_name(&lossy);
+    if sanitized != lossy {
+        output::print_warning(&format!("Renamed {:?} to {:?}", lossy, sanitized));
+    }
+    sanitized
+}
 
-    let pb = ProgressBar::new(file_size);
-    let template = "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({eta})";
-    pb.set_style(
-        ProgressStyle::with_template(template)
-            .unwrap()
-            .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
-                write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
-            })
-            .progress_chars("/-"),
-    );
+/// the argument is an http(s) URL to send instead of a file
+fn http_url(filename: &Path) -> Option<&str> {
+    let url = filename.to_str()?;
+    let scheme = url.get(..8)?.to_ascii_lowercase();
+    (scheme.starts_with("http://") || scheme == "https://").then_some(url)
+}
 
+/// %XX escapes for the value of a filename* parameter, everything but the attr-char of RFC 5987
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => (byte as char).to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
 
-    // another stream with chunks
-    let frame = FramedRead::new(file, BytesCodec::new());
-    let stream = frame
-        .map_ok(move |chunk| {
-            bytes_uploaded += chunk.len() as u64;
-            pb.set_position(bytes_uploaded);
-            if bytes_uploaded == file_size {
-                pb.finish_and_clear();
+/// %XX escapes of a URL path or a filename* parameter, invalid ones are left as they are
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok()).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
             }
-            chunk
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// the name of a file at a URL, the last part of its path, e.g. release.tar.gz
+fn url_name(url: &str) -> String {
+    let last = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.path_segments().and_then(|mut segments| segments.rfind(|s| !s.is_empty()).map(percent_decode)));
+    sanitize_name(&last.unwrap_or_else(|| "download".to_string()))
+}
+
+/// the filename of a Content-Disposition header, filename*=UTF-8''... wins over filename="..."
+fn disposition_name(value: &str) -> Option<String> {
+    let param = |key: &str| {
+        value.split(';').map(str::trim).find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim().eq_ignore_ascii_case(key).then(|| value.trim().trim_matches('"').to_string())
         })
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+    };
+    if let Some(encoded) = param("filename*") {
+        // charset'language'value
+        let value = encoded.splitn(3, '\'').nth(2).unwrap_or(&encoded);
+        return Some(percent_decode(value));
+    }
+    param("filename").filter(|name| !name.is_empty())
+}
 
-    let body = Body::wrap_stream(stream);
+/// the options that need the bytes of a URL, telegram only takes the URL as it is
+fn url_needs_file(args: &Args) -> Option<&'static str> {
+    [
+        (args.name.is_some(), "--name"),
+        (args.compress.is_some(), "--compress"),
+        (args.encrypt, "--encrypt"),
+        (args.split, "--split"),
+        (args.mime.is_some(), "--mime"),
+        (args.caption_template.is_some(), "--caption-template"),
+        (args.checksum_caption, "--checksum-caption"),
+        // a thumbnail can only be uploaded with the file
+        (args.thumb.is_some(), "--thumb"),
+    ]
+    .into_iter()
+    .find_map(|(given, option)| given.then_some(option))
+}
 
-    //make form part of file
-    let mime_type = mime_guess::from_path(filename).first_or_octet_stream();
-    let part = multipart::Part::stream(body)
-        .file_name(filename.to_string())
-        .mime_str(mime_type.essence_str())?;
+/// the name of a file part with the filename in it, for a form made with percent_encode_noop
+/// reqwest only writes filename="...", so filename*=UTF-8''... (RFC 6266, RFC 5987) rides in the name:
+/// name="document"; filename*=UTF-8''%D0%BE%D1%82.pdf; filename="__.pdf"
+/// this relies on reqwest 0.11.14 writing a noop-encoded name as is between the quotes of name="...",
+/// which is why Cargo.toml pins that version and tests/file_names.rs checks the whole header
+/// the quoted one is the ascii fallback for servers that don't read filename*
+fn part_name(field: &str, file_name: &str) -> String {
+    format!("{}\"; filename*=UTF-8''{}; filename=\"{}", field, percent_encode(file_name), ascii_fallback(file_name))
+}
 
-    //create the multipart form
-    let form = multipart::Form::new()
-        .part("document", part);
+/// the name for filename="...", every character that isn't printable ascii becomes _
+/// quotes and backslashes are escaped, control characters never get this far anyway
+fn ascii_fallback(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => format!("\\{}", c),
+            ' '..='~' => c.to_string(),
+            _ => "_".to_string(),
+        })
+        .collect()
+}
 
-    //send request
-    let result = client
-        .post(url)
-        .multipart(form)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    // parse the response and get the file_id
-    let mut file_id = String::new();
-    let result: Result<TelegramResponseDocument, serde_json::Error> = serde_json::from_str(&result);
-    match result {
-        Ok(r) => {
-            if !r.ok {
-                println!("{}", "Uploading error".red());
-            }
-            // check result.document.file_id
-            if r.result.is_none() {
-                println!("{}", "Uploading error".red());
-            }
-            file_id = r.result.unwrap().document.file_id;
-        },
-        Err(err) => {
-            // Handle the error
-            println!("{} {}", "Error deserializing response:".red(), err);
-        }
-    }
+// ===== API document upload structs
 
-    Ok(file_id)
+#[derive(Debug, Deserialize, Serialize)]
+struct TelegramResponseDocument {
+    ok: bool,
+    result: Option<TelegramSentMessage>,
+    #[serde(flatten)]
+    error: TelegramError,
 }
 
-/// getFile telegram bot api
-/// https://core.telegram.org/bots/api#getfile
-/// Use this method to get basic information about a file and prepare it for downloading. 
-/// For the moment, bots can download files of up to 20MB in size. On success, a File object is returned. 
-/// The file can then be downloaded via the link https://api.telegram.org/file/bot<token>/<file_path>, 
-/// where <file_path> is taken from the response. 
-/// It is guaranteed that the link will be valid for at least 1 hour. 
-/// When the link expires, a new one can be requested by calling getFile again.
-async fn api_get_file_path(client: &Client, file_id: &str, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+/// what telegram says when ok is false
+/// https://core.telegram.org/bots/api#making-requests
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct TelegramError {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error_code: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parameters: Option<ResponseParameters>,
+}
 
-    let request = RequestGetFile {
-        file_id: file_id.to_string(),
-    };
-    let result = client
-        .post(url)
-        .json(&request)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    // parse the response and get the file_id
-    let mut file_path = String::new();
-    let result: Result<FileUploadResponse, serde_json::Error> = serde_json::from_str(&result);
-    match result {
-        Ok(r) => {
-            if !r.ok {
-                println!("{}", "File path API error".red());
-            }
-            // check result.document.file_id
-            if r.result.file_path == "" {
-                println!("{}", "File path API error: file_path not found".red());
-            }
-            file_path = r.result.file_path;
-        },
-        Err(err) => {
-            println!("{} {}", "Error deserializing response:".red(), err);
+// the chat is wrong or the bot can't see it yet
+const CHAT_NOT_FOUND_HINT: &str =
+    "check chat_id: a user has to start the bot first, a group or channel has to have it as a member. `teledrop setup` lists the chats the bot can see";
+
+// reason phrases telegram puts before the description, the code already says it
+const ERROR_REASONS: &[&str] = &["Bad Request", "Unauthorized", "Forbidden", "Not Found", "Conflict", "Too Many Requests"];
+
+impl TelegramError {
+    /// e.g. "Telegram error 400: chat not found"
+    /// `status` is the http status, for the responses that have no error_code
+    fn summary(&self, status: Option<u16>) -> String {
+        let description = self.description.as_deref().unwrap_or("no description");
+        let description = match description.split_once(": ") {
+            Some((reason, rest)) if ERROR_REASONS.contains(&reason) => rest,
+            _ => description,
+        };
+        match self.error_code.or(status.map(i64::from)) {
+            Some(code) => format!("Telegram error {}: {}", code, description),
+            None => format!("Telegram error: {}", description),
         }
     }
-    // exit if not found
-    if file_path == "" {
-        std::process::exit(1);
+
+    /// the summary with a hint for the codes that have an obvious fix
+    fn message(&self, status: Option<u16>) -> String {
+        let mut msg = self.summary(status);
+        if let Some(hint) = self.error_code.or(status.map(i64::from)).and_then(error_hint) {
+            msg.push_str(", ");
+            msg.push_str(hint);
+        }
+        msg
     }
 
-    Ok(file_path)
-}
+    /// the error to return for a failed call
+    /// a refused token, an unknown chat and a bot that may not send there get their own exit codes
+    fn to_error(&self) -> Error {
+        let description = self.description.as_deref().unwrap_or_default();
+        let summary = self.summary(None);
+        match self.error_code {
+            Some(401) => {}
+            Some(400) if description.contains("chat not found") => {
+                return Error::ChatNotFound(format!("{}, {}", summary, CHAT_NOT_FOUND_HINT));
+            }
+            Some(400) if description.contains("BUTTON_URL_INVALID") => {
+                return Error::Api(format!("{}, Telegram refused the URL of a --button", summary));
+            }
+            Some(400) if description.contains("message thread not found") => {
+                return Error::Api(format!("{}, check --topic and topic_id, the link of a message in the topic has its ID: t.me/c/CHAT/TOPIC/MESSAGE", summary));
+            }
+            Some(400) if description.contains("message to reply not found") => {
+                return Error::Api(format!("{}, check --reply-to, --allow-sending-without-reply sends it anyway", summary));
+            }
+            Some(403) if description.contains("kicked") => {
+                return Error::Forbidden(format!("{}, add the bot to the chat again or send to another chat", summary));
+            }
+            Some(403) if description.contains("blocked") => {
+                return Error::Forbidden(format!("{}, the user has to unblock it before it can send them files", summary));
+            }
+            Some(403) => return Error::Forbidden(self.message(None)),
+            _ => return Error::Api(self.message(None)),
+        }
+        let path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|_| "the config file".to_string());
+        Error::Auth(format!(
+            "{}. Check bot_token in {} (or --token and TELEDROP_BOT_TOKEN), @BotFather shows the current token",
+            self.message(None),
+            path
+        ))
+    }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // ===== CONFIG
-    let cfg_result = confy::load(APP_NAME, CONFIG_NAME);
-    let cfg: Config = match cfg_result {
-        Ok(file) => file,
-        Err(error) => {
-            println!("{} {}", "Config error:".red(), error);
-            return Ok(());
+    /// to_error for a send, `protect` is whether it had protect_content
+    /// only then is a refusal that names it about --protect
+    fn to_send_error(&self, protect: bool) -> Error {
+        let description = self.description.as_deref().unwrap_or_default().to_ascii_lowercase();
+        if protect && self.error_code == Some(400) && description.contains("protect") {
+            return Error::Api(format!("{}, send it without --protect or set protect_content = false for this chat", self.summary(None)));
         }
-    };
-    // check if bot_token and chat_id exists in config
-    if cfg.bot_token == "" {
-        println!("{}", "Config param bot_token is missing".red());
-    }
-    if cfg.chat_id == "" {
-        println!("{}", "Config param chat_id is missing".red());
-    }
-    if cfg.bot_token == "" || cfg.chat_id == "" {
-        // print config file path
-        let config_path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME);
-        println!(
-            "Please set up your configuration file at \n\n\"{}\"",
-            config_path.unwrap().to_str().unwrap().green()
-        );
-        return Ok(());
+        self.to_error()
     }
+}
 
-    // // ===== OPEN & READ THE FILE
-    // check arg, check the file size and read the contents
-    let filename_opt = env::args().nth(1);
-    if filename_opt.is_none() {
-        println!("{}", "No filename provided".red());
-        return Ok(());
+/// what to do about an error code, 5xx and 429 are retried so they need none
+fn error_hint(code: i64) -> Option<&'static str> {
+    match code {
+        401 => Some("the bot token is wrong or was revoked"),
+        403 => Some("the bot may not post in this chat, it was removed, blocked or lacks the rights"),
+        404 => Some("check the bot token and api_base_url"),
+        413 => Some("the file is too big for the server, --split sends it in parts"),
+        _ => None,
     }
-    let filename = filename_opt.unwrap();
+}
 
-    // create API client
-    let client = Client::new();
+/// https://core.telegram.org/bots/api#responseparameters
+#[derive(Debug, Deserialize, Serialize)]
+struct ResponseParameters {
+    // seconds to wait after a 429 before the request may be repeated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+}
 
-    // ===== UPLOAD FILE
-    let url = cfg.api_url_send_document();
-    let upload_res = api_upload_document(&client, &filename, &url);
-    let file_id = tokio::runtime::Runtime::new().unwrap().block_on(upload_res).unwrap();
-    // create an empty spinner and stop imidiately printing the file_id
-    let mut sp = Spinner::new(Spinners::Dots12, "".into());
-    let file_id_msg = format!("File ID: {}", file_id);
-    sp.stop_and_persist("✔", file_id_msg.into());
+/// the sent message as telegram returns it, the file may end up as a photo, video, audio or animation
+/// instead of a document, e.g. when a bot api server of its own converts it
+#[derive(Debug, Deserialize, Serialize)]
+struct TelegramSentMessage {
+    message_id: i64,
+    chat: TelegramChat,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    document: Option<TelegramDocument>,
+    // every size telegram made of the photo
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    photo: Vec<TelegramPhotoSize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    video: Option<TelegramDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audio: Option<TelegramDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    animation: Option<TelegramDocument>,
+    // the message it replies to, --reply-to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reply_to_message: Option<TelegramReplyTo>,
+}
 
+/// only the id of the message replied to is kept
+#[derive(Debug, Deserialize, Serialize)]
+struct TelegramReplyTo {
+    message_id: i64,
+}
 
-    // ===== GET FILE URL
-    // start the spinner
-    let loading_str = "Loading file URL...";
-    sp = Spinner::new(Spinners::Dots12, loading_str.into());
-    // do API call
-    let api_file_path = cfg.api_url_get_file();
-    let file_path_res = api_get_file_path(&client, &file_id, &api_file_path);
-    let file_path = tokio::runtime::Runtime::new().unwrap().block_on(file_path_res).unwrap();
-    let file_url = cfg.api_url_file_url(file_path);
-    // stop the spinner and print the URL
-    let file_url_msg = format!("Download URL (valid for 1 hour):\n{}", &file_url.green());
-    sp.stop_and_persist("✔", file_url_msg.into());
-    Ok(())
+impl TelegramSentMessage {
+    /// whichever file the message has, of a photo the largest size
+    /// none when there is no file or its file_id is empty
+    fn into_result(self) -> Option<TelegramResult> {
+        let photo = self
+            .photo
+            .into_iter()
+            .max_by_key(|size| size.file_size.unwrap_or(u64::from(size.width) * u64::from(size.height)))
+            .map(|size| TelegramDocument {
+                file_id: size.file_id,
+                file_unique_id: size.file_unique_id,
+                file_name: None,
+                file_size: size.file_size,
+            });
+        let document = self
+            .document
+            .or(self.video)
+            .or(self.audio)
+            .or(self.animation)
+            .or(photo)
+            .filter(|document| !document.file_id.is_empty())?;
+        Some(TelegramResult {
+            message_id: self.message_id,
+            chat: self.chat,
+            document,
+            reply_to: self.reply_to_message.map(|reply| reply.message_id),
+        })
+    }
+}
 
+#[derive(Debug, Deserialize, Serialize)]
+struct TelegramPhotoSize {
+    file_id: String,
+    file_unique_id: String,
+    width: u32,
+    height: u32,
+    file_size: Option<u64>,
+}
+
+/// a sent message with the file it holds
+#[derive(Debug)]
+struct TelegramResult {
+    message_id: i64,
+    chat: TelegramChat,
+    document: TelegramDocument,
+    reply_to: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TelegramChat {
+    id: i64,
+    // groups and channels have a title, private chats a name, any of them may have a username
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    first_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_name: Option<String>,
+    // private, group, supergroup or channel
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    kind: Option<String>,
+    // supergroups with topics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    is_forum: Option<bool>,
+}
+
+impl TelegramChat {
+    /// the title of a group or channel, the name of a user
+    fn name(&self) -> Option<String> {
+        match (&self.title, &self.first_name, &self.last_name) {
+            (Some(title), _, _) => Some(title.clone()),
+            (None, Some(first), Some(last)) => Some(format!("{} {}", first, last)),
+            (None, Some(first), None) => Some(first.clone()),
+            (None, None, _) => None,
+        }
+    }
+    /// the name, or the @username when there is none
+    fn display_name(&self) -> Option<String> {
+        self.name().or_else(|| self.username.as_ref().map(|username| format!("@{}", username)))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TelegramDocument {
+    file_id: String,
+    file_unique_id: String,
+    file_name: Option<String>,
+    file_size: Option<u64>,
+}
+
+// sendDocument and the other send methods with a file_id or URL instead of an upload
+#[derive(Debug, Serialize)]
+struct RequestSendFile<'a> {
+    chat_id: &'a str,
+    #[serde(flatten)]
+    file: FileField<'a>,
+    #[serde(flatten)]
+    send: &'a SendOptions,
+}
+
+/// a file of an album, the media is attach://NAME for a part of the form or a file_id
+/// only the caption is taken from send, the other options go with the whole sendMediaGroup call
+/// https://core.telegram.org/bots/api#inputmedia
+#[derive(Debug, Serialize)]
+struct InputMedia {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    media: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    caption: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<ParseMode>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    supports_streaming: bool,
+}
+
+impl InputMedia {
+    fn new(method: SendMethod, media: String, send: SendOptions) -> Self {
+        InputMedia {
+            kind: method.field(),
+            media,
+            caption: send.caption,
+            parse_mode: send.parse_mode,
+            supports_streaming: method == SendMethod::Video,
+        }
+    }
+}
+
+// sendMediaGroup with the file_ids of an album that was uploaded already
+#[derive(Debug, Serialize)]
+struct RequestSendMediaGroup<'a> {
+    chat_id: &'a str,
+    media: Vec<InputMedia>,
+    #[serde(flatten)]
+    send: SendOptions,
+}
+
+/// the file_id or URL under the field of the method, e.g. "photo": "AgAC..."
+#[derive(Debug)]
+struct FileField<'a>(SendMethod, &'a str);
+
+impl Serialize for FileField<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.0.field(), self.1)?;
+        map.end()
+    }
+}
+
+// ===== API file path structs
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RequestGetFile {
+    file_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileUploadResponse {
+    ok: bool,
+    result: Option<FileUploadResult>,
+    // why the request failed, when ok is false
+    #[serde(flatten)]
+    error: TelegramError,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileUploadResult {
+    file_id: String,
+    file_unique_id: String,
+    file_size: Option<u64>,
+    file_path: String,
+}
+
+// ===== API json method structs
+
+/// response of any bot api method
+#[derive(Debug, Deserialize)]
+struct TelegramResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    // why the request failed, when ok is false
+    #[serde(flatten)]
+    error: TelegramError,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestDeleteMessage<'a> {
+    chat_id: &'a str,
+    message_id: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestEditMessageCaption<'a> {
+    chat_id: &'a str,
+    message_id: i64,
+    caption: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<ParseMode>,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestForwardMessage<'a> {
+    chat_id: &'a str,
+    from_chat_id: &'a str,
+    message_id: i64,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    disable_notification: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    protect_content: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestPinChatMessage {
+    chat_id: i64,
+    message_id: i64,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    disable_notification: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestGetChat<'a> {
+    chat_id: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramUser {
+    id: i64,
+    first_name: String,
+    username: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestGetChatMember<'a> {
+    chat_id: &'a str,
+    user_id: i64,
+}
+
+/// the status of the bot in a chat, the rights are only there for admins and restricted members
+#[derive(Debug, Deserialize)]
+struct TelegramChatMember {
+    status: String,
+    can_post_messages: Option<bool>,
+    can_send_documents: Option<bool>,
+    is_member: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestSendMessage<'a> {
+    chat_id: &'a str,
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_thread_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestSendChatAction<'a> {
+    chat_id: &'a str,
+    // "upload_document" shows "sending a file..." for a few seconds, nothing is sent
+    action: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestGetUpdates {
+    // seconds to wait for an update, long polling
+    timeout: u64,
+}
+
+/// the parts of an update that tell about a chat
+#[derive(Debug, Deserialize)]
+struct TelegramUpdate {
+    message: Option<TelegramSentMessage>,
+    channel_post: Option<TelegramSentMessage>,
+    // the bot was added to or removed from a chat
+    my_chat_member: Option<TelegramChatMemberUpdated>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramChatMemberUpdated {
+    chat: TelegramChat,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramMessage {
+    message_id: i64,
+    chat: TelegramChat,
+    caption: Option<String>,
+}
+
+// set while --jobs uploads several files at once, their bars and spinners would draw over each other
+static CONCURRENT: AtomicBool = AtomicBool::new(false);
+
+/// progress bar with bytes transferred, percentage, speed and eta
+/// when stderr is not a terminal the bar is hidden and a single plain line like "Uploading a.pdf (1.20 MB)..." is printed instead
+fn transfer_progress_bar(action: &str, name: &str, file_size: u64, progress: bool) -> ProgressBar {
+    if !progress {
+        return ProgressBar::hidden();
+    }
+    if !std::io::stderr().is_terminal() {
+        eprintln!("{} {} ({})...", action, name, DecimalBytes(file_size));
+        return ProgressBar::hidden();
+    }
+    if CONCURRENT.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new(file_size);
+    let template = if colored::control::SHOULD_COLORIZE.should_colorize() {
+        "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {decimal_bytes}/{decimal_total_bytes} {percent}% {decimal_bytes_per_sec} ({eta})"
+    } else {
+        "{spinner} [{elapsed_precise}] [{wide_bar}] {decimal_bytes}/{decimal_total_bytes} {percent}% {decimal_bytes_per_sec} ({eta})"
+    };
+    pb.set_style(
+        ProgressStyle::with_template(template)
+            .unwrap()
+            .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
+                write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
+            })
+            .progress_chars("/-"),
+    );
+    pb
+}
+
+/// one bar for the files of --jobs, with the names of the ones uploading right now
+fn batch_progress_bar(files: u64, mode: &OutputMode) -> ProgressBar {
+    if !mode.is_human() || !std::io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new(files);
+    let template = if colored::control::SHOULD_COLORIZE.should_colorize() {
+        "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos}/{len} files\n  {msg}"
+    } else {
+        "{spinner} [{elapsed_precise}] [{wide_bar}] {pos}/{len} files\n  {msg}"
+    };
+    pb.set_style(ProgressStyle::with_template(template).unwrap().progress_chars("/-"));
+    pb.enable_steady_tick(Duration::from_millis(100));
+    pb
+}
+
+/// result of a successful upload
+struct Uploaded {
+    file_id: String,
+    file_unique_id: String,
+    message_id: i64,
+    chat_id: i64,
+    chat_name: Option<String>,
+    reply_to: Option<i64>,
+    // checksum of the bytes that were sent
+    sha256: String,
+    // what telegram says it got, when it says
+    file_size: Option<u64>,
+}
+
+/// opened upload input
+/// files are streamed from disk, stdin is buffered in memory because its size is not known upfront
+struct Payload {
+    source: PayloadSource,
+    size: u64,
+}
+
+enum PayloadSource {
+    File(File),
+    // a byte range of a file, for split parts
+    Part(io::Take<File>, u64),
+    Memory(Vec<u8>),
+}
+
+impl Payload {
+    fn into_reader(self) -> Box<dyn AsyncRead + Send + Sync + Unpin> {
+        match self.source {
+            PayloadSource::File(file) => Box::new(file),
+            PayloadSource::Part(part, _) => Box::new(part),
+            PayloadSource::Memory(contents) => Box::new(std::io::Cursor::new(contents)),
+        }
+    }
+
+    /// the same payload from its start again, for another attempt at uploading it
+    async fn try_clone(&self) -> io::Result<Payload> {
+        let source = match &self.source {
+            PayloadSource::File(file) => {
+                let mut file = file.try_clone().await?;
+                file.seek(std::io::SeekFrom::Start(0)).await?;
+                PayloadSource::File(file)
+            }
+            PayloadSource::Part(part, offset) => {
+                let mut file = part.get_ref().try_clone().await?;
+                file.seek(std::io::SeekFrom::Start(*offset)).await?;
+                PayloadSource::Part(file.take(self.size), *offset)
+            }
+            PayloadSource::Memory(contents) => PayloadSource::Memory(contents.clone()),
+        };
+        Ok(Payload { source, size: self.size })
+    }
+
+    /// where the payload starts in the file on disk
+    fn offset(&self) -> u64 {
+        match self.source {
+            PayloadSource::Part(_, offset) => offset,
+            _ => 0,
+        }
+    }
+}
+
+/// the most telegram takes in one upload, --upload-limit or the limit of the config
+fn upload_limit(cfg: &Config, args: &Args) -> u64 {
+    args.upload_limit.unwrap_or_else(|| cfg.upload_size_limit())
+}
+
+fn file_too_big_error(size: u64, limit: u64) -> Box<dyn std::error::Error> {
+    Error::File(format!(
+        "File is too big: {}, the upload limit is {} (use --split to send it in parts)",
+        DecimalBytes(size),
+        DecimalBytes(limit)
+    ))
+    .into()
+}
+
+/// reading from a terminal would just hang waiting for input
+fn check_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    if std::io::stdin().is_terminal() {
+        return Err(Error::Usage("stdin is a terminal, pipe some data in, e.g. `cat file | teledrop - --name file`".into()).into());
+    }
+    Ok(())
+}
+
+/// what --allow-empty sends instead of an empty file
+const EMPTY_PLACEHOLDER: &[u8] = b"\n";
+
+/// telegram answers "file must be non-empty", so don't even send it
+/// with --allow-empty the payload is a single newline instead
+fn empty_payload(filename: &Path, allow_empty: bool) -> Result<Payload, Box<dyn std::error::Error>> {
+    let stdin = filename.as_os_str() == STDIN_FILENAME;
+    if !allow_empty {
+        let what = if stdin { "stdin" } else { "File" };
+        let msg = format!("{} is empty, Telegram doesn't accept empty files (--allow-empty sends a single newline instead)", what);
+        return Err(Error::File(msg).into());
+    }
+    let what = if stdin { "stdin".into() } else { filename.display().to_string() };
+    output::print_warning(&format!("{} is empty, sending a single newline instead", what));
+    Ok(Payload {
+        size: EMPTY_PLACEHOLDER.len() as u64,
+        source: PayloadSource::Memory(EMPTY_PLACEHOLDER.to_vec()),
+    })
+}
+
+/// open the file or read stdin and check the size limit
+async fn open_payload(filename: &Path, limit: u64, allow_empty: bool) -> Result<Payload, Box<dyn std::error::Error>> {
+    if filename.as_os_str() == STDIN_FILENAME {
+        check_stdin()?;
+        // read one byte over the limit to know if it was exceeded without buffering everything
+        let mut contents = Vec::new();
+        io::stdin().take(limit + 1).read_to_end(&mut contents).await?;
+        if contents.len() as u64 > limit {
+            return Err(Error::File(format!("stdin is too big: over the upload limit of {}", DecimalBytes(limit))).into());
+        }
+        if contents.is_empty() {
+            return empty_payload(filename, allow_empty);
+        }
+        return Ok(Payload {
+            size: contents.len() as u64,
+            source: PayloadSource::Memory(contents),
+        });
+    }
+
+    let file = File::open(filename).await.map_err(|err| Error::io(filename.display(), &err))?;
+    let file_size = file.metadata().await.map_err(|err| Error::io(filename.display(), &err))?.len();
+    // check filesize
+    if file_size > limit {
+        return Err(file_too_big_error(file_size, limit));
+    }
+    if file_size == 0 {
+        return empty_payload(filename, allow_empty);
+    }
+    Ok(Payload {
+        source: PayloadSource::File(file),
+        size: file_size,
+    })
+}
+
+/// open a byte range of the file as a payload
+async fn open_payload_part(filename: &Path, offset: u64, size: u64) -> Result<Payload, Box<dyn std::error::Error>> {
+    let mut file = File::open(filename).await.map_err(|err| Error::io(filename.display(), &err))?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    Ok(Payload {
+        source: PayloadSource::Part(file.take(size), offset),
+        size,
+    })
+}
+
+/// read the payload bytes from disk again, in chunks
+/// only the range of the file that is going to be uploaded is read
+fn read_payload(filename: &Path, payload: &Payload, mut f: impl FnMut(&[u8])) -> std::io::Result<()> {
+    if let PayloadSource::Memory(contents) = &payload.source {
+        f(contents);
+        return Ok(());
+    }
+    let mut file = std::fs::File::open(filename)?;
+    std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(payload.offset()))?;
+    let mut file = file.take(payload.size);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        f(&buf[..n]);
+    }
+}
+
+// how many bytes of the file are looked at to sniff the content type
+const MIME_SNIFF_LEN: usize = 8192;
+// formats that are zip files inside, for these the extension is more specific than the content
+const MIME_CONTAINERS: &[&str] = &["application/zip"];
+
+/// detect the content type of the upload
+/// the magic bytes win over the extension, unless the content is only recognized as a generic zip container
+/// (docx, jar, epub and friends), octet-stream is used when nothing matches
+fn detect_mime(name: &str, filename: &Path, payload: &Payload) -> Result<(String, &'static str), Box<dyn std::error::Error>> {
+    let mut head = Vec::with_capacity(MIME_SNIFF_LEN);
+    match &payload.source {
+        PayloadSource::Memory(contents) => head.extend_from_slice(&contents[..contents.len().min(MIME_SNIFF_LEN)]),
+        _ => {
+            let file = std::fs::File::open(filename).map_err(|err| Error::io(filename.display(), &err))?;
+            file.take(MIME_SNIFF_LEN as u64).read_to_end(&mut head).map_err(|err| Error::io(filename.display(), &err))?;
+        }
+    }
+    let sniffed = infer::get(&head).map(|t| t.mime_type().to_string());
+    let guessed = mime_guess::from_path(name).first().map(|m| m.essence_str().to_string());
+    let detected = match (sniffed, guessed) {
+        (Some(sniffed), Some(guessed)) if MIME_CONTAINERS.contains(&sniffed.as_str()) => (guessed, "extension"),
+        (Some(sniffed), _) => (sniffed, "content"),
+        (None, Some(guessed)) => (guessed, "extension"),
+        (None, None) => ("application/octet-stream".to_string(), "default"),
+    };
+    Ok(detected)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// hex digest of the payload
+/// this is an extra pass over the file, so it's only done when a caption asks for it
+fn payload_digest<D: Digest>(filename: &Path, payload: &Payload) -> Result<String, Box<dyn std::error::Error>> {
+    let mut hasher = D::new();
+    read_payload(filename, payload, |chunk| hasher.update(chunk)).map_err(|err| Error::io(filename.display(), &err))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// hex sha256 of a whole file on disk
+fn file_sha256(path: &str) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hex(&hasher.finalize()));
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+const CAPTION_PLACEHOLDERS: &[&str] = &["name", "size", "size_human", "sha256", "md5", "mtime", "hostname"];
+
+/// render --caption-template for the file about to be uploaded
+fn render_caption(template: &str, name: &str, filename: &Path, payload: &Payload) -> Result<String, Box<dyn std::error::Error>> {
+    let caption = template::render(template, |key| -> Result<String, Box<dyn std::error::Error>> {
+        let value = match key {
+            "name" => name.to_string(),
+            "size" => payload.size.to_string(),
+            "size_human" => DecimalBytes(payload.size).to_string(),
+            "sha256" => payload_digest::<Sha256>(filename, payload)?,
+            "md5" => payload_digest::<Md5>(filename, payload)?,
+            "mtime" => {
+                // stdin has no mtime, the time of upload is the closest thing
+                let mtime = match payload.source {
+                    PayloadSource::Memory(_) => Local::now(),
+                    _ => DateTime::<Local>::from(std::fs::metadata(filename).and_then(|m| m.modified()).map_err(|err| Error::io(filename.display(), &err))?),
+                };
+                mtime.format("%Y-%m-%d %H:%M:%S").to_string()
+            }
+            "hostname" => gethostname::gethostname().to_string_lossy().into_owned(),
+            _ => return Err(format!("Unknown placeholder {{{}}}", key).into()),
+        };
+        Ok(value)
+    })?;
+    validate_caption(&caption)?;
+    Ok(caption)
+}
+
+/// sendDocument telegram bot api
+/// https://core.telegram.org/bots/api#senddocument
+/// Use this method to send general files. On success, the sent Message is returned. 
+/// Bots can currently send files of any type of up to 50 MB in size, this limit may be changed in the future.
+#[allow(clippy::too_many_arguments)]
+async fn api_upload_document(client: &Client, payload: Payload, name: &str, mime_type: &str, method: SendMethod, send: &SendOptions, thumb: Option<&[u8]>, url: &str, timeout: Duration, progress: bool) -> Result<Uploaded, Box<dyn std::error::Error>> {
+    let file_size = payload.size;
+    // progress bar init
+    let pb = transfer_progress_bar("Uploading", name, file_size, progress);
+
+    // stream the file in chunks so it never has to be fully loaded in memory
+    // every chunk reqwest pulls from the stream moves the progress bar and goes into the checksum
+    let frame = FramedRead::new(payload.into_reader(), BytesCodec::new());
+    let pb_stream = pb.clone();
+    let hasher = Arc::new(Mutex::new(Sha256::new()));
+    let hasher_stream = hasher.clone();
+    // reqwest pulls the first chunk once it is connected, for the timing of -vv
+    let start = Instant::now();
+    let mut connected = false;
+    // reqwest only says the body failed, this keeps why reading the file did
+    let read_error: Arc<Mutex<Option<Error>>> = Arc::new(Mutex::new(None));
+    let read_error_stream = read_error.clone();
+    let name_stream = name.to_string();
+    let stream = frame
+        .map_ok(move |chunk| {
+            if !connected {
+                connected = true;
+                trace::phase("connect", start);
+            }
+            pb_stream.inc(chunk.len() as u64);
+            hasher_stream.lock().unwrap().update(&chunk);
+            chunk
+        })
+        .map_err(move |err| {
+            *read_error_stream.lock().unwrap() = Some(Error::io(&name_stream, &err));
+            io::Error::other(err)
+        });
+
+    let body = Body::wrap_stream(stream);
+
+    //make form part of file
+    // length is known upfront, so the multipart gets a proper Content-Length
+    let part = multipart::Part::stream_with_length(body, file_size)
+        .mime_str(mime_type)?;
+
+    //create the multipart form
+    // the names are written as they are, the one of the file part has its filename parameters
+    let mut form = multipart::Form::new()
+        .percent_encode_noop()
+        .part(part_name(method.field(), name), part);
+    // without it the clients download the whole video before playing it
+    if method == SendMethod::Video {
+        form = form.text("supports_streaming", "true");
+    }
+    // a thumbnail is a part of its own, the field refers to it by name
+    if let Some(thumb) = thumb {
+        trace::part("thumbnail", &format!("attach://thumb, {} bytes", thumb.len()));
+        let part = multipart::Part::bytes(thumb.to_vec()).file_name("thumb.jpg").mime_str("image/jpeg")?;
+        form = form.part("thumb", part).text("thumbnail", "attach://thumb");
+    }
+    let form = send.apply(form);
+    trace::part(method.field(), &format!("filename={:?}, type={}, {} bytes", name, mime_type, file_size));
+    if let Some(caption) = &send.caption {
+        trace::part("caption", &format!("{} characters", caption.chars().count()));
+    }
+    if let Some(parse_mode) = send.parse_mode {
+        trace::part("parse_mode", parse_mode.as_str());
+    }
+
+    //send request
+    let result = trace::send(client, client.post(url).multipart(form).timeout(timeout)).await;
+    // the body is fully sent at this point (or failed), clear the bar before printing anything
+    pb.finish_and_clear();
+    trace::phase("upload", start);
+    if let Some(err) = read_error.lock().unwrap().take() {
+        return Err(err.into());
+    }
+    let result = trace::text(result?, Some(timeout)).await?;
+    let start = Instant::now();
+    let r = parse_sent_document(&result, send.protect)?;
+    trace::phase("parse", start);
+    let sha256 = hex(&hasher.lock().unwrap().clone().finalize());
+    Ok(Uploaded {
+        file_id: r.document.file_id,
+        file_unique_id: r.document.file_unique_id,
+        message_id: r.message_id,
+        chat_name: r.chat.display_name(),
+        chat_id: r.chat.id,
+        reply_to: r.reply_to,
+        sha256,
+        file_size: r.document.file_size,
+    })
+}
+
+/// parse the response of sendDocument or another send method and get the sent message
+fn parse_sent_document(body: &str, protect: bool) -> Result<TelegramResult, Box<dyn std::error::Error>> {
+    let response: TelegramResponseDocument = serde_json::from_str(body)
+        .map_err(|err| Error::Api(format!("Error deserializing response: {}", err)))?;
+    if !response.ok {
+        return Err(response.error.to_send_error(protect).into());
+    }
+    // without a file_id there is nothing to getFile or to send again
+    response
+        .result
+        .and_then(TelegramSentMessage::into_result)
+        .ok_or_else(|| Error::Api("Telegram accepted the file but the response has no file_id for it".into()).into())
+}
+
+/// send the file_id of a file already on the servers, or a URL telegram downloads, nothing is uploaded
+async fn api_send_file_by_id(client: &Client, request: &RequestSendFile<'_>, url: &str, retry: &retry::Policy) -> Result<TelegramResult, Box<dyn std::error::Error>> {
+    let what = format!("Sending the {}", request.file.0.field());
+    let result = retry::run(*retry, &what, || trace::send_text(client, client.post(url).json(request))).await?;
+    parse_sent_document(&result, request.send.protect)
+}
+
+/// a file of an album to upload
+struct AlbumFile {
+    payload: Payload,
+    name: String,
+    mime_type: String,
+    method: SendMethod,
+    send: SendOptions,
+}
+
+impl AlbumFile {
+    async fn try_clone(&self) -> io::Result<AlbumFile> {
+        Ok(AlbumFile {
+            payload: self.payload.try_clone().await?,
+            name: self.name.clone(),
+            mime_type: self.mime_type.clone(),
+            method: self.method,
+            send: self.send.clone(),
+        })
+    }
+}
+
+/// sendMediaGroup, every file is a part of the form that the media array refers to by its name
+/// one progress bar for the whole album, the results are in the order of the files
+async fn api_upload_album(client: &Client, files: Vec<AlbumFile>, send: &SendOptions, url: &str, timeout: Duration, progress: bool) -> Result<Vec<Uploaded>, Box<dyn std::error::Error>> {
+    let count = files.len();
+    let total = files.iter().map(|file| file.payload.size).sum();
+    let pb = transfer_progress_bar("Uploading", &format!("an album of {} files", count), total, progress);
+    let read_error: Arc<Mutex<Option<Error>>> = Arc::new(Mutex::new(None));
+    let mut hashers = Vec::new();
+    let mut media = Vec::new();
+    let mut form = multipart::Form::new().percent_encode_noop();
+    for (index, file) in files.into_iter().enumerate() {
+        let attach = format!("file{}", index);
+        let hasher = Arc::new(Mutex::new(Sha256::new()));
+        let (pb_stream, hasher_stream, read_error_stream, name_stream) = (pb.clone(), hasher.clone(), read_error.clone(), file.name.clone());
+        let size = file.payload.size;
+        let stream = FramedRead::new(file.payload.into_reader(), BytesCodec::new())
+            .map_ok(move |chunk| {
+                pb_stream.inc(chunk.len() as u64);
+                hasher_stream.lock().unwrap().update(&chunk);
+                chunk
+            })
+            .map_err(move |err| {
+                *read_error_stream.lock().unwrap() = Some(Error::io(&name_stream, &err));
+                io::Error::other(err)
+            });
+        trace::part(&attach, &format!("{}, filename={:?}, type={}, {} bytes", file.method.field(), file.name, file.mime_type, size));
+        let part = multipart::Part::stream_with_length(Body::wrap_stream(stream), size).mime_str(&file.mime_type)?;
+        form = form.part(part_name(&attach, &file.name), part);
+        media.push(InputMedia::new(file.method, format!("attach://{}", attach), file.send));
+        hashers.push(hasher);
+    }
+    form = send.apply(form.text("media", serde_json::to_string(&media)?));
+
+    let start = Instant::now();
+    let result = trace::send(client, client.post(url).multipart(form).timeout(timeout)).await;
+    pb.finish_and_clear();
+    trace::phase("upload", start);
+    if let Some(err) = read_error.lock().unwrap().take() {
+        return Err(err.into());
+    }
+    let result = trace::text(result?, Some(timeout)).await?;
+    let sent = parse_sent_album(&result, count, send.protect)?;
+    Ok(sent
+        .into_iter()
+        .zip(hashers)
+        .map(|(r, hasher)| Uploaded {
+            file_id: r.document.file_id,
+            file_unique_id: r.document.file_unique_id,
+            message_id: r.message_id,
+            chat_name: r.chat.display_name(),
+            chat_id: r.chat.id,
+            reply_to: r.reply_to,
+            sha256: hex(&hasher.lock().unwrap().clone().finalize()),
+            file_size: r.document.file_size,
+        })
+        .collect())
+}
+
+/// parse the response of sendMediaGroup, a message for each of the `count` files
+fn parse_sent_album(body: &str, count: usize, protect: bool) -> Result<Vec<TelegramResult>, Box<dyn std::error::Error>> {
+    let response: TelegramResponse<Vec<TelegramSentMessage>> = serde_json::from_str(body)
+        .map_err(|err| Error::Api(format!("Error deserializing response: {}", err)))?;
+    let messages = match response.result {
+        Some(messages) if response.ok => messages,
+        _ => return Err(response.error.to_send_error(protect).into()),
+    };
+    if messages.len() != count {
+        return Err(Error::Api(format!("Telegram sent back {} messages for an album of {} files", messages.len(), count)).into());
+    }
+    messages
+        .into_iter()
+        .map(|message| message.into_result().ok_or_else(|| Error::Api("Telegram accepted the album but the response has no file_id for a file of it".into()).into()))
+        .collect()
+}
+
+/// getFile telegram bot api
+/// https://core.telegram.org/bots/api#getfile
+/// Use this method to get basic information about a file and prepare it for downloading. 
+/// For the moment, bots can download files of up to 20MB in size. On success, a File object is returned. 
+/// The file can then be downloaded via the link https://api.telegram.org/file/bot<token>/<file_path>, 
+/// where <file_path> is taken from the response. 
+/// It is guaranteed that the link will be valid for at least 1 hour. 
+/// When the link expires, a new one can be requested by calling getFile again.
+async fn api_get_file(client: &Client, file_id: &str, url: &str, retry: &retry::Policy) -> Result<FileUploadResult, Box<dyn std::error::Error>> {
+    // an empty file_id is a bug before this point, telegram would only answer with a confusing error
+    if file_id.is_empty() {
+        return Err(Error::Api("No file_id to load the file URL for, the upload didn't return one".into()).into());
+    }
+
+    let request = RequestGetFile {
+        file_id: file_id.to_string(),
+    };
+    let result = retry::run(*retry, "Loading the file URL", || trace::send_text(client, client.post(url).json(&request))).await?;
+
+    // parse the response and get the file_path
+    let response: FileUploadResponse = serde_json::from_str(&result)
+        .map_err(|err| Error::Api(format!("Error deserializing response: {}", err)))?;
+    if !response.ok {
+        let msg = response.error.message(None);
+        // bots can't download files over 20 MB, that's a limit of the file, not a failed call
+        if msg.contains("file is too big") {
+            let msg = format!("{}, bots can only download files up to {}", msg, DecimalBytes(DOWNLOAD_SIZE_LIMIT));
+            return Err(Error::File(msg).into());
+        }
+        return Err(response.error.to_error().into());
+    }
+    match response.result {
+        Some(r) if !r.file_path.is_empty() => Ok(r),
+        _ => Err(Error::Api("File path API error: file_path not found".into()).into()),
+    }
+}
+
+/// call a bot api method with a json body
+/// a failed call becomes an api error with telegram's code and description as the message
+async fn api_call<T: DeserializeOwned>(client: &Client, url: &str, request: &impl Serialize) -> Result<T, Box<dyn std::error::Error>> {
+    api_send(client, url, request, false).await
+}
+
+/// api_call for a send method, `protect` is whether the request has protect_content
+async fn api_send<T: DeserializeOwned>(client: &Client, url: &str, request: &impl Serialize, protect: bool) -> Result<T, Box<dyn std::error::Error>> {
+    let body = trace::send_text(client, client.post(url).json(request)).await?;
+    let response: TelegramResponse<T> = serde_json::from_str(&body)
+        .map_err(|err| Error::Api(format!("Error deserializing response: {}", err)))?;
+    match response.result {
+        Some(result) if response.ok => Ok(result),
+        _ => Err(response.error.to_send_error(protect).into()),
+    }
+}
+
+/// download the file at the url into `file`, with a progress bar named after `name`
+/// a non-empty `file` is resumed with a range request, it starts over when the server doesn't support that
+/// returns the size of the whole file
+async fn api_download_file(client: &Client, url: &str, name: &str, file: std::fs::File, timeout: Duration) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut offset = file.metadata()?.len();
+    let mut response = loop {
+        let mut request = client.get(url).timeout(timeout);
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let response = trace::send(client, request).await?;
+        match response.status() {
+            // the .part file is bigger than the file on the server, it's from something else
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => offset = 0,
+            StatusCode::PARTIAL_CONTENT if offset > 0 => {
+                let content_range = response.headers().get(reqwest::header::CONTENT_RANGE).and_then(|v| v.to_str().ok());
+                if !content_range.is_some_and(|range| range.starts_with(&format!("bytes {}-", offset))) {
+                    return Err(Error::Api(format!("Download failed: the server sent the wrong range ({})", content_range.unwrap_or("none"))).into());
+                }
+                eprintln!("Resuming {} from {}", name, DecimalBytes(offset));
+                break response;
+            }
+            status if status.is_success() => {
+                if offset > 0 {
+                    eprintln!("{}", "The server can't resume downloads, starting over".yellow());
+                    offset = 0;
+                }
+                break response;
+            }
+            status => return Err(Error::Api(format!("Download failed: {}", status)).into()),
+        }
+    };
+    // keep what was already downloaded, or all of it is replaced
+    file.set_len(offset)?;
+    let mut file = File::from_std(file);
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+    let pb = transfer_progress_bar("Downloading", name, offset + response.content_length().unwrap_or(0), true);
+    pb.set_position(offset);
+    let size = async {
+        let mut size = offset;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+            pb.inc(chunk.len() as u64);
+            size += chunk.len() as u64;
+        }
+        file.flush().await?;
+        Ok::<u64, Box<dyn std::error::Error>>(size)
+    }
+    .await;
+    // cleared on failure too, before the error is printed
+    pb.finish_and_clear();
+    size
+}
+
+/// download a URL for --fetch into `dir`, named after its Content-Disposition or the end of its path
+/// over `limit` is an error before the download when the size is known, or as soon as it's reached
+async fn api_fetch_url(client: &Client, url: &str, dir: &Path, limit: u64, timeout: Duration, progress: bool) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let mut response = trace::send(client, client.get(url).timeout(timeout)).await?;
+    if !response.status().is_success() {
+        return Err(Error::File(format!("Fetching {} failed: {}", url, response.status())).into());
+    }
+    let name = response
+        .headers()
+        .get(reqwest::header::CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(disposition_name)
+        .map(|name| sanitize_name(&name))
+        .filter(|name| name != "." && name != "..")
+        .unwrap_or_else(|| url_name(url));
+    let too_big = || Error::File(format!("{} is too big: over the upload limit of {}", url, DecimalBytes(limit)));
+    if response.content_length().is_some_and(|size| size > limit) {
+        return Err(too_big().into());
+    }
+    let path = dir.join(&name);
+    let mut file = File::create(&path).await?;
+    let pb = transfer_progress_bar("Fetching", &name, response.content_length().unwrap_or(0), progress);
+    let res = async {
+        let mut size = 0;
+        while let Some(chunk) = response.chunk().await? {
+            size += chunk.len() as u64;
+            if size > limit {
+                return Err(too_big().into());
+            }
+            file.write_all(&chunk).await?;
+            pb.inc(chunk.len() as u64);
+        }
+        file.flush().await?;
+        Ok::<(), Box<dyn std::error::Error>>(())
+    }
+    .await;
+    pb.finish_and_clear();
+    res.map(|_| path)
+}
+
+/// checksum of the file recorded at upload time, from the history or a NAME.sha256 sidecar in sha256sum format
+fn recorded_sha256(output: &str, uploaded: Option<&history::Entry>) -> Option<String> {
+    if let Some(sha256) = uploaded.and_then(|entry| entry.sha256.clone()) {
+        return Some(sha256);
+    }
+    let sidecar = std::fs::read_to_string(format!("{}.sha256", output)).ok()?;
+    sidecar.split_whitespace().next().map(|hash| hash.to_lowercase())
+}
+
+/// spinner on stderr, so stdout only gets the results
+/// nothing is drawn when stderr is not a terminal or the output is not for humans
+fn start_spinner(msg: &str, mode: &OutputMode) -> Option<Spinner> {
+    if !mode.is_human() || !std::io::stderr().is_terminal() || CONCURRENT.load(Ordering::Relaxed) {
+        return None;
+    }
+    Some(Spinner::with_stream(Spinners::Dots12, msg.into(), Stream::Stderr))
+}
+
+/// stop the spinner and clear its line
+fn stop_spinner(sp: Option<Spinner>) {
+    if let Some(mut sp) = sp {
+        sp.stop();
+        eprint!("\r\x1b[2K");
+    }
+}
+
+/// passphrase from the file (first line) or prompted without echo
+fn read_passphrase(passphrase_file: Option<&str>, confirm: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let passphrase = match passphrase_file {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path).map_err(|err| Error::File(format!("{}: {}", path, err)))?;
+            contents.lines().next().unwrap_or_default().to_string()
+        }
+        None => {
+            let passphrase = rpassword::prompt_password("Passphrase: ")?;
+            if confirm && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+                return Err(Error::Usage("Passphrases don't match".into()).into());
+            }
+            passphrase
+        }
+    };
+    if passphrase.is_empty() {
+        return Err(Error::Usage("Passphrase is empty".into()).into());
+    }
+    Ok(passphrase)
+}
+
+/// the size of a file or directory on disk, none for stdin
+fn local_size(filename: &Path) -> Option<u64> {
+    if filename.as_os_str() == STDIN_FILENAME {
+        return None;
+    }
+    let metadata = std::fs::metadata(filename).ok()?;
+    if !metadata.is_dir() {
+        return Some(metadata.len());
+    }
+    let files = walkdir::WalkDir::new(filename).into_iter().filter_map(Result::ok);
+    Some(files.filter_map(|entry| entry.metadata().ok()).filter(|m| m.is_file()).map(|m| m.len()).sum())
+}
+
+/// make sure the bot can send documents to cfg.chat_id before a big upload
+/// private chats only have to exist, in groups and channels the bot has to be a member that may post
+fn precheck_chat(rt: &Runtime, client: &Client, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let chat_id = cfg.chat_id.as_str();
+    let request = RequestGetChat { chat_id };
+    let chat = rt.block_on(api_call::<TelegramChat>(client, &cfg.api_url(API_GET_CHAT), &request)).map_err(|err| {
+        if error::exit_code(&*err) == error::EXIT_CHAT_NOT_FOUND {
+            Error::ChatNotFound(format!("Chat {} not found, {}", chat_id, CHAT_NOT_FOUND_HINT)).into()
+        } else {
+            err
+        }
+    })?;
+    if chat.kind.as_deref() == Some("private") {
+        return Ok(());
+    }
+    // the bot's id is the part of the token before the colon
+    let bot_id = match cfg.bot_token.split_once(':').and_then(|(id, _)| id.parse().ok()) {
+        Some(id) => id,
+        None => rt.block_on(api_call::<TelegramUser>(client, &cfg.api_url(API_GET_ME), &serde_json::json!({})))?.id,
+    };
+    let request = RequestGetChatMember { chat_id, user_id: bot_id };
+    let member = rt.block_on(api_call::<TelegramChatMember>(client, &cfg.api_url(API_GET_CHAT_MEMBER), &request))?;
+    let channel = chat.kind.as_deref() == Some("channel");
+    // not being in the chat is the same as telegram's 403, the missing rights are api errors
+    match member.status.as_str() {
+        "left" => return Err(Error::Forbidden(format!("The bot is not a member of chat {}, add it first", chat_id)).into()),
+        "kicked" => return Err(Error::Forbidden(format!("The bot was removed from chat {}, add it again", chat_id)).into()),
+        _ => {}
+    }
+    let problem = match member.status.as_str() {
+        "restricted" if member.is_member == Some(false) => Some(format!("The bot is not a member of chat {}, add it first", chat_id)),
+        "restricted" if member.can_send_documents == Some(false) => {
+            Some(format!("The bot is not allowed to send documents in chat {}", chat_id))
+        }
+        "member" if channel => Some(format!("The bot can't post in channel {}, make it an admin that may post messages", chat_id)),
+        "administrator" if channel && member.can_post_messages == Some(false) => {
+            Some(format!("The bot can't post in channel {}, give it the right to post messages", chat_id))
+        }
+        _ => None,
+    };
+    match problem {
+        Some(problem) => Err(Error::Api(problem).into()),
+        None => Ok(()),
+    }
+}
+
+/// the token works and the bot can see every chat, for --dry-run --verify
+/// only getMe and getChat are called, which send nothing
+fn verify_chats(rt: &Runtime, client: &Client, cfg: &Config, chats: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let bot = rt.block_on(api_call::<TelegramUser>(client, &cfg.api_url(API_GET_ME), &serde_json::json!({})))?;
+    let mut names = Vec::new();
+    for chat_id in chats {
+        let request = RequestGetChat { chat_id };
+        let chat = rt.block_on(api_call::<TelegramChat>(client, &cfg.api_url(API_GET_CHAT), &request)).map_err(|err| {
+            if error::exit_code(&*err) == error::EXIT_CHAT_NOT_FOUND {
+                Error::ChatNotFound(format!("Chat {} not found, {}", chat_id, CHAT_NOT_FOUND_HINT)).into()
+            } else {
+                err
+            }
+        })?;
+        names.push(match chat.display_name() {
+            Some(name) => format!("{} ({})", name, chat.id),
+            None => chat.id.to_string(),
+        });
+    }
+    let bot = bot.username.map(|username| format!("@{}", username)).unwrap_or(bot.first_name);
+    Ok(format!("The bot {} can see {}", bot, names.join(", ")))
+}
+
+/// upload a single file and get its file id and download url
+/// `relative` is the path of a file found by --recursive, it goes into the caption or with --prefix-name the name
+/// `chats` are the other chats it's sent to after the upload to cfg.chat_id
+#[allow(clippy::too_many_arguments)]
+fn upload_file(rt: &Runtime, client: &Client, cfg: &Config, args: &Args, passphrase: Option<&str>, filename: &Path, relative: Option<&str>, chats: &[String]) -> Result<UploadResult, Box<dyn std::error::Error>> {
+    if let Some(url) = http_url(filename) {
+        return upload_url(rt, client, cfg, args, passphrase, url, chats);
+    }
+    let mut name = match relative {
+        Some(relative) if args.prefix_name => sanitize_name(&relative.replace('/', "_")),
+        _ => upload_name(filename, args.name.as_deref()),
+    };
+
+    // a dangling link would only be "not found", which is confusing when the link is right there
+    if filename.is_symlink() {
+        match std::fs::canonicalize(filename) {
+            Ok(target) if args.verbose > 0 => eprintln!("{} is a link to {}", filename.display(), target.display()),
+            Ok(_) => {}
+            Err(_) => {
+                let target = std::fs::read_link(filename).map(|target| target.display().to_string()).unwrap_or_default();
+                return Err(Error::File(format!("{} is a link to {} which doesn't exist", filename.display(), target)).into());
+            }
+        }
+    }
+
+    // ===== ARCHIVE DIRECTORY
+    // the temp archive lives until the end of this function and is removed on drop
+    let mut filename = filename.to_path_buf();
+    let archive = if filename.as_os_str() != STDIN_FILENAME && filename.is_dir() {
+        let format = args.archive_format;
+        let tmp = archive::archive_dir(Path::new(&filename), format, &args.walk())?;
+        if args.name.is_none() {
+            name = format!("{}.{}", name, format.extension());
+        }
+        let archive_size = tmp.as_file().metadata()?.len();
+        eprintln!("Archived {} into {} ({})", filename.display(), name, DecimalBytes(archive_size));
+        filename = tmp.path().to_path_buf();
+        Some(tmp)
+    } else {
+        None
+    };
+
+    // ===== COMPRESS
+    // same as the archive, the compressed temp file is removed on drop
+    let compressed = match args.compress {
+        Some(Compression::Auto) if compress::is_compressed_name(&name) => {
+            if args.verbose > 0 {
+                eprintln!("{} is already compressed, sending as is", name);
+            }
+            None
+        }
+        Some(method) => {
+            if compress::is_compressed_name(&name) {
+                let msg = format!("Warning: {} looks already compressed, compressing it again won't help much", name);
+                eprintln!("{}", msg.yellow());
+            }
+            let (tmp, original_size) = if filename.as_os_str() == STDIN_FILENAME {
+                check_stdin()?;
+                (compress::compress(std::io::stdin().lock(), method)?, None)
+            } else {
+                let file = std::fs::File::open(&filename).map_err(|err| Error::io(filename.display(), &err))?;
+                let size = file.metadata().map_err(|err| Error::io(filename.display(), &err))?.len();
+                (compress::compress(file, method)?, Some(size))
+            };
+            // a --name that already has the extension is kept as is
+            let extension = format!(".{}", method.extension());
+            if !name.ends_with(&extension) {
+                name.push_str(&extension);
+            }
+            let compressed_size = tmp.as_file().metadata()?.len();
+            match original_size {
+                Some(size) => eprintln!(
+                    "Compressed {} from {} to {}",
+                    name,
+                    DecimalBytes(size),
+                    DecimalBytes(compressed_size)
+                ),
+                None => eprintln!("Compressed stdin to {}", DecimalBytes(compressed_size)),
+            }
+            filename = tmp.path().to_path_buf();
+            Some(tmp)
+        }
+        None => None,
+    };
+
+    // ===== ENCRYPT
+    // encrypted last, compressing ciphertext is pointless
+    let encrypted = match passphrase {
+        Some(passphrase) => {
+            let tmp = tempfile::NamedTempFile::new()?;
+            let writer = std::io::BufWriter::new(tmp.reopen()?);
+            if filename.as_os_str() == STDIN_FILENAME {
+                check_stdin()?;
+                crypto::encrypt(std::io::stdin().lock(), writer, passphrase)?;
+            } else {
+                let file = std::fs::File::open(&filename).map_err(|err| Error::io(filename.display(), &err))?;
+                crypto::encrypt(file, writer, passphrase)?;
+            }
+            let encrypted_name = format!("{}.{}", name, crypto::ENCRYPTED_EXTENSION);
+            eprintln!("Encrypted {} with AES-256-GCM, decrypt it with `teledrop decrypt {}`", name, encrypted_name);
+            name = encrypted_name;
+            filename = tmp.path().to_path_buf();
+            Some(tmp)
+        }
+        None => None,
+    };
+
+    // ===== UPLOAD FILE
+    let limit = upload_limit(cfg, args);
+    if args.split && filename.as_os_str() != STDIN_FILENAME {
+        let file_size = std::fs::metadata(&filename).map_err(|err| Error::io(filename.display(), &err))?.len();
+        if file_size > limit || args.split_size.is_some_and(|size| file_size > size) {
+            // leave a little room under the limit by default
+            let part_size = args.split_size.unwrap_or(limit - limit / 100);
+            if part_size == 0 || part_size > limit {
+                let msg = format!("--split-size must be between 1 byte and the upload limit of {}", DecimalBytes(limit));
+                return Err(Error::Usage(msg).into());
+            }
+            return upload_split(rt, client, cfg, args, &filename, &name, file_size, part_size);
+        }
+    }
+    let start = Instant::now();
+    let payload = tracing::info_span!("read", file = %filename.display()).in_scope(|| rt.block_on(open_payload(&filename, limit, args.allow_empty)))?;
+    trace::phase("read", start);
+    let file_size = payload.size;
+    let send = file_caption(args, args.send.clone(), &name, &filename, relative, &payload)?;
+    let (mime_type, mime_source) = match &args.mime {
+        Some(mime) => (mime.clone(), "--mime"),
+        None => detect_mime(&name, &filename, &payload)?,
+    };
+    let method = send_method(args, &mime_type, file_size);
+    if args.verbose > 0 {
+        eprintln!("MIME type: {} (from {})", mime_type, mime_source);
+        if !method.is_document() {
+            eprintln!("Sending as {}, --as-document keeps it as it is", method.describe());
+        }
+    }
+
+    // ===== DEDUPE
+    // a file uploaded before is sent again by its file_id, nothing is uploaded then
+    if !args.no_dedupe {
+        if let Some(mut result) = send_uploaded_before(rt, client, cfg, args, &filename, &payload, &name, method, &send)? {
+            send_to_chats(rt, client, cfg, chats, &mut result, &send, &args.output);
+            add_download_url(rt, client, cfg, &mut result, &args.output)?;
+            return Ok(result);
+        }
+    }
+
+    let thumb = match &args.thumb {
+        Some(_) if method == SendMethod::Photo => {
+            output::print_warning(&format!("Warning: photos have no thumbnail, --thumb is left out for {} (--as-document keeps it)", name));
+            None
+        }
+        Some(path) => Some(std::fs::read(path).map_err(|err| Error::io(path.display(), &err))?),
+        None => None,
+    };
+
+    cfg.pace(&cfg.chat_id);
+    let started = std::time::Instant::now();
+    let url = cfg.api_url_send(method);
+    let what = format!("Uploading {}", name);
+    let upload_res = {
+        // every attempt sends the payload from its start again
+        let (payload, name, mime_type, send, thumb, url) = (&payload, name.as_str(), mime_type.as_str(), &send, thumb.as_deref(), url.as_str());
+        retry::run(cfg.retry_policy(), &what, move || async move {
+            let payload = payload.try_clone().await?;
+            api_upload_document(client, payload, name, mime_type, method, send, thumb, url, cfg.transfer_timeout(file_size), args.output.is_human()).await
+        })
+    };
+    let uploaded = tracing::info_span!("upload", file = %name, size = file_size).in_scope(|| rt.block_on(upload_res));
+    // telegram only says what's wrong, not that it's about --thumb
+    let uploaded = match (uploaded, &args.thumb) {
+        (Err(err), Some(path)) if thumb.is_some() && err.to_string().to_lowercase().contains("thumb") => {
+            return Err(Error::context(&*err, &format!("Telegram refused the thumbnail {}", path.display())).into());
+        }
+        (uploaded, _) => uploaded?,
+    };
+    let duration = started.elapsed();
+    drop(encrypted);
+    drop(compressed);
+    drop(archive);
+    let mut result = UploadResult {
+        name,
+        size: file_size,
+        sha256: Some(uploaded.sha256),
+        file_id: uploaded.file_id,
+        file_unique_id: uploaded.file_unique_id,
+        sent_as: method,
+        silent: send.silent,
+        protected: send.protect,
+        message_id: uploaded.message_id,
+        chat_id: uploaded.chat_id,
+        chat_name: uploaded.chat_name,
+        reply_to: uploaded.reply_to,
+        duration_secs: duration.as_secs_f64(),
+        url: None,
+        parts: Vec::new(),
+        broadcast: Vec::new(),
+        broadcast_failed: Vec::new(),
+        reported_size: uploaded.file_size,
+    };
+
+    send_to_chats(rt, client, cfg, chats, &mut result, &send, &args.output);
+    add_download_url(rt, client, cfg, &mut result, &args.output)?;
+    Ok(result)
+}
+
+/// the caption of a file: --caption-template, the path of a file of --recursive, and the checksum
+fn file_caption(args: &Args, mut send: SendOptions, name: &str, filename: &Path, relative: Option<&str>, payload: &Payload) -> Result<SendOptions, Box<dyn std::error::Error>> {
+    if let Some(template) = &args.caption_template {
+        send.caption = Some(render_caption(template, name, filename, payload)?);
+    }
+    if let (Some(relative), false, None) = (relative, args.prefix_name, &send.caption) {
+        send.caption = Some(relative.to_string());
+    }
+    // the caption is sent before the file, so this needs its own pass over the file
+    if args.checksum_caption {
+        let checksum = format!("SHA-256: {}", payload_digest::<Sha256>(filename, payload)?);
+        let caption = match send.caption.take() {
+            Some(caption) => format!("{}\n{}", caption, checksum),
+            None => checksum,
+        };
+        validate_caption(&caption).map_err(Error::Usage)?;
+        send.caption = Some(caption);
+    }
+    Ok(send)
+}
+
+/// send a URL as the document, telegram downloads it itself
+/// with --fetch it's downloaded here and uploaded instead when telegram can't, or an option needs the bytes
+fn upload_url(rt: &Runtime, client: &Client, cfg: &Config, args: &Args, passphrase: Option<&str>, url: &str, chats: &[String]) -> Result<UploadResult, Box<dyn std::error::Error>> {
+    let fetch = || -> Result<UploadResult, Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        // --split takes files over the limit
+        let limit = if args.split { u64::MAX } else { upload_limit(cfg, args) };
+        let path = rt.block_on(api_fetch_url(client, url, dir.path(), limit, cfg.transfer_timeout(limit), args.output.is_human()))?;
+        upload_file(rt, client, cfg, args, passphrase, &path, None, chats)
+    };
+    match (url_needs_file(args), args.fetch) {
+        (Some(option), false) => return Err(Error::Usage(format!("{} needs the file itself, --fetch downloads {} first", option, url)).into()),
+        (Some(_), true) => return fetch(),
+        (None, _) => {}
+    }
+
+    // the type can only be told by the extension, telegram would refuse a URL that isn't one
+    let mime_type = mime_guess::from_path(url_name(url)).first().map(|m| m.essence_str().to_string()).unwrap_or_default();
+    let method = send_method(args, &mime_type, 0);
+    let request = RequestSendFile {
+        chat_id: &cfg.chat_id,
+        file: FileField(method, url),
+        send: &args.send,
+    };
+    cfg.pace(&cfg.chat_id);
+    let started = std::time::Instant::now();
+    let sp = start_spinner(&format!("Sending {}...", url), &args.output);
+    let res = rt.block_on(api_send_file_by_id(client, &request, &cfg.api_url(method.path()), &cfg.retry_policy()));
+    stop_spinner(sp);
+    // telegram only fetches some types by URL, and documents up to 20 MB
+    let sent = match res {
+        Ok(sent) => sent,
+        Err(err) if error::exit_code(&*err) == error::EXIT_API && args.fetch => {
+            eprintln!("Telegram couldn't send {} ({}), fetching it to upload it instead", url, err);
+            return fetch();
+        }
+        Err(err) if error::exit_code(&*err) == error::EXIT_API => {
+            return Err(Error::Api(format!("{} (--fetch downloads it and uploads it instead)", err)).into());
+        }
+        Err(err) => return Err(err),
+    };
+    let mut result = UploadResult {
+        name: sent.document.file_name.unwrap_or_else(|| url_name(url)),
+        size: sent.document.file_size.unwrap_or_default(),
+        // the bytes never passed through here
+        sha256: None,
+        file_id: sent.document.file_id,
+        file_unique_id: sent.document.file_unique_id,
+        sent_as: method,
+        silent: args.send.silent,
+        protected: args.send.protect,
+        message_id: sent.message_id,
+        chat_name: sent.chat.display_name(),
+        reply_to: sent.reply_to,
+        chat_id: sent.chat.id,
+        duration_secs: started.elapsed().as_secs_f64(),
+        url: None,
+        parts: Vec::new(),
+        broadcast: Vec::new(),
+        broadcast_failed: Vec::new(),
+        reported_size: sent.document.file_size,
+    };
+    send_to_chats(rt, client, cfg, chats, &mut result, &args.send, &args.output);
+    add_download_url(rt, client, cfg, &mut result, &args.output)?;
+    Ok(result)
+}
+
+/// what uploading the file would do, for --dry-run
+/// the same local work as upload_file without sending anything, a directory isn't packed and nothing is compressed
+/// or encrypted, so those leave the size and type open
+fn plan_file(rt: &Runtime, cfg: &Config, args: &Args, filename: &Path, relative: Option<&str>) -> Result<output::Plan, Box<dyn std::error::Error>> {
+    let mut name = match relative {
+        Some(relative) if args.prefix_name => sanitize_name(&relative.replace('/', "_")),
+        _ => upload_name(filename, args.name.as_deref()),
+    };
+    let file = relative.map(str::to_string).unwrap_or_else(|| filename.display().to_string());
+    let mut plan = output::Plan::new(file, cfg.chat_id.clone());
+    if let Some(url) = http_url(filename) {
+        plan.name = Some(args.name.clone().unwrap_or_else(|| url_name(url)));
+        plan.caption = args.send.caption.clone();
+        plan.note = Some(match (url_needs_file(args), args.fetch) {
+            (Some(option), false) => return Err(Error::Usage(format!("{} needs the file itself, --fetch downloads {} first", option, url)).into()),
+            (Some(option), true) => format!("fetched here for {} and uploaded", option),
+            (None, true) => "sent by its URL, fetched here and uploaded if Telegram can't".to_string(),
+            (None, false) => "sent by its URL, Telegram fetches it".to_string(),
+        });
+        return Ok(plan);
+    }
+    plan.mime_type = args.mime.clone();
+    if filename.is_symlink() && std::fs::canonicalize(filename).is_err() {
+        let target = std::fs::read_link(filename).map(|target| target.display().to_string()).unwrap_or_default();
+        return Err(Error::File(format!("{} is a link to {} which doesn't exist", filename.display(), target)).into());
+    }
+    let stdin = filename.as_os_str() == STDIN_FILENAME;
+    let mut notes = Vec::new();
+    // the bytes sent are the file itself, so its size, type and checksum are the ones of the upload
+    let mut plain = !stdin;
+    if !stdin && filename.is_dir() {
+        let files = archive::files(filename, &args.walk()).map_err(|err| Error::File(err.to_string()))?;
+        let size: u64 = files.iter().filter_map(|(file, _)| local_size(file)).sum();
+        notes.push(format!("archive of {} files ({})", files.len(), DecimalBytes(size)));
+        if args.name.is_none() {
+            name = format!("{}.{}", name, args.archive_format.extension());
+        }
+        plain = false;
+    }
+    match args.compress {
+        Some(Compression::Auto) if compress::is_compressed_name(&name) => {}
+        Some(method) => {
+            let extension = format!(".{}", method.extension());
+            if !name.ends_with(&extension) {
+                name.push_str(&extension);
+            }
+            notes.push(format!("compressed with {}", method.extension()));
+            plain = false;
+        }
+        None => {}
+    }
+    if args.encrypt {
+        name = format!("{}.{}", name, crypto::ENCRYPTED_EXTENSION);
+        notes.push("encrypted".to_string());
+        plain = false;
+    }
+    if stdin {
+        notes.push("read from stdin when uploading".to_string());
+    }
+
+    let limit = upload_limit(cfg, args);
+    let mut send = args.send.clone();
+    if plain {
+        let file_size = std::fs::metadata(filename).map_err(|err| Error::io(filename.display(), &err))?.len();
+        plan.size = Some(file_size);
+        if args.split && (file_size > limit || args.split_size.is_some_and(|size| file_size > size)) {
+            let part_size = args.split_size.unwrap_or(limit - limit / 100);
+            if part_size == 0 || part_size > limit {
+                let msg = format!("--split-size must be between 1 byte and the upload limit of {}", DecimalBytes(limit));
+                return Err(Error::Usage(msg).into());
+            }
+            plan.action = output::Action::Split;
+            notes.push(format!("{} parts of up to {}", file_size.div_ceil(part_size), DecimalBytes(part_size)));
+        } else {
+            let payload = rt.block_on(open_payload(filename, limit, args.allow_empty))?;
+            if let Some(template) = &args.caption_template {
+                send.caption = Some(render_caption(template, &name, filename, &payload)?);
+            }
+            if plan.mime_type.is_none() {
+                plan.mime_type = Some(detect_mime(&name, filename, &payload)?.0);
+            }
+            if args.checksum_caption {
+                let checksum = format!("SHA-256: {}", payload_digest::<Sha256>(filename, &payload)?);
+                send.caption = Some(match send.caption.take() {
+                    Some(caption) => format!("{}\n{}", caption, checksum),
+                    None => checksum,
+                });
+            }
+            if !args.no_dedupe && args.thumb.is_none() {
+                let method = send_method(args, plan.mime_type.as_deref().unwrap_or_default(), file_size);
+                if let Some(entry) = uploaded_before(filename, &payload)?.filter(|entry| entry.sent_as == method) {
+                    if args.dedupe {
+                        plan.action = output::Action::Resend;
+                        notes.push(format!("sent again by the file_id of #{} ({})", entry.id, entry.name));
+                    } else {
+                        notes.push(format!("uploaded before as #{} ({}), --dedupe sends that again", entry.id, entry.name));
+                    }
+                }
+            }
+        }
+    }
+    if let (Some(relative), false, None) = (relative, args.prefix_name, &send.caption) {
+        send.caption = Some(relative.to_string());
+    }
+    if let Some(caption) = &send.caption {
+        validate_caption(caption).map_err(Error::Usage)?;
+    }
+    if plan.action == output::Action::Upload {
+        let method = send_method(args, plan.mime_type.as_deref().unwrap_or_default(), plan.size.unwrap_or_default());
+        if !method.is_document() {
+            notes.push(format!("sent as {}", method.describe()));
+        }
+        match &args.thumb {
+            Some(_) if method == SendMethod::Photo => notes.push("photos have no thumbnail".to_string()),
+            Some(thumb) => notes.push(format!("with the thumbnail {}", thumb.display())),
+            None => {}
+        }
+    }
+    plan.name = Some(name);
+    plan.caption = send.caption;
+    plan.note = Some(notes.join(", ")).filter(|note| !note.is_empty());
+    Ok(plan)
+}
+
+/// send an uploaded file to more chats by its file_id, so it's only uploaded once
+/// a chat that fails doesn't stop the others, it's added to broadcast_failed
+fn send_to_chats(rt: &Runtime, client: &Client, cfg: &Config, chats: &[String], result: &mut UploadResult, send: &SendOptions, mode: &OutputMode) {
+    if chats.is_empty() {
+        return;
+    }
+    let sp = start_spinner(&format!("Sending to {} more chats...", chats.len()), mode);
+    let send = &send.for_other_chats();
+    for chat_id in chats {
+        // a file_id only goes with the method it was sent with
+        let request = RequestSendFile {
+            chat_id,
+            file: FileField(result.sent_as, &result.file_id),
+            send,
+        };
+        cfg.pace(chat_id);
+        match rt.block_on(api_send_file_by_id(client, &request, &cfg.api_url(result.sent_as.path()), &cfg.retry_policy())) {
+            Ok(sent) => result.broadcast.push(SentMessage {
+                chat_id: sent.chat.id,
+                message_id: sent.message_id,
+            }),
+            Err(err) => result.broadcast_failed.push((chat_id.clone(), Error::from_boxed(&*err))),
+        }
+    }
+    stop_spinner(sp);
+}
+
+/// the album a file can go into with --album and the MIME type it's uploaded with
+/// none for what is sent on its own: stdin, URLs, directories, files that are compressed, encrypted, or too big or
+/// empty to be sent as they are, and animations
+fn album_file(rt: &Runtime, cfg: &Config, args: &Args, path: &Path) -> Option<(&'static str, String, SendMethod)> {
+    if path.as_os_str() == STDIN_FILENAME || http_url(path).is_some() || !path.is_file() || args.compress.is_some() || args.encrypt {
+        return None;
+    }
+    let limit = upload_limit(cfg, args);
+    let payload = rt.block_on(open_payload(path, limit, false)).ok()?;
+    let name = upload_name(path, args.name.as_deref());
+    let mime_type = match &args.mime {
+        Some(mime) => mime.clone(),
+        None => detect_mime(&name, path, &payload).ok()?.0,
+    };
+    let method = send_method(args, &mime_type, payload.size);
+    Some((method.album()?, mime_type, method))
+}
+
+/// upload the files as one album, with a result for each in the order of the files
+/// the --caption only goes to the first file, the clients show it as the caption of the album
+fn upload_album(rt: &Runtime, client: &Client, cfg: &Config, args: &Args, files: &[(&ListedFile, String, SendMethod)], chats: &[String]) -> Result<Vec<UploadResult>, Box<dyn std::error::Error>> {
+    let limit = upload_limit(cfg, args);
+    let mut album = Vec::new();
+    for (index, (ListedFile { path, relative }, mime_type, method)) in files.iter().enumerate() {
+        let name = match relative {
+            Some(relative) if args.prefix_name => sanitize_name(&relative.replace('/', "_")),
+            _ => upload_name(path, args.name.as_deref()),
+        };
+        let payload = rt.block_on(open_payload(path, limit, false))?;
+        let send = SendOptions {
+            caption: args.send.caption.clone().filter(|_| index == 0),
+            ..args.send.clone()
+        };
+        let send = file_caption(args, send, &name, path, relative.as_deref(), &payload)?;
+        album.push(AlbumFile {
+            payload,
+            name,
+            mime_type: mime_type.clone(),
+            method: *method,
+            send,
+        });
+    }
+    let total = album.iter().map(|file| file.payload.size).sum();
+    let sends: Vec<SendOptions> = album.iter().map(|file| file.send.clone()).collect();
+
+    cfg.pace(&cfg.chat_id);
+    let started = std::time::Instant::now();
+    let url = format!("{}?chat_id={}", cfg.api_url(API_SEND_MEDIA_GROUP), cfg.chat_id);
+    let what = format!("Uploading an album of {} files", album.len());
+    let upload_res = {
+        let (album, url) = (&album, url.as_str());
+        retry::run(cfg.retry_policy(), &what, move || async move {
+            let mut files = Vec::new();
+            for file in album {
+                files.push(file.try_clone().await?);
+            }
+            api_upload_album(client, files, &args.send.without_caption(), url, cfg.transfer_timeout(total), args.output.is_human()).await
+        })
+    };
+    let uploaded = tracing::info_span!("upload", album = album.len(), size = total).in_scope(|| rt.block_on(upload_res))?;
+    eprintln!("Sent {} files as an album", album.len());
+    let duration = started.elapsed();
+    let mut results: Vec<UploadResult> = album
+        .into_iter()
+        .zip(uploaded)
+        .map(|(file, uploaded)| UploadResult {
+            name: file.name,
+            size: file.payload.size,
+            sha256: Some(uploaded.sha256),
+            file_id: uploaded.file_id,
+            file_unique_id: uploaded.file_unique_id,
+            sent_as: file.method,
+            silent: args.send.silent,
+            protected: args.send.protect,
+            message_id: uploaded.message_id,
+            chat_id: uploaded.chat_id,
+            chat_name: uploaded.chat_name,
+            reply_to: uploaded.reply_to,
+            // the files went up together, each took as long as the album
+            duration_secs: duration.as_secs_f64(),
+            url: None,
+            parts: Vec::new(),
+            broadcast: Vec::new(),
+            broadcast_failed: Vec::new(),
+            reported_size: uploaded.file_size,
+        })
+        .collect();
+    send_album_to_chats(rt, client, cfg, chats, &mut results, &sends, &args.output);
+    for result in &mut results {
+        add_download_url(rt, client, cfg, result, &args.output)?;
+    }
+    Ok(results)
+}
+
+/// send an uploaded album to more chats by the file_ids, it stays an album there
+fn send_album_to_chats(rt: &Runtime, client: &Client, cfg: &Config, chats: &[String], results: &mut [UploadResult], sends: &[SendOptions], mode: &OutputMode) {
+    if chats.is_empty() {
+        return;
+    }
+    let sp = start_spinner(&format!("Sending the album to {} more chats...", chats.len()), mode);
+    let url = cfg.api_url(API_SEND_MEDIA_GROUP);
+    // every file has the options of the album, only the captions differ
+    let send = sends.first().map(|send| send.without_caption().for_other_chats()).unwrap_or_default();
+    for chat_id in chats {
+        let request = RequestSendMediaGroup {
+            chat_id,
+            media: results.iter().zip(sends).map(|(result, send)| InputMedia::new(result.sent_as, result.file_id.clone(), send.clone())).collect(),
+            send: send.clone(),
+        };
+        cfg.pace(chat_id);
+        let res = rt.block_on(retry::run(cfg.retry_policy(), "Sending the album", || api_send::<Vec<TelegramMessage>>(client, &url, &request, request.send.protect)));
+        match res {
+            Ok(messages) if messages.len() == results.len() => {
+                for (result, message) in results.iter_mut().zip(messages) {
+                    result.broadcast.push(SentMessage {
+                        chat_id: message.chat.id,
+                        message_id: message.message_id,
+                    });
+                }
+            }
+            Ok(messages) => {
+                let err = Error::Api(format!("Telegram sent back {} messages for an album of {} files", messages.len(), results.len()));
+                for result in results.iter_mut() {
+                    result.broadcast_failed.push((chat_id.clone(), Error::from_boxed(&err)));
+                }
+            }
+            Err(err) => {
+                for result in results.iter_mut() {
+                    result.broadcast_failed.push((chat_id.clone(), Error::from_boxed(&*err)));
+                }
+            }
+        }
+    }
+    stop_spinner(sp);
+}
+
+/// ask on the terminal, the answer is yes unless it starts with n
+fn confirm(question: &str) -> bool {
+    eprint!("{} [Y/n] ", question);
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    !answer.trim().to_lowercase().starts_with('n')
+}
+
+/// the latest upload in the history with the same checksum
+fn uploaded_before(filename: &Path, payload: &Payload) -> Result<Option<history::Entry>, Box<dyn std::error::Error>> {
+    // only files of the same size need to be hashed
+    let entries = history::load().unwrap_or_default();
+    let same_size: Vec<&history::Entry> = entries.iter().filter(|e| e.size == payload.size && e.sha256.is_some()).collect();
+    if same_size.is_empty() {
+        return Ok(None);
+    }
+    let sha256 = payload_digest::<Sha256>(filename, payload)?;
+    Ok(same_size.into_iter().rev().find(|e| e.sha256.as_deref() == Some(sha256.as_str())).cloned())
+}
+
+/// send the file by the file_id of an earlier upload with the same checksum
+/// with --dedupe that happens without asking, otherwise only when the user agrees on a terminal
+/// there is no asking while --jobs uploads several files at once
+/// none when there is no such upload, it was sent with another method, or telegram doesn't accept the file_id anymore
+#[allow(clippy::too_many_arguments)]
+fn send_uploaded_before(rt: &Runtime, client: &Client, cfg: &Config, args: &Args, filename: &Path, payload: &Payload, name: &str, method: SendMethod, send: &SendOptions) -> Result<Option<UploadResult>, Box<dyn std::error::Error>> {
+    let interactive = filename.as_os_str() != STDIN_FILENAME
+        && std::io::stdin().is_terminal()
+        && std::io::stderr().is_terminal()
+        && !CONCURRENT.load(Ordering::Relaxed);
+    // a thumbnail only goes up with the file
+    if (!args.dedupe && !interactive) || args.thumb.is_some() {
+        return Ok(None);
+    }
+    // e.g. a photo can't be sent again as a document, telegram recompressed it
+    let Some(entry) = uploaded_before(filename, payload)?.filter(|entry| entry.sent_as == method) else {
+        return Ok(None);
+    };
+    let question = format!("{} was uploaded before as {} (#{}), send that again instead?", name, entry.name, entry.id);
+    if !args.dedupe && !confirm(&question) {
+        return Ok(None);
+    }
+
+    let request = RequestSendFile {
+        chat_id: &cfg.chat_id,
+        file: FileField(method, &entry.file_id),
+        send,
+    };
+    cfg.pace(&cfg.chat_id);
+    let started = std::time::Instant::now();
+    let sent = match rt.block_on(api_send_file_by_id(client, &request, &cfg.api_url(method.path()), &cfg.retry_policy())) {
+        Ok(sent) => sent,
+        Err(err) => {
+            output::print_warning(&format!("Sending the earlier upload #{} failed ({}), uploading instead", entry.id, err));
+            return Ok(None);
+        }
+    };
+    eprintln!("Sent {} from the earlier upload #{}, nothing was uploaded", name, entry.id);
+    Ok(Some(UploadResult {
+        name: name.to_string(),
+        size: payload.size,
+        sha256: entry.sha256.clone(),
+        file_id: sent.document.file_id,
+        file_unique_id: sent.document.file_unique_id,
+        sent_as: method,
+        silent: send.silent,
+        protected: send.protect,
+        message_id: sent.message_id,
+        chat_name: sent.chat.display_name(),
+        reply_to: sent.reply_to,
+        chat_id: sent.chat.id,
+        duration_secs: started.elapsed().as_secs_f64(),
+        url: None,
+        parts: Vec::new(),
+        broadcast: Vec::new(),
+        broadcast_failed: Vec::new(),
+        reported_size: sent.document.file_size,
+    }))
+}
+
+/// get the download url of a sent file, with a spinner
+fn add_download_url(rt: &Runtime, client: &Client, cfg: &Config, result: &mut UploadResult, mode: &OutputMode) -> Result<(), Box<dyn std::error::Error>> {
+    // getFile refuses files over the download limit, so there is no url to get
+    // a self-hosted server has no such limit
+    if result.size > DOWNLOAD_SIZE_LIMIT && !cfg.is_local_server() {
+        let msg = format!(
+            "No download URL: bots can only download files up to {}",
+            DecimalBytes(DOWNLOAD_SIZE_LIMIT)
+        );
+        eprintln!("{}", msg.yellow());
+        return Ok(());
+    }
+
+    // ===== GET FILE URL
+    // start the spinner
+    let sp = start_spinner("Loading file URL...", mode);
+    // do API call
+    let api_file_path = cfg.api_url_get_file();
+    let start = Instant::now();
+    let retry = cfg.retry_policy();
+    let file_res = api_get_file(client, &result.file_id, &api_file_path, &retry);
+    let file = tracing::info_span!("getFile", file_id = %result.file_id).in_scope(|| rt.block_on(file_res));
+    trace::phase("getFile", start);
+    stop_spinner(sp);
+    let file = file.map_err(|err| Error::context(&*err, "Loading file URL failed"))?;
+    result.url = Some(cfg.api_url_file_url(file.file_path));
+    Ok(())
+}
+
+/// add the upload to the history, it's only a warning when that fails
+fn record_history(result: &UploadResult, path: Option<&str>) {
+    if let Err(err) = history::record(result, path) {
+        eprintln!("{}", format!("Warning: not saved in the history: {}", err).yellow());
+    }
+}
+
+/// pin the sent message, a failure is only a warning because the file is uploaded anyway
+/// private chats are skipped, there the pin is only on the bot's side
+fn pin_message(rt: &Runtime, client: &Client, cfg: &Config, name: &str, message: &SentMessage, silent: bool, verbose: bool) {
+    // users have positive ids, groups and channels negative ones
+    if message.chat_id > 0 {
+        if verbose {
+            eprintln!("Not pinning {} in chat {}, the chat is private", name, message.chat_id);
+        }
+        return;
+    }
+    let request = RequestPinChatMessage {
+        chat_id: message.chat_id,
+        message_id: message.message_id,
+        disable_notification: silent,
+    };
+    if let Err(err) = rt.block_on(api_call::<bool>(client, &cfg.api_url(API_PIN_CHAT_MESSAGE), &request)) {
+        output::print_warning(&format!("Warning: pinning {} in chat {} failed: {}", name, message.chat_id, err));
+        if err.to_string().contains("not enough rights") {
+            eprintln!("{}", "The bot needs the right to pin messages in this chat".yellow());
+        }
+    }
+}
+
+/// upload the file in parts, followed by the manifest
+/// when a part fails the rest are still tried, then the retry command is printed
+#[allow(clippy::too_many_arguments)]
+fn upload_split(rt: &Runtime, client: &Client, cfg: &Config, args: &Args, filename: &Path, name: &str, file_size: u64, part_size: u64) -> Result<UploadResult, Box<dyn std::error::Error>> {
+    let manifest = split::plan(Path::new(filename), name, file_size, part_size).map_err(|err| Error::io(filename.display(), &err))?;
+    let count = manifest.parts.len();
+    if let Some(parts) = &args.parts {
+        if let Some(bad) = parts.iter().find(|&&p| p == 0 || p > count) {
+            return Err(Error::Usage(format!("Part {} doesn't exist, the file has {} parts", bad, count)).into());
+        }
+    }
+    eprintln!("Splitting {} ({}) into {} parts", name, DecimalBytes(file_size), count);
+
+    // parts and the manifest are always documents
+    let url = cfg.api_url_send(SendMethod::Document);
+    let mut uploaded = Vec::new();
+    let mut results = Vec::new();
+    let mut failed = Vec::new();
+    // exit code of the first failed part
+    let mut failed_code = error::EXIT_API;
+    for part in &manifest.parts {
+        if args.parts.as_ref().is_some_and(|parts| !parts.contains(&part.index)) {
+            continue;
+        }
+        cfg.pace(&cfg.chat_id);
+        let started = std::time::Instant::now();
+        let url = url.as_str();
+        let res = rt.block_on(retry::run(cfg.retry_policy(), &format!("Uploading {}", part.name), || async move {
+            let payload = open_payload_part(filename, part.offset, part.size).await?;
+            // parts are raw bytes, the caption and the buttons go to the manifest
+            let send = SendOptions {
+                buttons: Vec::new(),
+                ..args.send.without_caption()
+            };
+            api_upload_document(client, payload, &part.name, "application/octet-stream", SendMethod::Document, &send, None, url, cfg.transfer_timeout(part.size), args.output.is_human()).await
+        }));
+        match res {
+            Ok(sent) => {
+                uploaded.push(part.index);
+                results.push(UploadResult {
+                    name: part.name.clone(),
+                    size: part.size,
+                    sha256: Some(sent.sha256),
+                    file_id: sent.file_id,
+                    file_unique_id: sent.file_unique_id,
+                    sent_as: SendMethod::Document,
+                    silent: args.send.silent,
+                    protected: args.send.protect,
+                    message_id: sent.message_id,
+                    chat_id: sent.chat_id,
+                    chat_name: sent.chat_name,
+                    reply_to: sent.reply_to,
+                    duration_secs: started.elapsed().as_secs_f64(),
+                    url: None,
+                    parts: Vec::new(),
+                    broadcast: Vec::new(),
+                    broadcast_failed: Vec::new(),
+                    reported_size: sent.file_size,
+                });
+            }
+            Err(err) => {
+                let code = error::exit_code(&*err);
+                output::print_error(&args.output, Some(&part.name), &err.to_string(), code);
+                if failed.is_empty() {
+                    failed_code = code;
+                }
+                failed.push(part.index);
+            }
+        }
+    }
+    if !failed.is_empty() {
+        let list = |parts: &[usize]| parts.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(",");
+        if !uploaded.is_empty() {
+            eprintln!("Uploaded parts: {}", list(&uploaded));
+        }
+        eprintln!("Retry the missing parts with: --split --parts {}", list(&failed));
+        let msg = format!("{} of {} parts failed", failed.len(), count);
+        return Err(Error::with_code(failed_code, msg).into());
+    }
+
+    // ===== UPLOAD MANIFEST
+    let manifest_name = split::manifest_name(name);
+    let contents = serde_json::to_vec_pretty(&manifest)?;
+    let payload = Payload {
+        size: contents.len() as u64,
+        source: PayloadSource::Memory(contents),
+    };
+    cfg.pace(&cfg.chat_id);
+    let started = std::time::Instant::now();
+    let timeout = cfg.transfer_timeout(payload.size);
+    let what = format!("Uploading {}", manifest_name);
+    let manifest_res = {
+        let (payload, manifest_name, url) = (&payload, manifest_name.as_str(), url.as_str());
+        retry::run(cfg.retry_policy(), &what, move || async move {
+            let payload = payload.try_clone().await?;
+            api_upload_document(client, payload, manifest_name, "application/json", SendMethod::Document, &args.send, None, url, timeout, args.output.is_human()).await
+        })
+    };
+    let sent = rt.block_on(manifest_res)?;
+    // the result describes the whole file, reachable through the manifest
+    Ok(UploadResult {
+        name: name.to_string(),
+        size: file_size,
+        sha256: Some(manifest.sha256),
+        file_id: sent.file_id,
+        file_unique_id: sent.file_unique_id,
+        sent_as: SendMethod::Document,
+        silent: args.send.silent,
+        protected: args.send.protect,
+        message_id: sent.message_id,
+        chat_id: sent.chat_id,
+        chat_name: sent.chat_name,
+        reply_to: sent.reply_to,
+        duration_secs: started.elapsed().as_secs_f64() + results.iter().map(|r| r.duration_secs).sum::<f64>(),
+        url: None,
+        parts: results,
+        broadcast: Vec::new(),
+        broadcast_failed: Vec::new(),
+        // telegram only knows the size of the manifest, the parts have theirs
+        reported_size: None,
+    })
+}
+
+/// load the config and check that it can be used to talk to the bot api
+/// the command line wins over the environment, which wins over the profile, a .teledrop.toml and then the global file
+/// with token_source = "keyring" the global token comes from the keyring instead
+/// `chat` is the --chat of the subcommand, the file is optional, everything can be set with TELEDROP_* variables
+fn load_config(overrides: &ConfigArgs, chat: Option<&str>) -> Result<Config, Error> {
+    let config_path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)
+        .map_err(|err| Error::Usage(format!("Config error: {}", err)))?;
+    let mut cfg = read_config_file(&config_path)?;
+    check_permissions(&config_path, &cfg);
+    // the keyring is only asked when the environment or the command line don't have the token anyway
+    let token_given = overrides.token.is_some() || std::env::var_os("TELEDROP_BOT_TOKEN").is_some_and(|v| !v.is_empty());
+    if cfg.token_source == Some(TokenSource::Keyring) && !token_given {
+        cfg.bot_token = secrets::get_token().map_err(Error::Usage)?;
+    }
+    if let Some(path) = find_local_config().filter(|_| !overrides.no_local_config) {
+        let local: LocalConfig = confy::load_path(&path).map_err(|err| Error::Usage(format!("Config error in {}: {}", path.display(), err)))?;
+        cfg.apply_local(local, &path);
+    }
+    cfg.apply_profile(overrides.profile.as_deref()).map_err(Error::Usage)?;
+    cfg.apply_env().map_err(Error::Usage)?;
+    if let Some(token) = &overrides.token {
+        cfg.bot_token = token.clone();
+    }
+    cfg.connect_timeout = overrides.connect_timeout.or(cfg.connect_timeout);
+    cfg.timeout = overrides.timeout.or(cfg.timeout);
+    cfg.retries = overrides.retries.or(cfg.retries);
+    cfg.retry_delay = overrides.retry_delay.or(cfg.retry_delay);
+    cfg.max_retry_after = overrides.max_retry_after.or(cfg.max_retry_after);
+    cfg.rate = overrides.rate.or(cfg.rate);
+    if let Some(chat) = chat {
+        cfg.chat_id = chat.to_string();
+        // the topic is one of the configured chat
+        cfg.topic_id = None;
+    }
+    if cfg.chat_id.is_empty() {
+        cfg.chat_id = cfg.broadcast.first().cloned().unwrap_or_default();
+    }
+    // check if bot_token and chat_id exists in config
+    let missing: Vec<&str> = [("bot_token", &cfg.bot_token), ("chat_id", &cfg.chat_id)]
+        .iter()
+        .filter(|(_, value)| value.is_empty())
+        .map(|(key, _)| *key)
+        .collect();
+    if !missing.is_empty() {
+        // point to the config file path and the variables
+        let env: Vec<&str> = missing.iter().filter_map(|key| CONFIG_ENV.iter().find(|(k, _)| k == key)).map(|(_, env)| *env).collect();
+        return Err(Error::Usage(format!(
+            "Config param {} is missing, set it with `teledrop config set {} <VALUE>`, in the configuration file at \"{}\" or with {}",
+            missing.join(" and "),
+            missing[0],
+            config_path.display(),
+            env.join(" and ")
+        )));
+    }
+    cfg.chat_id = cfg.resolve_chat(&cfg.chat_id)?;
+    cfg.validate_api_base_url().map_err(Error::Usage)?;
+    output::hide_token(&cfg.bot_token);
+    // after hide_token, so the log is redacted too
+    let log_file = overrides.log_file.as_deref().or(cfg.log_file.as_deref());
+    if let Err(msg) = logging::init(log_file.map(Path::new)) {
+        output::print_warning(&format!("Warning: {}", msg));
+    }
+    tracing::debug!(config = %config_path.display(), chat_id = %cfg.chat_id, "config loaded");
+    Ok(cfg)
+}
+
+/// teledrop join <MANIFEST | PARTS...> [-o <OUTPUT>]
+fn run_join(args: JoinArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let (output, verified) = split::join(&args.inputs, args.output.as_deref())?;
+    if verified {
+        println!("{} Joined into {} (checksum verified)", "✔".green(), output.display());
+    } else {
+        println!("{} Joined into {}", "✔".green(), output.display());
+        eprintln!("{}", "No manifest found next to the parts, the checksum was not verified".yellow());
+    }
+    Ok(())
+}
+
+/// teledrop download <FILE_ID> [-o <OUTPUT>] [--force]
+fn run_download(args: DownloadArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let cfg = load_config(&args.config, None)?;
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    let file_id = history::resolve_file_id(&args.file_id)?;
+    let file = get_file(&rt, &client, &cfg, &file_id, &OutputMode::Human)?;
+    let uploaded = history::find_file_id(&file_id);
+    // telegram stores files as e.g. documents/file_1.pdf, the original name is only in the history
+    let output = match (args.output, &uploaded) {
+        (Some(output), _) => output,
+        (None, Some(entry)) => upload_name(Path::new(&entry.name), None),
+        (None, None) => upload_name(Path::new(&file.file_path), None),
+    };
+    if Path::new(&output).exists() && !args.force {
+        return Err(Error::File(format!("{} already exists, use --force to overwrite it", output)).into());
+    }
+
+    // download next to the output under a .part name, an interrupted download is resumed from there
+    let part = format!("{}.part", output);
+    let part_file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(args.no_resume)
+        .open(&part)
+        .map_err(|err| Error::File(format!("{}: {}", part, err)))?;
+    let timeout = cfg.transfer_timeout(file.size.unwrap_or(DOWNLOAD_SIZE_LIMIT));
+    let size = rt.block_on(api_download_file(&client, &file.url, &output, part_file, timeout))?;
+    let verified = match recorded_sha256(&output, uploaded.as_ref()) {
+        Some(expected) => {
+            if file_sha256(&part)? != expected {
+                // a resumed download can't be trusted anymore, the next one starts over
+                std::fs::remove_file(&part)?;
+                return Err(Error::File(format!("Checksum mismatch for {}, expected SHA-256 {}", output, expected)).into());
+            }
+            true
+        }
+        None => false,
+    };
+    std::fs::rename(&part, &output)?;
+    if verified {
+        println!("{} Downloaded into {} ({}, checksum verified)", "✔".green(), output, DecimalBytes(size));
+        return Ok(());
+    }
+    println!("{} Downloaded into {} ({})", "✔".green(), output, DecimalBytes(size));
+    Ok(())
+}
+
+/// getFile with a spinner, the file with a fresh download url
+fn get_file(rt: &Runtime, client: &Client, cfg: &Config, file_id: &str, mode: &OutputMode) -> Result<FileResult, Box<dyn std::error::Error>> {
+    let sp = start_spinner("Loading file info...", mode);
+    let file = rt.block_on(api_get_file(client, file_id, &cfg.api_url_get_file(), &cfg.retry_policy()));
+    stop_spinner(sp);
+    let file = file.map_err(|err| Error::context(&*err, &format!("Loading file {} failed", file_id)))?;
+    Ok(FileResult {
+        url: cfg.api_url_file_url(file.file_path.clone()),
+        file_id: file.file_id,
+        file_unique_id: file.file_unique_id,
+        size: file.file_size,
+        file_path: file.file_path,
+        history: None,
+    })
+}
+
+/// teledrop url <FILE_ID> [--json | --format <TEMPLATE>]
+fn run_url(args: UrlArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = args.output();
+    let cfg = load_config(&args.config, None)?;
+    let file_id = history::resolve_file_id(&args.file_id)?;
+    let file = get_file(&Runtime::new()?, &cfg.client()?, &cfg, &file_id, &mode)?;
+    output::print_file(&mode, &file);
+    Ok(())
+}
+
+/// teledrop info <FILE_ID> [--json]
+fn run_info(args: InfoArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = if args.json { OutputMode::Json } else { OutputMode::Human };
+    let cfg = load_config(&args.config, None)?;
+    let file_id = history::resolve_file_id(&args.file_id)?;
+    let mut file = get_file(&Runtime::new()?, &cfg.client()?, &cfg, &file_id, &mode)?;
+    file.history = history::find_file_id(&file_id);
+    output::print_info(&mode, &file);
+    Ok(())
+}
+
+/// teledrop reshare <FILE_ID> [--chat <CHAT_ID>]
+fn run_reshare(mut args: ReshareArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = args.print.mode();
+    let cfg = load_config(&args.config, args.chat.as_deref())?;
+    args.send.silent |= cfg.disable_notification;
+    args.send.protect |= cfg.protect_content;
+    cfg.apply_topic(&mut args.send)?;
+    button::check(&args.send.buttons).map_err(Error::Usage)?;
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    let file_id = history::resolve_file_id(&args.file_id)?;
+    // a file_id only goes with the method it was sent with, the history knows it
+    let method = history::find_file_id(&file_id).map(|entry| entry.sent_as).unwrap_or_default();
+    let request = RequestSendFile {
+        chat_id: &cfg.chat_id,
+        file: FileField(method, &file_id),
+        send: &args.send,
+    };
+    let started = std::time::Instant::now();
+    let sent = rt.block_on(api_send_file_by_id(&client, &request, &cfg.api_url(method.path()), &cfg.retry_policy()))?;
+    let mut result = UploadResult {
+        name: sent.document.file_name.unwrap_or_default(),
+        size: sent.document.file_size.unwrap_or_default(),
+        // the bytes never passed through here
+        sha256: None,
+        file_id: sent.document.file_id,
+        file_unique_id: sent.document.file_unique_id,
+        sent_as: method,
+        silent: args.send.silent,
+        protected: args.send.protect,
+        message_id: sent.message_id,
+        chat_name: sent.chat.display_name(),
+        reply_to: sent.reply_to,
+        chat_id: sent.chat.id,
+        duration_secs: started.elapsed().as_secs_f64(),
+        url: None,
+        parts: Vec::new(),
+        broadcast: Vec::new(),
+        broadcast_failed: Vec::new(),
+        reported_size: sent.document.file_size,
+    };
+    add_download_url(&rt, &client, &cfg, &mut result, &mode)?;
+    output::print_result(&mode, &result)?;
+    record_history(&result, None);
+    Ok(())
+}
+
+/// the message of a handle and the chat it's in: the --chat override, the chat from the history or the configured one
+fn resolve_message(handle: &str, chat: Option<&str>, cfg: &Config) -> Result<(i64, String), Box<dyn std::error::Error>> {
+    let (message_id, history_chat) = history::resolve_message(handle)?;
+    let chat_id = match (chat, history_chat) {
+        (Some(chat), _) => cfg.resolve_chat(chat)?,
+        (None, Some(chat)) => chat.to_string(),
+        (None, None) => cfg.chat_id.clone(),
+    };
+    Ok((message_id, chat_id))
+}
+
+/// teledrop delete <MESSAGE_ID> [--chat <CHAT_ID>]
+fn run_delete(args: DeleteArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let cfg = load_config(&args.config, args.chat.as_deref())?;
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    let (message_id, chat_id) = resolve_message(&args.message, args.chat.as_deref(), &cfg)?;
+    let chat_id = chat_id.as_str();
+    let request = RequestDeleteMessage { chat_id, message_id };
+    let res = rt.block_on(api_call::<bool>(&client, &cfg.api_url(API_DELETE_MESSAGE), &request));
+    res.map_err(|err| -> Box<dyn std::error::Error> {
+        let msg = err.to_string();
+        if msg.contains("message to delete not found") {
+            Error::NotFound(format!("Message {} not found in chat {}, it may be deleted already", message_id, chat_id)).into()
+        } else if msg.contains("message can't be deleted") {
+            let hint = "bots can only delete messages up to 48 hours old, and need the right to delete messages in groups";
+            Error::Api(format!("{} ({})", msg, hint)).into()
+        } else {
+            err
+        }
+    })?;
+    println!("{} Deleted message {} in chat {}", "✔".green(), message_id, chat_id);
+    Ok(())
+}
+
+/// teledrop test [--chat <CHAT>] [--delete]
+/// a text message goes through the same config, token and chat as an upload, without a file
+fn run_test(args: TestArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let cfg = load_config(&args.config, args.chat.as_deref())?;
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    let hostname = gethostname::gethostname().to_string_lossy().into_owned();
+    let text = format!("teledrop test ✔ from {} at {}", hostname, Local::now().format("%Y-%m-%d %H:%M:%S"));
+    let request = RequestSendMessage {
+        chat_id: &cfg.chat_id,
+        text: &text,
+        message_thread_id: cfg.topic_id,
+    };
+    let started = std::time::Instant::now();
+    let res = rt.block_on(api_call::<TelegramMessage>(&client, &cfg.api_url(API_SEND_MESSAGE), &request));
+    let message = res.map_err(|err| Error::context(&*err, "Sending the test message failed"))?;
+    let elapsed = started.elapsed();
+    println!(
+        "{} Delivered to chat {} in {:.2}s",
+        "✔".green(),
+        message.chat.id,
+        elapsed.as_secs_f64()
+    );
+    println!("Message ID: {}", message.message_id);
+
+    if args.delete {
+        let chat_id = message.chat.id.to_string();
+        let request = RequestDeleteMessage {
+            chat_id: &chat_id,
+            message_id: message.message_id,
+        };
+        let res = rt.block_on(api_call::<bool>(&client, &cfg.api_url(API_DELETE_MESSAGE), &request));
+        res.map_err(|err| Error::context(&*err, "Deleting the test message failed"))?;
+        println!("{} Deleted the test message", "✔".green());
+    }
+    Ok(())
+}
+
+/// teledrop edit-caption <MESSAGE_ID> <TEXT> [--parse-mode <MODE>] [--chat <CHAT_ID>]
+fn run_edit_caption(args: EditCaptionArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let cfg = load_config(&args.config, args.chat.as_deref())?;
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    let (message_id, chat_id) = resolve_message(&args.message, args.chat.as_deref(), &cfg)?;
+    let chat_id = chat_id.as_str();
+    let request = RequestEditMessageCaption {
+        chat_id,
+        message_id,
+        caption: &args.caption,
+        parse_mode: args.parse_mode,
+    };
+    let res = rt.block_on(api_call::<TelegramMessage>(&client, &cfg.api_url(API_EDIT_MESSAGE_CAPTION), &request));
+    let message = res.map_err(|err| -> Box<dyn std::error::Error> {
+        let msg = err.to_string();
+        if msg.contains("message is not modified") {
+            Error::Unchanged(format!("Message {} already has this caption", message_id)).into()
+        } else if msg.contains("message to edit not found") {
+            Error::NotFound(format!("Message {} not found in chat {}", message_id, chat_id)).into()
+        } else if msg.contains("message can't be edited") {
+            let hint = "bots can only edit their own messages";
+            Error::Api(format!("{} ({})", msg, hint)).into()
+        } else {
+            err
+        }
+    })?;
+    println!("{} Caption of message {} in chat {} changed", "✔".green(), message.message_id, message.chat.id);
+    match message.caption {
+        Some(caption) => println!("{}", caption),
+        None => println!("(no caption)"),
+    }
+    Ok(())
+}
+
+/// teledrop forward <MESSAGE_ID> --to <CHAT_ID> [--from <CHAT_ID>] [--silent] [--protect]
+fn run_forward(args: ForwardArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let cfg = load_config(&args.config, args.from.as_deref())?;
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    let (message_id, from_chat_id) = resolve_message(&args.message, args.from.as_deref(), &cfg)?;
+    let to = cfg.resolve_chat(&args.to)?;
+    let request = RequestForwardMessage {
+        chat_id: &to,
+        from_chat_id: &from_chat_id,
+        message_id,
+        disable_notification: args.silent || cfg.disable_notification,
+        protect_content: args.protect || cfg.protect_content,
+    };
+    // telegram's description is clear enough here, e.g. when the bot is not a member of the chat
+    let res = rt.block_on(api_send::<TelegramMessage>(&client, &cfg.api_url(API_FORWARD_MESSAGE), &request, request.protect_content));
+    let message = res.map_err(|err| Error::context(&*err, "Forwarding failed"))?;
+    println!("{} Forwarded to chat {}", "✔".green(), message.chat.id);
+    println!("Message ID: {}", message.message_id);
+    if request.protect_content {
+        println!("Protected, it can't be forwarded or saved");
+    }
+    Ok(())
+}
+
+/// the bot id and the ends of the secret, e.g. 123456:ABC…ew11
+fn mask_token(token: &str) -> String {
+    match token.split_once(':') {
+        Some((id, secret)) if secret.chars().count() > 8 => {
+            let start: String = secret.chars().take(3).collect();
+            let end: String = secret.chars().skip(secret.chars().count() - 4).collect();
+            format!("{}:{}…{}", id, start, end)
+        }
+        Some((id, _)) => format!("{}:…", id),
+        None => "…".to_string(),
+    }
+}
+
+/// the permission bits of the file when other users can read or write it
+#[cfg(unix)]
+fn open_permissions(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    (mode & 0o077 != 0).then_some(mode)
+}
+
+// windows has acls instead, the config dir is private to the user there
+#[cfg(not(unix))]
+fn open_permissions(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// warn when a token in the config file can be read by other users on the machine
+fn check_permissions(path: &Path, cfg: &Config) {
+    let has_token = !cfg.bot_token.is_empty() || cfg.profiles.values().any(|profile| profile.bot_token.is_some());
+    if cfg.suppress_permission_warning || !has_token {
+        return;
+    }
+    if let Some(mode) = open_permissions(path) {
+        let msg = format!(
+            "Warning: {} holds the bot token and other users can read it (mode {:o}), fix it with `teledrop config fix-perms`",
+            path.display(),
+            mode
+        );
+        eprintln!("{}", msg.yellow());
+    }
+}
+
+/// the global config file as it is, without the local file, profiles or the environment
+fn read_config_file(path: &Path) -> Result<Config, Error> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    confy::load_path(path).map_err(|err| Error::Usage(format!("Config error: {}", err)))
+}
+
+/// store the config through a temp file next to it, so it's never half written
+/// the temp file is only readable by the user, like the token should be
+fn write_config_file(path: &Path, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = path.parent().expect("config path has a parent");
+    std::fs::create_dir_all(dir)?;
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    confy::store_path(tmp.path(), cfg)?;
+    tmp.persist(path)?;
+    Ok(())
+}
+
+/// make the config file readable by the user only
+#[cfg(unix)]
+fn fix_permissions(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|err| Error::File(format!("Changing the permissions of {} failed: {}", path.display(), err)))?;
+    eprintln!("{} {} is only readable by you now (mode 600)", "✔".green(), path.display());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn fix_permissions(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("Nothing to do on Windows, {} is protected by the permissions of your user profile", path.display());
+    Ok(())
+}
+
+/// teledrop config <path | get <KEY> | set <KEY> <VALUE> | fix-perms | edit>
+fn run_config(command: ConfigCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
+    match command {
+        ConfigCommand::Path => println!("{}", path.display()),
+        ConfigCommand::Get(args) => {
+            let mut cfg = read_config_file(&path)?;
+            if args.key == "bot_token" && cfg.token_source == Some(TokenSource::Keyring) {
+                cfg.bot_token = secrets::get_token().map_err(Error::Usage)?;
+            }
+            let Some(value) = cfg.get_key(&args.key) else {
+                return Err(Error::Other(format!("{} is not set in {}", args.key, path.display())).into());
+            };
+            match args.key.as_str() {
+                "bot_token" if !args.reveal => println!("{}", mask_token(&value)),
+                _ => println!("{}", value),
+            }
+        }
+        ConfigCommand::Set(args) => {
+            if args.keyring && args.key != "bot_token" {
+                return Err(Error::Usage("--keyring is only for bot_token".into()).into());
+            }
+            let mut cfg = read_config_file(&path)?;
+            cfg.set_key(&args.key, &args.value).map_err(Error::Usage)?;
+            if args.keyring {
+                // the token leaves the file, only the note where it is stays
+                secrets::set_token(&cfg.bot_token).map_err(Error::Usage)?;
+                cfg.bot_token.clear();
+                cfg.token_source = Some(TokenSource::Keyring);
+            } else if args.key == "bot_token" {
+                cfg.token_source = None;
+            }
+            write_config_file(&path, &cfg)?;
+            if args.keyring {
+                eprintln!("Stored bot_token in the keyring, {} only has token_source = \"keyring\"", path.display());
+            } else if args.value.is_empty() {
+                eprintln!("Removed {} from {}", args.key, path.display());
+            } else {
+                eprintln!("Set {} in {}", args.key, path.display());
+            }
+        }
+        ConfigCommand::FixPerms => {
+            if !path.exists() {
+                return Err(Error::File(format!("{} doesn't exist", path.display())).into());
+            }
+            fix_permissions(&path)?;
+        }
+        ConfigCommand::Edit => {
+            if !path.exists() {
+                write_config_file(&path, &Config::default())?;
+            }
+            // the variable can carry arguments, like "code --wait"
+            let editor = std::env::var("VISUAL")
+                .or_else(|_| std::env::var("EDITOR"))
+                .ok()
+                .filter(|editor| !editor.trim().is_empty())
+                .unwrap_or_else(|| if cfg!(windows) { "notepad".into() } else { "vi".into() });
+            let mut words = editor.split_whitespace();
+            let program = words.next().expect("editor is not empty");
+            let status = std::process::Command::new(program)
+                .args(words)
+                .arg(&path)
+                .status()
+                .map_err(|err| Error::Other(format!("Can't start the editor {}: {}", program, err)))?;
+            if !status.success() {
+                return Err(Error::Other(format!("{} exited with {}", program, status)).into());
+            }
+            // a mistake should show up now, not at the next upload
+            if let Err(err) = read_config_file(&path) {
+                eprintln!("{}", format!("Warning: {}", err).yellow());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// read a line from the terminal after the question, trimmed
+fn prompt(question: &str) -> Result<String, Box<dyn std::error::Error>> {
+    eprint!("{}", question);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+/// the chats seen in the updates, each once, in the order they showed up
+fn update_chats(updates: Vec<TelegramUpdate>) -> Vec<TelegramChat> {
+    let mut chats: Vec<TelegramChat> = Vec::new();
+    for update in updates {
+        let chat = update
+            .message
+            .or(update.channel_post)
+            .map(|message| message.chat)
+            .or(update.my_chat_member.map(|member| member.chat));
+        if let Some(chat) = chat.filter(|chat| chats.iter().all(|seen| seen.id != chat.id)) {
+            chats.push(chat);
+        }
+    }
+    chats
+}
+
+/// teledrop setup
+/// asks for the token, finds the chat from the messages the bot got and writes the config file
+fn run_setup() -> Result<(), Box<dyn std::error::Error>> {
+    let path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
+    let mut cfg = read_config_file(&path)?;
+    let keyring = cfg.token_source == Some(TokenSource::Keyring);
+    if keyring {
+        cfg.bot_token = secrets::get_token().unwrap_or_default();
+    }
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+    let mode = OutputMode::Human;
+
+    // ===== TOKEN
+    // hidden like a password, only the masked token is ever shown
+    eprintln!("Create a bot with @BotFather in Telegram if you don't have one, it replies with the token.");
+    let question = if cfg.bot_token.is_empty() {
+        "Bot token: ".to_string()
+    } else {
+        format!("Bot token (Enter keeps {}): ", mask_token(&cfg.bot_token))
+    };
+    let token = rpassword::prompt_password(question)?.trim().to_string();
+    if !token.is_empty() {
+        cfg.bot_token = token;
+    }
+    if cfg.bot_token.is_empty() {
+        return Err(Error::Usage("No bot token given".into()).into());
+    }
+    output::hide_token(&cfg.bot_token);
+    let sp = start_spinner("Checking the token...", &mode);
+    let bot = rt.block_on(api_call::<TelegramUser>(&client, &cfg.api_url(API_GET_ME), &serde_json::json!({})));
+    stop_spinner(sp);
+    let bot = bot.map_err(|err| Error::context(&*err, &format!("Token {} was not accepted", mask_token(&cfg.bot_token))))?;
+    let handle = bot.username.map(|username| format!("@{}", username)).unwrap_or_else(|| bot.first_name.clone());
+    eprintln!("{} Bot: {} ({})", "✔".green(), bot.first_name, handle);
+
+    // ===== CHAT
+    // the chat_id is only known from an update the bot got from that chat
+    let chats = loop {
+        eprintln!(
+            "Send any message to {} from the chat the files should go to. For a group or channel add the bot \
+             there first and post a message in it.",
+            handle
+        );
+        let sp = start_spinner("Waiting for messages...", &mode);
+        let request = RequestGetUpdates { timeout: 20 };
+        let updates = rt.block_on(api_call::<Vec<TelegramUpdate>>(&client, &cfg.api_url(API_GET_UPDATES), &request));
+        stop_spinner(sp);
+        let chats = update_chats(updates.map_err(|err| Error::context(&*err, "Getting the messages of the bot failed"))?);
+        if !chats.is_empty() {
+            break chats;
+        }
+        eprintln!("{}", "The bot hasn't received any messages yet.".yellow());
+        eprintln!("In a private chat open {} and press Start. In groups bots only see commands and replies", handle);
+        eprintln!("while privacy mode is on, send /start@{} or turn it off with /setprivacy in @BotFather.", handle.trim_start_matches('@'));
+        eprintln!("Channels only work when the bot is an admin there.");
+        if !confirm("Try again?") {
+            let msg = "No chat found, set it later with `teledrop config set chat_id <CHAT_ID>`".to_string();
+            return Err(Error::Usage(msg).into());
+        }
+    };
+    for (i, chat) in chats.iter().enumerate() {
+        let name = chat.name().unwrap_or_else(|| "(no name)".to_string());
+        let username = chat.username.as_ref().map(|username| format!(" @{}", username)).unwrap_or_default();
+        let kind = chat.kind.as_deref().unwrap_or("chat");
+        eprintln!("  {}. {}{} ({}, {})", i + 1, name, username, kind, chat.id);
+    }
+    let chat = loop {
+        let answer = prompt(&format!("Chat to send the files to [1-{}, default 1]: ", chats.len()))?;
+        match answer.parse::<usize>() {
+            _ if answer.is_empty() => break &chats[0],
+            Ok(n) if (1..=chats.len()).contains(&n) => break &chats[n - 1],
+            _ => eprintln!("{}", format!("Pick a number from 1 to {}", chats.len()).yellow()),
+        }
+    };
+    cfg.chat_id = chat.id.to_string();
+
+    // a token in the keyring stays there
+    if keyring {
+        secrets::set_token(&cfg.bot_token).map_err(Error::Usage)?;
+        cfg.bot_token.clear();
+    }
+    write_config_file(&path, &cfg)?;
+    eprintln!("{} Saved to {}, try it with `teledrop <file>`", "✔".green(), path.display());
+    Ok(())
+}
+
+/// print the outcome of a doctor check, a failure comes with what to do about it
+fn print_check(name: &str, result: &Result<String, (String, &str, i32)>) {
+    match result {
+        Ok(detail) => println!("{} {:<10} {}", "✔".green(), name, detail),
+        Err((err, hint, _)) => {
+            println!("{} {:<10} {}", "✘".red(), name, output::redact(err).red());
+            println!("  {:<10} {}", "", hint);
+        }
+    }
+}
+
+/// teledrop doctor
+/// every check needs the ones before it, after the first failure the rest are skipped
+fn run_doctor(args: DoctorArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let rt = Runtime::new()?;
+    fn skip_rest(code: i32) -> ! {
+        println!("{}", "Skipped the other checks".dimmed());
+        std::process::exit(code);
+    }
+
+    // ===== CONFIG FILE
+    // a missing file is fine when everything is in the environment
+    let path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
+    let file = match read_config_file(&path) {
+        Ok(_) if !path.exists() => Ok(format!("{} doesn't exist, using the environment", path.display())),
+        Ok(_) => Ok(path.display().to_string()),
+        Err(err) => {
+            let hint = "Fix the file with `teledrop config edit` or start over with `teledrop setup`";
+            Err((err.to_string(), hint, error::EXIT_USAGE))
+        }
+    };
+    print_check("Config", &file);
+    if let Err((_, _, code)) = file {
+        skip_rest(code);
+    }
+    let cfg = match load_config(&args.config, None) {
+        Ok(cfg) => {
+            print_check("Settings", &Ok(format!("bot token {}, chat {}", mask_token(&cfg.bot_token), cfg.chat_id)));
+            cfg
+        }
+        Err(err) => {
+            let hint = "Run `teledrop setup` to set the bot token and the chat";
+            print_check("Settings", &Err((err.to_string(), hint, error::exit_code(&err))));
+            skip_rest(error::exit_code(&err));
+        }
+    };
+    let client = cfg.client()?;
+
+    // ===== NETWORK
+    // any http answer means the server is reachable, the token is checked next
+    let reachable = rt.block_on(trace::send(&client, client.get(cfg.api_base_url())));
+    let reachable = match reachable {
+        Ok(_) => Ok(cfg.api_base_url().to_string()),
+        Err(err) => {
+            let hint = "Check the network connection, a proxy or firewall, and api_base_url";
+            Err((err.to_string(), hint, error::exit_code(&*err)))
+        }
+    };
+    print_check("Network", &reachable);
+    if let Err((_, _, code)) = reachable {
+        skip_rest(code);
+    }
+
+    // ===== BOT
+    let bot = rt.block_on(api_call::<TelegramUser>(&client, &cfg.api_url(API_GET_ME), &serde_json::json!({})));
+    let bot = match bot {
+        Ok(bot) => Ok(match bot.username {
+            Some(username) => format!("{} (@{})", bot.first_name, username),
+            None => bot.first_name,
+        }),
+        Err(err) => {
+            let hint = "The token is wrong or revoked, get it from @BotFather and `teledrop config set bot_token <TOKEN>`";
+            Err((err.to_string(), hint, error::exit_code(&*err)))
+        }
+    };
+    print_check("Bot", &bot);
+    if let Err((_, _, code)) = bot {
+        skip_rest(code);
+    }
+
+    // ===== CHAT
+    let request = RequestGetChat { chat_id: &cfg.chat_id };
+    let chat = rt.block_on(api_call::<TelegramChat>(&client, &cfg.api_url(API_GET_CHAT), &request));
+    let chat = match chat {
+        Ok(chat) => {
+            let name = chat.display_name().unwrap_or_else(|| chat.id.to_string());
+            Ok(format!("{} ({}, {})", name, chat.kind.as_deref().unwrap_or("chat"), chat.id))
+        }
+        Err(err) => {
+            let hint = "The bot can't see the chat: send it a message or add it to the group, `teledrop setup` finds the chat ID";
+            Err((err.to_string(), hint, error::exit_code(&*err)))
+        }
+    };
+    print_check("Chat", &chat);
+    if let Err((_, _, code)) = chat {
+        skip_rest(code);
+    }
+
+    // ===== SENDING
+    // a chat action is the one thing a bot can send that leaves nothing behind
+    let request = RequestSendChatAction {
+        chat_id: &cfg.chat_id,
+        action: "upload_document",
+    };
+    let send = match rt.block_on(api_call::<bool>(&client, &cfg.api_url(API_SEND_CHAT_ACTION), &request)) {
+        Ok(_) => Ok("the bot can send to the chat".to_string()),
+        Err(err) => {
+            let hint = "The bot needs to be able to post, make it an admin of the channel or unblock it";
+            Err((err.to_string(), hint, error::exit_code(&*err)))
+        }
+    };
+    print_check("Sending", &send);
+    if let Err((_, _, code)) = send {
+        skip_rest(code);
+    }
+    println!("{} Everything looks fine", "✔".green());
+    Ok(())
+}
+
+/// teledrop chat-info [<CHAT>] [--json]
+fn run_chat_info(args: ChatInfoArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = if args.json { OutputMode::Json } else { OutputMode::Human };
+    let cfg = load_config(&args.config, args.chat.as_deref())?;
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    let sp = start_spinner("Loading chat info...", &mode);
+    let request = RequestGetChat { chat_id: &cfg.chat_id };
+    let chat = rt.block_on(api_call::<TelegramChat>(&client, &cfg.api_url(API_GET_CHAT), &request));
+    // only groups and channels have members to count
+    let private = chat.as_ref().is_ok_and(|chat| chat.kind.as_deref() == Some("private"));
+    let member_count = if chat.is_ok() && !private {
+        rt.block_on(api_call::<u64>(&client, &cfg.api_url(API_GET_CHAT_MEMBER_COUNT), &request)).ok()
+    } else {
+        None
+    };
+    stop_spinner(sp);
+    let chat = chat.map_err(|err| Error::context(&*err, &format!("Loading chat {} failed", cfg.chat_id)))?;
+
+    let title = chat.name();
+    let info = output::ChatInfo {
+        id: chat.id,
+        title,
+        kind: chat.kind,
+        username: chat.username,
+        member_count,
+        is_forum: chat.is_forum.unwrap_or(false),
+    };
+    output::print_chat_info(&mode, &info);
+    Ok(())
+}
+
+/// teledrop chats [--json]
+fn run_chats(args: ChatsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = if args.json { OutputMode::Json } else { OutputMode::Human };
+    let cfg = load_config(&args.config, None)?;
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    let sp = start_spinner("Loading chats...", &mode);
+    let mut chats = Vec::new();
+    let mut failed = Vec::new();
+    for (name, chat_id) in &cfg.chats {
+        let request = RequestGetChat { chat_id };
+        // the bot may have been removed from the chat, the name is still listed then
+        let title = match rt.block_on(api_call::<TelegramChat>(&client, &cfg.api_url(API_GET_CHAT), &request)) {
+            Ok(chat) => chat.display_name(),
+            Err(err) => {
+                failed.push(format!("No title for {} ({}): {}", name, chat_id, err));
+                None
+            }
+        };
+        chats.push(output::ChatResult {
+            name: name.clone(),
+            chat_id: chat_id.clone(),
+            title,
+        });
+    }
+    stop_spinner(sp);
+    for warning in failed {
+        output::print_warning(&warning);
+    }
+    output::print_chats(&mode, &chats);
+    Ok(())
+}
+
+/// teledrop history [--limit <N>] [--json | --count] [FILTERS]
+fn run_history(args: HistoryArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(HistoryCommand::Export(args)) = args.command {
+        return run_history_export(args);
+    }
+    let entries = history::load()?;
+    let matching = entries.iter().rev().filter(|entry| args.filter.matches(entry));
+    if args.count {
+        println!("{}", matching.count());
+        return Ok(());
+    }
+    let newest: Vec<&history::Entry> = matching.take(args.limit).collect();
+    let mode = if args.json { OutputMode::Json } else { OutputMode::Human };
+    output::print_history(&mode, &newest);
+    Ok(())
+}
+
+/// teledrop history export [--format csv|jsonl] [-o <OUTPUT>] [FILTERS]
+fn run_history_export(args: ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = history::load()?;
+    let matching: Vec<&history::Entry> = entries.iter().filter(|entry| args.filter.matches(entry)).collect();
+    match &args.output {
+        Some(output) => {
+            let file = std::fs::File::create(output).map_err(|err| Error::File(format!("{}: {}", output, err)))?;
+            history::export(&matching, args.format, std::io::BufWriter::new(file))?;
+            eprintln!("Exported {} uploads to {}", matching.len(), output);
+        }
+        None => history::export(&matching, args.format, std::io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+/// teledrop queue list|rm
+fn run_queue(command: QueueCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        QueueCommand::List(args) => {
+            let mode = if args.json { OutputMode::Json } else { OutputMode::Human };
+            output::print_queue(&mode, &queue::load()?);
+        }
+        QueueCommand::Rm(args) => {
+            let missing = queue::remove(&args.ids)?;
+            for id in args.ids.iter().filter(|id| !missing.contains(id)) {
+                eprintln!("Removed #{} from the queue", id);
+            }
+            if let Some(id) = missing.first() {
+                return Err(Error::NotFound(format!("Nothing is queued as #{}, `teledrop queue list` shows what is", id)).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// teledrop flush [--include-changed]
+/// every entry is uploaded by running teledrop again with its options, so each gets the config, the passphrase
+/// prompt and the output of a normal upload. The ones that went through are taken out of the queue
+fn run_flush(args: FlushArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = queue::load()?;
+    if entries.is_empty() {
+        eprintln!("Nothing is queued");
+        return Ok(());
+    }
+    let exe = std::env::current_exe()?;
+    let (mut sent, mut left) = (0, 0);
+    let mut exit_code = 0;
+    for entry in &entries {
+        let path = entry.path.display();
+        if std::fs::symlink_metadata(&entry.path).is_err() {
+            output::print_warning(&format!("Skipping #{}, {} is gone (`teledrop queue rm {}` drops it)", entry.id, path, entry.id));
+            left += 1;
+            continue;
+        }
+        if !entry.unchanged() && !args.include_changed {
+            output::print_warning(&format!("Skipping #{}, {} changed since it was queued (--include-changed sends it as it is now)", entry.id, path));
+            left += 1;
+            continue;
+        }
+        eprintln!("Sending #{} {}, queued {}", entry.id, path, entry.queued_at.format("%Y-%m-%d %H:%M"));
+        let dir = if entry.dir.is_dir() { entry.dir.as_path() } else { Path::new(".") };
+        let status = std::process::Command::new(&exe)
+            .arg("upload")
+            .args(&entry.args)
+            .arg("--no-queue")
+            .arg("--")
+            .arg(&entry.path)
+            .current_dir(dir)
+            .status()?;
+        if status.success() {
+            queue::remove(&[entry.id])?;
+            sent += 1;
+        } else {
+            eprintln!("#{} stays queued", entry.id);
+            left += 1;
+            if exit_code == 0 {
+                exit_code = status.code().unwrap_or(1);
+            }
+        }
+    }
+    let msg = format!("{} sent, {} still queued", sent, left);
+    if left > 0 {
+        eprintln!("{}", msg.yellow());
+    } else {
+        eprintln!("{}", msg);
+    }
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+    Ok(())
+}
+
+/// teledrop decrypt <FILE> [-o <OUTPUT>] [--passphrase-file <PATH>]
+fn run_decrypt(args: DecryptArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = args.input;
+    let output = match args.output {
+        Some(output) => output,
+        None => input
+            .strip_suffix(&format!(".{}", crypto::ENCRYPTED_EXTENSION))
+            .ok_or(Error::Usage("Can't guess the output name without the .enc extension, use -o".into()))?
+            .to_string(),
+    };
+    if Path::new(&output).exists() {
+        return Err(Error::File(format!("{} already exists", output)).into());
+    }
+    let reader = std::io::BufReader::new(std::fs::File::open(&input).map_err(|err| Error::File(format!("{}: {}", input, err)))?);
+    let passphrase = read_passphrase(args.passphrase_file.as_deref(), false)?;
+
+    // decrypt into a temp file next to the output, so nothing unverified is left behind on failure
+    let dir = Path::new(&output).parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let tmp = tempfile::NamedTempFile::new_in(dir)?;
+    crypto::decrypt(reader, std::io::BufWriter::new(tmp.reopen()?), &passphrase)?;
+    tmp.persist(&output)?;
+    println!("{} Decrypted into {}", "✔".green(), output);
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // ===== SUBCOMMANDS
+    // parsed before the config, so --help works without one
+    let cli = cli::parse();
+    let mode = cli.command.as_ref().map(Command::output).unwrap_or_default();
+    let res = match cli.command {
+        // join and decrypt work on local files only, no config needed
+        Some(Command::Join(args)) => run_join(args),
+        Some(Command::Decrypt(args)) => run_decrypt(args),
+        Some(Command::History(args)) => run_history(args),
+        Some(Command::Chats(args)) => run_chats(args),
+        Some(Command::ChatInfo(args)) => run_chat_info(args),
+        Some(Command::Config(command)) => run_config(command),
+        Some(Command::Setup) => run_setup(),
+        Some(Command::Doctor(args)) => run_doctor(args),
+        Some(Command::Test(args)) => run_test(args),
+        // these talk to the bot api and load the config themselves
+        Some(Command::Download(args)) => run_download(args),
+        Some(Command::Url(args)) => run_url(args),
+        Some(Command::Info(args)) => run_info(args),
+        Some(Command::Reshare(args)) => run_reshare(args),
+        Some(Command::Delete(args)) => run_delete(args),
+        Some(Command::EditCaption(args)) => run_edit_caption(args),
+        Some(Command::Forward(args)) => run_forward(args),
+        Some(Command::Queue(command)) => run_queue(command),
+        Some(Command::Flush(args)) => run_flush(args),
+        Some(Command::Completions(args)) => {
+            cli::print_completions(args.shell);
+            Ok(())
+        }
+        Some(Command::Man(args)) => cli::print_man(args.subcommand.as_deref()),
+        Some(Command::Upload(args)) => return run_upload(args, None),
+        Some(Command::Watch(args)) => return run_upload(args.upload, Some(Duration::from_secs(args.settle))),
+        None => return run_upload(cli.upload, None),
+    };
+    if let Err(err) = res {
+        let code = error::exit_code(&*err);
+        output::print_error(&mode, None, &err.to_string(), code);
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// a file to upload, with its path relative to the directory --recursive found it in
+struct ListedFile {
+    path: PathBuf,
+    relative: Option<String>,
+}
+
+/// how one file of the batch went
+// one per file and moved right away, boxing the result wouldn't save anything
+#[allow(clippy::large_enum_variant)]
+enum Outcome {
+    // a link with --no-follow
+    Link,
+    // outside of --min-size and --max-size, with its size and why
+    Filtered(u64, String),
+    // uploaded by an earlier run of the batch, with its file_id
+    Resumed(String),
+    Done(Result<UploadResult, Error>),
+}
+
+impl Outcome {
+    /// why the file was left out, none when it was tried
+    fn reason(&self) -> Option<String> {
+        match self {
+            Outcome::Link => Some("link (--no-follow)".to_string()),
+            Outcome::Filtered(_, reason) => Some(reason.clone()),
+            Outcome::Resumed(_) => Some("uploaded before (--resume)".to_string()),
+            Outcome::Done(_) => None,
+        }
+    }
+}
+
+/// the file is left out of the batch before uploading it, the outcome says why
+fn skip_file(cfg: &Config, args: &Args, batch: bool, state: Option<&Mutex<State>>, path: &Path) -> Option<Outcome> {
+    if args.no_follow && path.is_symlink() {
+        return Some(Outcome::Link);
+    }
+    let done = state.filter(|_| args.resume).and_then(|state| state.lock().unwrap().done(path).cloned());
+    if let Some(done) = done {
+        // gone since, e.g. --rm deleted it
+        if done.unchanged(path, args.resume_check) || std::fs::symlink_metadata(path).is_err() {
+            return Some(Outcome::Resumed(done.file_id));
+        }
+        eprintln!("{} changed since it was uploaded, sending it again", path.display());
+    }
+    let size = std::fs::metadata(path).ok().filter(|metadata| metadata.is_file()).map(|metadata| metadata.len())?;
+    if !batch {
+        return None;
+    }
+    // a file over the upload limit is no choice of the filters, it goes on to fail like a single one
+    if size > upload_limit(cfg, args) && !args.split {
+        return None;
+    }
+    match (args.min_size, args.max_size) {
+        (Some(min), _) if size < min => Some(Outcome::Filtered(size, format!("under --min-size {}", DecimalBytes(min)))),
+        (_, Some(max)) if size > max => Some(Outcome::Filtered(size, format!("over --max-size {}", DecimalBytes(max)))),
+        _ => None,
+    }
+}
+
+/// delete a file that was uploaded, for --rm
+/// with `verify` only when telegram reports the size that was sent, for a split file the size of each part
+fn remove_uploaded(path: &Path, result: &UploadResult, verify: bool) -> Result<(), String> {
+    if !result.broadcast_failed.is_empty() {
+        return Err("it wasn't sent to every chat".into());
+    }
+    // a link is removed, not what it points to
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()) {
+        return Err("--rm doesn't remove directories".into());
+    }
+    if verify {
+        let sent = if result.parts.is_empty() { std::slice::from_ref(result) } else { &result.parts };
+        for sent in sent {
+            match sent.reported_size {
+                Some(size) if size == sent.size => {}
+                Some(size) => return Err(format!("Telegram reports {} bytes for {} but {} were sent", size, sent.name, sent.size)),
+                None => return Err(format!("Telegram didn't report the size of {}", sent.name)),
+            }
+        }
+    }
+    std::fs::remove_file(path).map_err(|err| err.to_string())
+}
+
+/// move a file that was uploaded into `dir`, for --move-to, and say where it ended up
+/// a file of a walked directory keeps its relative path, a name that is taken gets a number like report-1.pdf
+fn move_uploaded(path: &Path, relative: Option<&str>, dir: &Path, result: &UploadResult) -> Result<PathBuf, String> {
+    if !result.broadcast_failed.is_empty() {
+        return Err("it wasn't sent to every chat".into());
+    }
+    let to = match (relative, path.file_name()) {
+        (Some(relative), _) => dir.join(relative),
+        (None, Some(name)) => dir.join(name),
+        (None, None) => return Err("it has no file name".into()),
+    };
+    let to = free_path(&to);
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| format!("can't create {}: {}", parent.display(), err))?;
+    }
+    match std::fs::rename(path, &to) {
+        Ok(()) => Ok(to),
+        // another filesystem, copied and removed instead
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices && !path.is_dir() => {
+            let copy = || -> std::io::Result<()> {
+                std::fs::copy(path, &to)?;
+                if let Ok(modified) = std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+                    std::fs::File::options().write(true).open(&to)?.set_modified(modified)?;
+                }
+                std::fs::remove_file(path)
+            };
+            copy().map(|_| to).map_err(|err| err.to_string())
+        }
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// `path`, or the first of path-1, path-2... that doesn't exist, the number goes before the extension
+fn free_path(path: &Path) -> PathBuf {
+    if std::fs::symlink_metadata(path).is_err() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{}-{}{}", stem, n, extension)))
+        .find(|candidate| std::fs::symlink_metadata(candidate).is_err())
+        .unwrap()
+}
+
+/// the files to upload in order, without --recursive a directory stays one entry and is archived
+fn upload_list(args: &Args) -> Result<Vec<ListedFile>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for path in &args.filenames {
+        let walk = args.recursive && path.as_os_str() != STDIN_FILENAME && path.is_dir() && !(args.no_follow && path.is_symlink());
+        if !walk {
+            files.push(ListedFile {
+                path: path.clone(),
+                relative: None,
+            });
+            continue;
+        }
+        let found = archive::files(path, &args.walk()).map_err(|err| Error::File(err.to_string()))?;
+        if found.is_empty() {
+            eprintln!("{} has no files to send", path.display());
+        }
+        for (file, relative) in found {
+            // the same on every platform, it ends up in the caption
+            let relative = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            files.push(ListedFile {
+                path: file,
+                relative: Some(relative),
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// teledrop [upload] <FILE>...
+/// with `watch` the files are the ones that turn up in the directories of args, each sent once it didn't change for that long
+fn run_upload(mut args: Args, watch: Option<Duration>) -> Result<(), Box<dyn std::error::Error>> {
+    colored::control::set_override(args.color.enabled(&args.output));
+    trace::set_level(args.verbose);
+
+    // ===== CONFIG
+    let mut cfg = match load_config(&args.config, args.chat.first().map(String::as_str)) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            output::print_error(&args.output, None, &err.to_string(), error::EXIT_USAGE);
+            std::process::exit(error::EXIT_USAGE);
+        }
+    };
+    // disable_notification is --silent for every upload
+    args.send.silent |= cfg.disable_notification;
+    args.send.protect |= cfg.protect_content;
+    if let Err(err) = cfg.apply_topic(&mut args.send) {
+        output::print_error(&args.output, None, &err.to_string(), error::EXIT_USAGE);
+        std::process::exit(error::EXIT_USAGE);
+    }
+    let mode = &args.output;
+    // the file is uploaded to the first chat and sent to the others by its file_id
+    let chats = if args.chat.is_empty() { &cfg.broadcast } else { &args.chat };
+    let mut other_chats: Vec<String> = Vec::new();
+    for chat in chats {
+        match cfg.resolve_chat(chat) {
+            Ok(chat_id) if !other_chats.contains(&chat_id) => other_chats.push(chat_id),
+            Ok(_) => {}
+            Err(err) => {
+                output::print_error(mode, None, &err.to_string(), error::EXIT_USAGE);
+                std::process::exit(error::EXIT_USAGE);
+            }
+        }
+    }
+    if !other_chats.is_empty() {
+        cfg.chat_id = other_chats.remove(0);
+    }
+    if args.split && !other_chats.is_empty() {
+        let msg = "--split can't be used with several chats";
+        output::print_error(mode, None, msg, error::EXIT_USAGE);
+        std::process::exit(error::EXIT_USAGE);
+    }
+
+    // ask for the passphrase once for all files
+    let passphrase = if args.encrypt && !args.dry_run {
+        match read_passphrase(args.passphrase_file.as_deref(), true) {
+            Ok(passphrase) => Some(passphrase),
+            Err(err) => {
+                let code = error::exit_code(&*err);
+                output::print_error(mode, None, &err.to_string(), code);
+                std::process::exit(code);
+            }
+        }
+    } else {
+        None
+    };
+
+    // create API client
+    let client = cfg.client()?;
+    let rt = Runtime::new()?;
+
+    // ===== UPLOAD FILES
+    // files are uploaded one by one in the given order, with --jobs several at once
+    // while the results are still handled in the given order
+    let mut failed = 0;
+    let mut uploaded = 0;
+    let mut skipped = 0;
+    // left out by --min-size and --max-size, and how big they were together
+    let mut filtered = 0;
+    let mut filtered_size = 0;
+    // done by an earlier run, with --resume
+    let mut resumed = 0;
+    // the first failure decides the exit code
+    let mut exit_code = 0;
+    let mut urls = Vec::new();
+    // checked once, before the first big file, the answer holds for the whole batch
+    let precheck_size = cfg.precheck_size.unwrap_or(PRECHECK_SIZE);
+    let precheck: Mutex<Option<Result<(), (i32, String)>>> = Mutex::new(None);
+    let files = if watch.is_some() { Vec::new() } else { upload_list(&args)? };
+    interrupt::install(&rt, Some(files.len()).filter(|_| watch.is_none()), mode);
+    // --min-size and --max-size only leave files of a batch out, a file over the upload limit fails either way
+    let batch = files.len() > 1 || args.recursive || watch.is_some();
+    // the table at the end of a batch
+    let mut summary = Vec::new();
+    // what the batch uploaded so far, for --resume
+    let state = if (batch || args.resume || args.state.is_some()) && watch.is_none() {
+        let path = match &args.state {
+            Some(path) => path.clone(),
+            None => state::default_path(&cfg.chat_id, &args.filenames)?,
+        };
+        let state = if args.resume {
+            State::load(path)?
+        } else {
+            // overwritten with the first file, only --resume goes on with it
+            let left = State::load(path.clone()).map(|state| state.len()).unwrap_or(0);
+            if left > 0 {
+                eprintln!("An earlier run of this batch stopped after {} files, --resume skips them", left);
+            }
+            State::new(path)
+        };
+        interrupt::state(state.path());
+        Some(Mutex::new(state))
+    } else {
+        None
+    };
+
+    // ===== DRY RUN
+    // the plan goes to stdout like the results would, nothing is sent
+    if args.dry_run {
+        if mode.is_human() {
+            let more = if other_chats.is_empty() { String::new() } else { format!(" and {} more", other_chats.len()) };
+            eprintln!("Dry run, nothing is sent to chat {}{}", cfg.chat_id, more);
+        }
+        let (mut planned, mut left_out) = (0, 0);
+        for ListedFile { path, relative } in &files {
+            let mut plan = match skip_file(&cfg, &args, batch, state.as_ref(), path) {
+                Some(outcome) => {
+                    let mut plan = output::Plan::new(relative.clone().unwrap_or_else(|| path.display().to_string()), cfg.chat_id.clone());
+                    plan.action = output::Action::Skip;
+                    plan.size = local_size(path);
+                    plan.note = outcome.reason();
+                    left_out += 1;
+                    plan
+                }
+                None => plan_file(&rt, &cfg, &args, path, relative.as_deref()).unwrap_or_else(|err| {
+                    let code = error::exit_code(&*err);
+                    if exit_code == 0 {
+                        exit_code = code;
+                    }
+                    failed += 1;
+                    let mut plan = output::Plan::new(relative.clone().unwrap_or_else(|| path.display().to_string()), cfg.chat_id.clone());
+                    plan.action = output::Action::Fail;
+                    plan.note = Some(err.to_string());
+                    plan
+                }),
+            };
+            if plan.action != output::Action::Fail && plan.action != output::Action::Skip {
+                planned += 1;
+            }
+            plan.broadcast = other_chats.clone();
+            output::print_plan(mode, &plan);
+        }
+        if args.verify {
+            let chats: Vec<&str> = std::iter::once(cfg.chat_id.as_str()).chain(other_chats.iter().map(String::as_str)).collect();
+            let sp = start_spinner("Checking the token and the chats...", mode);
+            let res = verify_chats(&rt, &client, &cfg, &chats);
+            stop_spinner(sp);
+            match res {
+                Ok(msg) if mode.is_human() => eprintln!("{}", msg),
+                Ok(_) => {}
+                Err(err) => {
+                    let code = error::exit_code(&*err);
+                    output::print_error(mode, None, &err.to_string(), code);
+                    if exit_code == 0 {
+                        exit_code = code;
+                    }
+                }
+            }
+        }
+        if mode.is_human() {
+            let msg = format!("{} files would be sent, {} left out, {} would fail", planned, left_out, failed);
+            if failed > 0 {
+                eprintln!("{}", msg.red());
+            } else {
+                eprintln!("{}", msg);
+            }
+        }
+        if exit_code != 0 {
+            std::process::exit(exit_code);
+        }
+        return Ok(());
+    }
+
+    // before an upload of size, a single file or a whole album
+    let check_chat = |size: Option<u64>| -> Result<(), Error> {
+        if args.no_precheck || size.is_none_or(|size| size <= precheck_size) {
+            return Ok(());
+        }
+        // the other workers wait for the check instead of doing it again
+        let mut precheck = precheck.lock().unwrap();
+        if precheck.is_none() {
+            let sp = start_spinner("Checking the chat...", mode);
+            *precheck = Some(precheck_chat(&rt, &client, &cfg).map_err(|err| {
+                let code = error::exit_code(&*err);
+                // a refused token fails every upload, skipping the check won't help there
+                let msg = if code == error::EXIT_AUTH { err.to_string() } else { format!("{} (--no-precheck skips this check)", err) };
+                (code, msg)
+            }));
+            stop_spinner(sp);
+        }
+        match &*precheck {
+            Some(Err((code, msg))) => Err(Error::with_code(*code, msg.clone())),
+            _ => Ok(()),
+        }
+    };
+
+    // everything up to the result, this part runs on the workers of --jobs
+    let work = |ListedFile { path, relative }: &ListedFile| -> Outcome {
+        if let Some(outcome) = skip_file(&cfg, &args, batch, state.as_ref(), path) {
+            return outcome;
+        }
+        if let Err(err) = check_chat(local_size(path)) {
+            return Outcome::Done(Err(err));
+        }
+        let res = upload_file(&rt, &client, &cfg, &args, passphrase.as_deref(), path, relative.as_deref(), &other_chats);
+        Outcome::Done(res.map_err(|err| Error::from_boxed(&*err)))
+    };
+
+    // whether the files that fail for the network are queued, asked once for the whole batch
+    let mut queueing = args.queue.then_some(true);
+    let can_ask = !args.no_queue && mode.is_human() && std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+
+    // the command line wins over the config
+    let on_success = args.on_success.as_deref().or(cfg.on_success.as_deref());
+    let on_failure = args.on_failure.as_deref().or(cfg.on_failure.as_deref());
+
+    // print and record the outcome of a file, true when the batch should stop
+    let mut handle = |ListedFile { path, relative }: &ListedFile, outcome: Outcome| -> bool {
+        let filename = &path.display().to_string();
+        // stdin and URLs aren't files here to keep track of, remove or queue
+        let local = filename != STDIN_FILENAME && http_url(path).is_none();
+        let row = |size, status, file_id, error| output::SummaryRow {
+            file: relative.clone().unwrap_or_else(|| filename.clone()),
+            size,
+            status,
+            file_id,
+            error,
+        };
+        let reason = outcome.reason();
+        let res = match outcome {
+            Outcome::Link => {
+                eprintln!("Skipping the link {} (--no-follow)", filename);
+                summary.push(row(None, output::Status::Skipped, None, reason));
+                skipped += 1;
+                return false;
+            }
+            Outcome::Filtered(size, reason) => {
+                eprintln!("Skipping {} ({}), {}", filename, DecimalBytes(size), reason);
+                summary.push(row(Some(size), output::Status::Skipped, None, Some(reason)));
+                filtered += 1;
+                filtered_size += size;
+                skipped += 1;
+                return false;
+            }
+            Outcome::Resumed(file_id) => {
+                eprintln!("Skipping {}, it was uploaded before (--resume)", filename);
+                summary.push(row(local_size(path), output::Status::Skipped, Some(file_id), reason));
+                resumed += 1;
+                skipped += 1;
+                return false;
+            }
+            Outcome::Done(res) => res,
+        };
+        match res.and_then(|result| output::print_result(mode, &result).map(|_| result)) {
+            Ok(result) => {
+                uploaded += 1;
+                interrupt::uploaded(&result.name, &result.file_id);
+                summary.push(row(Some(result.size), output::Status::Ok, Some(result.file_id.clone()), None));
+                // the file is uploaded, the chats it didn't get to only make the exit code non-zero
+                tracing::info!(
+                    file = %filename,
+                    size = result.size,
+                    chat_id = result.chat_id,
+                    message_id = result.message_id,
+                    file_id = %result.file_id,
+                    "uploaded"
+                );
+                for (chat, err) in &result.broadcast_failed {
+                    tracing::warn!(file = %filename, chat = %chat, error = %err, "sending to chat failed");
+                    let code = err.exit_code();
+                    let msg = format!("Sending to chat {} failed: {}", chat, err);
+                    output::print_error(mode, Some(filename), &msg, code);
+                    if exit_code == 0 {
+                        exit_code = code;
+                    }
+                }
+                record_history(&result, Some(filename.as_str()).filter(|f| *f != STDIN_FILENAME));
+                if let (Some(state), true) = (&state, local) {
+                    // the sha256 is the one of the file unless something else was sent
+                    let plain = !path.is_dir() && args.compress.is_none() && !args.encrypt && result.parts.is_empty();
+                    let mut state = state.lock().unwrap();
+                    if let Err(err) = state.record(path, &result, plain) {
+                        output::print_warning(&format!("Warning: can't save the state of the batch to {}: {}", state.path().display(), err));
+                    }
+                }
+                if args.pin || args.pin_silent {
+                    let first = SentMessage {
+                        chat_id: result.chat_id,
+                        message_id: result.message_id,
+                    };
+                    for message in std::iter::once(&first).chain(&result.broadcast) {
+                        pin_message(&rt, &client, &cfg, &result.name, message, args.pin_silent, args.verbose > 0);
+                    }
+                }
+                if let Some(command) = on_success {
+                    let vars = [
+                        ("TELEDROP_FILE", filename.clone()),
+                        ("TELEDROP_FILE_ID", result.file_id.clone()),
+                        ("TELEDROP_URL", result.url.clone().unwrap_or_default()),
+                        ("TELEDROP_MESSAGE_ID", result.message_id.to_string()),
+                        ("TELEDROP_CHAT_ID", result.chat_id.to_string()),
+                        ("TELEDROP_NAME", result.name.clone()),
+                        ("TELEDROP_SIZE", result.size.to_string()),
+                        ("TELEDROP_SHA256", result.sha256.clone().unwrap_or_default()),
+                    ];
+                    if let Err(err) = hook::run(command, &vars) {
+                        output::print_warning(&format!("Warning: --on-success for {}: {}", filename, err));
+                    }
+                }
+                if (args.rm || args.rm_verify) && local {
+                    match remove_uploaded(path, &result, args.rm_verify) {
+                        Ok(()) => eprintln!("Removed {}", filename),
+                        Err(reason) => output::print_warning(&format!("Warning: not removing {}: {}", filename, reason)),
+                    }
+                }
+                if let (Some(dir), true) = (&args.move_to, local) {
+                    match move_uploaded(path, relative.as_deref(), dir, &result) {
+                        Ok(to) => eprintln!("Moved {} to {}", filename, to.display()),
+                        Err(reason) => output::print_warning(&format!("Warning: not moving {}: {}", filename, reason)),
+                    }
+                }
+                // stdout stays for the results when they are meant for scripts
+                if let (true, Some(url)) = (args.qr, &result.url) {
+                    if let Err(reason) = qr::print(url, !mode.is_human()) {
+                        eprintln!("No QR code: {}", reason);
+                    }
+                }
+                urls.extend(result.url);
+                false
+            }
+            Err(err) => {
+                let code = err.exit_code();
+                tracing::error!(file = %filename, code, error = %err, "upload failed");
+                output::print_error(mode, Some(filename), &err.to_string(), code);
+                summary.push(row(local_size(path), output::Status::Failed, None, Some(err.to_string())));
+                if let Some(command) = on_failure {
+                    let vars = [("TELEDROP_FILE", filename.clone()), ("TELEDROP_ERROR", output::redact(&err.to_string()))];
+                    if let Err(err) = hook::run(command, &vars) {
+                        output::print_warning(&format!("Warning: --on-failure for {}: {}", filename, err));
+                    }
+                }
+                if code == error::EXIT_NETWORK && local && !args.no_queue {
+                    if queueing.is_none() {
+                        queueing = Some(can_ask && !CONCURRENT.load(Ordering::Relaxed) && confirm("The network seems to be down, queue the files that fail for `teledrop flush`?"));
+                        if queueing == Some(false) && !can_ask {
+                            eprintln!("--queue keeps the files that fail for the network, `teledrop flush` sends them later");
+                        }
+                    }
+                    if queueing == Some(true) {
+                        let chats = std::iter::once(cfg.chat_id.clone()).chain(other_chats.iter().cloned()).collect();
+                        match queue::add(path, chats, args.replay.clone(), &err.to_string()) {
+                            Ok(id) => eprintln!("Queued {} as #{}, `teledrop flush` sends it once the network is back", filename, id),
+                            Err(err) => output::print_warning(&format!("Warning: can't queue {}: {}", filename, err)),
+                        }
+                    }
+                }
+                if exit_code == 0 {
+                    exit_code = code;
+                }
+                failed += 1;
+                args.fail_fast
+            }
+        }
+    };
+
+    if let Some(settle) = watch {
+        let mut watch = match watch::Watch::new(&args.filenames, args.recursive, args.walk(), args.move_to.as_deref(), settle) {
+            Ok(watch) => watch,
+            Err(err) => {
+                output::print_error(mode, None, &err.to_string(), err.exit_code());
+                std::process::exit(err.exit_code());
+            }
+        };
+        watch::stop_on_sigterm(&rt);
+        for dir in watch.dirs() {
+            watch::log(&format!("Watching {} for new files", dir.display()));
+        }
+        while let Some((path, relative)) = watch.next() {
+            let file = ListedFile { path, relative };
+            watch::log(&format!("Sending {}", file.path.display()));
+            let outcome = work(&file);
+            // the network may be back later, anything else would fail again
+            if let Outcome::Done(Err(err)) = &outcome {
+                if err.exit_code() == error::EXIT_NETWORK {
+                    let wait = watch.again(file.path.clone());
+                    watch::log(&format!("Sending {} failed: {}, trying again in {}s", file.path.display(), err, wait.as_secs()).yellow().to_string());
+                    continue;
+                }
+            }
+            watch.done(&file.path);
+            if handle(&file, outcome) {
+                break;
+            }
+        }
+        watch::log(&format!("Stopped watching, {} files uploaded, {} failed", uploaded, failed));
+        return Ok(());
+    }
+
+    let jobs = usize::from(args.jobs).min(files.len());
+    if args.album {
+        // the files that can share an album are gathered where the first of them is, up to 10 in one
+        // the others, and an album that got only one file, are uploaded as usual
+        enum Step<'a> {
+            File(&'a ListedFile),
+            Album(Vec<(&'a ListedFile, String, SendMethod)>),
+        }
+        let mut steps = Vec::new();
+        // the step of the album that is filled for each kind
+        let mut filling: BTreeMap<&str, usize> = BTreeMap::new();
+        for file in &files {
+            let found = skip_file(&cfg, &args, batch, state.as_ref(), &file.path).is_none().then(|| album_file(&rt, &cfg, &args, &file.path)).flatten();
+            let Some((kind, mime_type, method)) = found else {
+                steps.push(Step::File(file));
+                continue;
+            };
+            let step = match filling.get(kind) {
+                Some(&step) if matches!(&steps[step], Step::Album(album) if album.len() < ALBUM_SIZE) => step,
+                _ => {
+                    steps.push(Step::Album(Vec::new()));
+                    filling.insert(kind, steps.len() - 1);
+                    steps.len() - 1
+                }
+            };
+            if let Step::Album(album) = &mut steps[step] {
+                album.push((file, mime_type, method));
+            }
+        }
+        for step in steps {
+            let stop = match step {
+                Step::File(file) => handle(file, work(file)),
+                Step::Album(album) if album.len() == 1 => handle(album[0].0, work(album[0].0)),
+                Step::Album(album) => match check_chat(album.iter().map(|(file, _, _)| local_size(&file.path)).sum())
+                    .map_err(|err| err.into())
+                    .and_then(|()| upload_album(&rt, &client, &cfg, &args, &album, &other_chats))
+                {
+                    // every file is handled, the album is uploaded already when --fail-fast stops
+                    Ok(results) => album.iter().zip(results).fold(false, |stop, ((file, _, _), result)| handle(file, Outcome::Done(Ok(result))) || stop),
+                    Err(err) => album.iter().fold(false, |stop, (file, _, _)| handle(file, Outcome::Done(Err(Error::from_boxed(&*err)))) || stop),
+                },
+            };
+            if stop {
+                break;
+            }
+        }
+    } else if jobs <= 1 {
+        for file in &files {
+            if handle(file, work(file)) {
+                break;
+            }
+        }
+    } else {
+        CONCURRENT.store(true, Ordering::Relaxed);
+        let pb = batch_progress_bar(files.len() as u64, mode);
+        // the names of the files uploading right now, for the bar
+        let current: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let (tx, rx) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for _ in 0..jobs {
+                let (tx, work, pb, current, next, stop, files) = (tx.clone(), &work, &pb, &current, &next, &stop, &files);
+                scope.spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(file) = files.get(index) else { break };
+                        let shown = file.relative.clone().unwrap_or_else(|| file.path.display().to_string());
+                        let set_message = |current: &[String]| pb.set_message(current.join(", "));
+                        {
+                            let mut current = current.lock().unwrap();
+                            current.push(shown.clone());
+                            set_message(&current);
+                        }
+                        let outcome = work(file);
+                        {
+                            let mut current = current.lock().unwrap();
+                            current.retain(|name| *name != shown);
+                            set_message(&current);
+                        }
+                        pb.inc(1);
+                        if tx.send((index, outcome)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(tx);
+            // results that came in before the ones of earlier files
+            let mut waiting = BTreeMap::new();
+            let mut shown = 0;
+            for (index, outcome) in rx {
+                waiting.insert(index, outcome);
+                while let Some(outcome) = waiting.remove(&shown) {
+                    // the files uploading already are still handled, --fail-fast only stops new ones
+                    if pb.suspend(|| handle(&files[shown], outcome)) {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    shown += 1;
+                }
+            }
+        });
+        pb.finish_and_clear();
+        CONCURRENT.store(false, Ordering::Relaxed);
+    }
+    // one url per line when several files were uploaded
+    if args.copy {
+        if urls.is_empty() {
+            eprintln!("{}", "Nothing copied to the clipboard, there is no download URL".yellow());
+        } else if let Err(err) = clipboard::copy(&urls.join("\n")) {
+            eprintln!("{}", format!("Warning: {}", err).yellow());
+        } else if mode.is_human() {
+            eprintln!("Download URL copied to the clipboard");
+        }
+    }
+
+    if filtered > 0 && mode.is_human() {
+        eprintln!("Skipped {} files ({}) outside of --min-size and --max-size", filtered, DecimalBytes(filtered_size));
+    }
+    if resumed > 0 && mode.is_human() {
+        eprintln!("Skipped {} files an earlier run uploaded (--resume)", resumed);
+    }
+    if batch {
+        output::print_summary(mode, &summary, pace::total());
+    }
+    if let Some(state) = state.map(|state| state.into_inner().unwrap()) {
+        if failed == 0 && exit_code == 0 && !args.keep_state {
+            if let Err(err) = state.remove() {
+                output::print_warning(&format!("Warning: can't remove the state of the batch {}: {}", state.path().display(), err));
+            }
+        } else if state.len() > 0 {
+            eprintln!("What the batch uploaded is kept in {}, --resume skips it next time", state.path().display());
+        }
+    }
+    if let (Some(listed), true) = (args.listed, mode.is_human()) {
+        let msg = format!("{} read from the list, {} uploaded, {} skipped, {} failed", listed, uploaded, skipped, failed);
+        if failed > 0 {
+            eprintln!("{}", msg.red());
+        } else {
+            eprintln!("{}", msg);
+        }
+    } else if failed > 0 && files.len() > 1 && mode.is_human() {
+        let msg = format!("{} of {} files failed", failed, files.len());
+        eprintln!("{}", msg.red());
+    }
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a sendDocument response as telegram sends it, with the chat of the test
+    fn sent_document(chat: &str, from: &str) -> String {
+        format!(
+            r#"{{"ok":true,"result":{{"message_id":1206,{}"chat":{},"date":1714213125,"document":{{"file_name":"report.pdf","mime_type":"application/pdf","file_id":"BQACAgIAAxkDAAIEtmYt3","file_unique_id":"AgADtk0AAmPSaUk","file_size":48213}}}}}}"#,
+            from, chat
+        )
+    }
+
+    const FROM_BOT: &str = r#""from":{"id":6021548839,"is_bot":true,"first_name":"Drop Bot","username":"drop_bot"},"#;
+
+    fn chat_name(chat: &str, from: &str) -> Option<String> {
+        parse_sent_document(&sent_document(chat, from), false).unwrap().chat.display_name()
+    }
+
+    #[test]
+    fn private_chat_shows_the_name_of_the_user() {
+        let chat = r#"{"id":183495103,"first_name":"Ann","last_name":"Lee","username":"annlee","type":"private"}"#;
+        assert_eq!(chat_name(chat, FROM_BOT).as_deref(), Some("Ann Lee"));
+    }
+
+    #[test]
+    fn group_shows_its_title() {
+        let chat = r#"{"id":-4125032611,"title":"Family","type":"group","all_members_are_administrators":true}"#;
+        assert_eq!(chat_name(chat, FROM_BOT).as_deref(), Some("Family"));
+    }
+
+    #[test]
+    fn supergroup_shows_its_title() {
+        let chat = r#"{"id":-1002093113545,"title":"Ops","username":"ops_team","type":"supergroup","is_forum":true}"#;
+        assert_eq!(chat_name(chat, FROM_BOT).as_deref(), Some("Ops"));
+    }
+
+    #[test]
+    fn channel_shows_its_title() {
+        // posts in a channel have a sender_chat instead of from
+        let chat = r#"{"id":-1001973862575,"title":"Releases","username":"releases","type":"channel"}"#;
+        let sender = r#""sender_chat":{"id":-1001973862575,"title":"Releases","username":"releases","type":"channel"},"#;
+        assert_eq!(chat_name(chat, sender).as_deref(), Some("Releases"));
+    }
+
+    #[test]
+    fn sender_without_username_still_parses() {
+        let chat = r#"{"id":183495103,"first_name":"Ann","type":"private"}"#;
+        let from = r#""from":{"id":6021548839,"is_bot":true,"first_name":"Drop Bot"},"#;
+        let sent = parse_sent_document(&sent_document(chat, from), false).unwrap();
+        assert_eq!(sent.document.file_id, "BQACAgIAAxkDAAIEtmYt3");
+        assert_eq!(sent.message_id, 1206);
+    }
+
+    #[test]
+    fn user_without_username_still_parses() {
+        let body = r#"{"ok":true,"result":{"id":6021548839,"is_bot":true,"first_name":"Drop Bot","can_join_groups":true}}"#;
+        let response: TelegramResponse<TelegramUser> = serde_json::from_str(body).unwrap();
+        let user = response.result.unwrap();
+        assert_eq!((user.id, user.first_name.as_str(), user.username), (6021548839, "Drop Bot", None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn name_that_is_not_utf8_is_sent_with_replacement_characters() {
+        use std::os::unix::ffi::OsStrExt;
+        // "café ÿ.txt" in latin-1, as an old zip or samba share leaves it
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/old/caf\xe9 \xff.txt"));
+        assert_eq!(upload_name(path, None), "caf\u{FFFD} \u{FFFD}.txt");
+        // --name is used as given
+        assert_eq!(upload_name(path, Some("cafe.txt")), "cafe.txt");
+    }
+
+    #[test]
+    fn long_multibyte_name_is_cut_at_a_char_and_keeps_the_extension() {
+        // 2, 3 and 4 byte characters, NAME_LIMIT - ".pdf" falls inside one of them
+        assert_eq!(sanitize_name(&format!("{}.pdf", "я".repeat(200))), format!("{}.pdf", "я".repeat(125)));
+        assert_eq!(sanitize_name(&format!("{}.docx", "文".repeat(100))), format!("{}.docx", "文".repeat(83)));
+        // every offset of the cut into a 4 byte emoji
+        for pad in 0..4 {
+            let stem = format!("{}{}", "a".repeat(pad), "😀".repeat(70));
+            let sanitized = sanitize_name(&format!("{}.pdf", stem));
+            assert!(sanitized.len() <= NAME_LIMIT && sanitized.len() > NAME_LIMIT - 4, "{} bytes", sanitized.len());
+            let kept = sanitized.strip_suffix(".pdf").expect("the extension is kept");
+            assert!(stem.starts_with(kept), "{}", sanitized);
+        }
+        // names that fit are left alone
+        let fits = format!("{}.pdf", "я".repeat(125));
+        assert_eq!(sanitize_name(&fits), fits);
+    }
+
+    fn refused(description: &str) -> TelegramError {
+        TelegramError {
+            error_code: Some(400),
+            description: Some(description.to_string()),
+            parameters: None,
+        }
+    }
+
+    #[test]
+    fn protect_hint_only_when_protect_content_was_sent() {
+        let hint = "send it without --protect";
+        let err = refused("Bad Request: protect_content is not allowed in this chat");
+        assert!(err.to_send_error(true).to_string().contains(hint), "{}", err.to_send_error(true));
+        assert!(!err.to_send_error(false).to_string().contains(hint), "{}", err.to_send_error(false));
+        // a description that only happens to have the word in it, without --protect
+        let err = refused("Bad Request: can't send a message to a protected chat");
+        assert!(!err.to_send_error(false).to_string().contains(hint), "{}", err.to_send_error(false));
+        assert!(!err.to_error().to_string().contains(hint), "{}", err.to_error());
+    }
+
+    #[test]
+    fn other_refusals_keep_their_hints_with_protect() {
+        let err = refused("Bad Request: chat not found");
+        assert_eq!(error::exit_code(&err.to_send_error(true)), error::EXIT_CHAT_NOT_FOUND);
+    }
 }
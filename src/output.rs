@@ -0,0 +1,658 @@
+//! Printing upload results
+//! human readable lines by default, or one json object per file for scripts
+//!
+//! json shape, one line per uploaded file:
+//! {"name", "size", "sha256", "file_id", "file_unique_id", "message_id", "chat_id", "chat_name", "duration_secs", "url"}
+//! chat_name is the title of a group or channel or the name of a user, left out when the chat has neither
+//! url is null when there is no download link, sha256 for files sent again with `teledrop reshare`, split files also get a "parts" array of the same objects
+//! and their file_id, file_unique_id and message_id belong to the manifest
+//! files sent to several chats get a "broadcast" array with {"chat_id", "message_id"} for every chat after the first
+//! errors are printed to stderr as {"file", "error", "exit_code"}
+//! after several files a summary goes to stderr too, an aligned table or with --json one line:
+//! {"summary": [{"file", "size", "status", "file_id", "error"}], "rate_limit_wait_secs"}, status is ok, failed or skipped
+//! rate_limit_wait_secs is how long the batch waited for the rate limit of telegram, apart from the duration_secs of the files
+//!
+//! --dry-run prints what would happen to each file instead, with --json one line per file:
+//! {"file", "action", "name", "size", "mime_type", "caption", "chat_id", "broadcast", "note"}
+//! action is upload, resend (by the file_id of an earlier upload), split, skip or fail, note says why
+//! size and mime_type are null when they are only known once the file is packed, compressed or read from stdin
+//!
+//! --format renders a template per file instead, with the same fields as placeholders
+//! --quiet prints just the download url, a file without one counts as failed
+//!
+//! `teledrop url` and `teledrop info` print a file already on the servers from getFile the same ways, json shape:
+//! {"file_id", "file_unique_id", "size", "file_path", "url"}
+//! info adds a "history" object with the upload from the local history, when there is one
+//!
+//! `teledrop history --json` prints the history entries, one per line:
+//! {"id", "uploaded_at", "path", "name", "size", "sha256", "chat_id", "message_id", "file_id", "file_unique_id"}
+//! plus "broadcast" like for uploads when the file was sent to several chats
+//!
+//! the bot token is replaced with bot*** in errors and warnings, download urls are the only place it's printed
+//!
+//! `teledrop chats --json` prints the [chats] of the config the same way, {"name", "chat_id", "title"}
+//! title is null when getChat failed
+//! `teledrop chat-info --json` prints {"id", "title", "type", "username", "member_count", "is_forum"}
+//! member_count is null for private chats
+
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use colored::Colorize;
+use indicatif::DecimalBytes;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::history::Entry;
+
+// the bot token of this run, see redact
+static TOKEN: OnceLock<String> = OnceLock::new();
+// the note that download urls contain the token is shown once per run
+static TOKEN_NOTE_SHOWN: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum OutputMode {
+    #[default]
+    Human,
+    Json,
+    // template with FORMAT_PLACEHOLDERS
+    Format(String),
+    // download url only
+    Quiet,
+}
+
+pub const FORMAT_PLACEHOLDERS: &[&str] = &[
+    "file_id",
+    "file_unique_id",
+    "message_id",
+    "chat_id",
+    "url",
+    "name",
+    "size",
+    "sha256",
+];
+
+// fields of a file from getFile
+pub const FILE_FORMAT_PLACEHOLDERS: &[&str] = &["file_id", "file_unique_id", "size", "file_path", "url"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// results go to stdout and messages to stderr, auto only colors when both are terminals
+    /// output for scripts is never colored
+    pub fn enabled(&self, mode: &OutputMode) -> bool {
+        if !mode.is_human() {
+            return false;
+        }
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+                !no_color && std::io::stdout().is_terminal() && std::io::stderr().is_terminal()
+            }
+        }
+    }
+}
+
+impl OutputMode {
+    /// progress bars, spinners and colors are only for humans
+    pub fn is_human(&self) -> bool {
+        *self == OutputMode::Human
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadResult {
+    pub name: String,
+    pub size: u64,
+    // not known for files sent again by file_id
+    pub sha256: Option<String>,
+    pub file_id: String,
+    pub file_unique_id: String,
+    // the method it was sent with, its file_id only works with that one
+    pub sent_as: crate::SendMethod,
+    // sent with disable_notification, --silent
+    pub silent: bool,
+    // sent with protect_content, --protect
+    pub protected: bool,
+    pub message_id: i64,
+    pub chat_id: i64,
+    // title of a group or channel, name of a user, as telegram sent it back
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chat_name: Option<String>,
+    // the message it replies to, --reply-to
+    #[serde(rename = "reply_to_message_id", skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<i64>,
+    pub duration_secs: f64,
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<UploadResult>,
+    // the other chats the file was sent to by its file_id
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub broadcast: Vec<SentMessage>,
+    // chats it couldn't be sent to, printed as errors
+    #[serde(skip)]
+    pub broadcast_failed: Vec<(String, Error)>,
+    // the size telegram reports for the file, for --rm-verify
+    #[serde(skip)]
+    pub reported_size: Option<u64>,
+}
+
+/// a message with the file in one of the chats
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SentMessage {
+    pub chat_id: i64,
+    pub message_id: i64,
+}
+
+/// a file on the telegram servers, with a fresh download url
+#[derive(Debug, Serialize)]
+pub struct FileResult {
+    pub file_id: String,
+    pub file_unique_id: String,
+    // telegram leaves it out when it's not known
+    pub size: Option<u64>,
+    pub file_path: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<Entry>,
+}
+
+/// a chat name from the config
+#[derive(Debug, Serialize)]
+pub struct ChatResult {
+    pub name: String,
+    pub chat_id: String,
+    pub title: Option<String>,
+}
+
+/// a chat from getChat
+#[derive(Debug, Serialize)]
+pub struct ChatInfo {
+    pub id: i64,
+    pub title: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub username: Option<String>,
+    pub member_count: Option<u64>,
+    pub is_forum: bool,
+}
+
+#[derive(Serialize)]
+struct ErrorResult<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<&'a str>,
+    error: String,
+    exit_code: i32,
+}
+
+/// remember the token so redact can hide it, the first one set stays
+pub fn hide_token(token: &str) {
+    if !token.is_empty() {
+        let _ = TOKEN.set(token.to_string());
+    }
+}
+
+/// replace the bot token with *** in text for stderr, e.g. an error with a request url in it
+/// urls have it as bot<token>, a token on its own is only replaced when it looks like one
+pub fn redact(text: &str) -> String {
+    let Some(token) = TOKEN.get() else {
+        return text.to_string();
+    };
+    let text = text.replace(&format!("bot{}", token), "bot***");
+    if token.contains(':') {
+        text.replace(token.as_str(), "***")
+    } else {
+        text
+    }
+}
+
+/// print a warning to stderr, redacted
+pub fn print_warning(msg: &str) {
+    eprintln!("{}", redact(msg).yellow());
+}
+
+/// download urls are the real link, which has the token in it
+fn print_token_note() {
+    if !TOKEN_NOTE_SHOWN.swap(true, Ordering::Relaxed) {
+        eprintln!("{}", "The download URL contains your bot token, don't share it publicly".dimmed());
+    }
+}
+
+/// turn \t, \n and \\ into the characters, like printf does
+pub fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn render_format(template: &str, value: impl Fn(&str) -> String) -> String {
+    let rendered = crate::template::render(template, |key| Ok::<_, String>(value(key)));
+    rendered.unwrap_or_default()
+}
+
+fn render_upload_format(template: &str, result: &UploadResult) -> String {
+    render_format(template, |key| match key {
+        "file_id" => result.file_id.clone(),
+        "file_unique_id" => result.file_unique_id.clone(),
+        "message_id" => result.message_id.to_string(),
+        "chat_id" => result.chat_id.to_string(),
+        // empty when there is no download link
+        "url" => result.url.clone().unwrap_or_default(),
+        "name" => result.name.clone(),
+        "size" => result.size.to_string(),
+        "sha256" => result.sha256.clone().unwrap_or_default(),
+        _ => unreachable!("placeholders are validated when parsing args"),
+    })
+}
+
+/// print the result of an upload to stdout
+/// fails when the mode needs something the result doesn't have, nothing is printed then
+pub fn print_result(mode: &OutputMode, result: &UploadResult) -> Result<(), Error> {
+    match mode {
+        OutputMode::Human => {
+            for part in &result.parts {
+                println!("{} {} File ID: {}", "✔".green(), part.name, part.file_id);
+            }
+            if result.parts.is_empty() {
+                println!("{} File ID: {}", "✔".green(), result.file_id);
+            } else {
+                println!("{} {} File ID: {}", "✔".green(), crate::split::manifest_name(&result.name), result.file_id);
+            }
+            if let Some(sha256) = &result.sha256 {
+                println!("SHA-256: {}", sha256);
+            }
+            if !result.sent_as.is_document() {
+                println!("Sent as {}", result.sent_as.describe());
+            }
+            if result.silent {
+                println!("Sent silently, without a notification");
+            }
+            if result.protected {
+                println!("Protected, it can't be forwarded or saved");
+            }
+            if let Some(chat_name) = &result.chat_name {
+                println!("Chat: {} ({})", chat_name, result.chat_id);
+            }
+            // needed to delete or edit the message later
+            println!("Message ID: {}", result.message_id);
+            if let Some(reply_to) = result.reply_to {
+                println!("Reply to message {}", reply_to);
+            }
+            for sent in &result.broadcast {
+                println!("{} Sent to chat {}, Message ID: {}", "✔".green(), sent.chat_id, sent.message_id);
+            }
+            if !result.broadcast_failed.is_empty() {
+                let chats = 1 + result.broadcast.len() + result.broadcast_failed.len();
+                println!("Sent to {} of {} chats", 1 + result.broadcast.len(), chats);
+            } else if !result.broadcast.is_empty() {
+                println!("Sent to all {} chats", 1 + result.broadcast.len());
+            }
+            if let Some(url) = &result.url {
+                println!("{} Download URL (valid for 1 hour):\n{}", "✔".green(), url.green());
+                print_token_note();
+            }
+        }
+        OutputMode::Json => println!("{}", serde_json::to_string(result).unwrap()),
+        OutputMode::Format(template) => println!("{}", render_upload_format(template, result)),
+        OutputMode::Quiet => match &result.url {
+            Some(url) => println!("{}", url),
+            None => return Err(Error::Api(format!("No download URL for {}", result.name))),
+        },
+    }
+    Ok(())
+}
+
+/// print a file from getFile to stdout
+pub fn print_file(mode: &OutputMode, file: &FileResult) {
+    match mode {
+        OutputMode::Human => {
+            println!("{} Download URL (valid for 1 hour):\n{}", "✔".green(), file.url.green());
+            print_token_note();
+        }
+        OutputMode::Json => println!("{}", serde_json::to_string(file).unwrap()),
+        OutputMode::Format(template) => println!(
+            "{}",
+            render_format(template, |key| match key {
+                "file_id" => file.file_id.clone(),
+                "file_unique_id" => file.file_unique_id.clone(),
+                // empty when telegram didn't say
+                "size" => file.size.map(|size| size.to_string()).unwrap_or_default(),
+                "file_path" => file.file_path.clone(),
+                "url" => file.url.clone(),
+                _ => unreachable!("placeholders are validated when parsing args"),
+            })
+        ),
+        OutputMode::Quiet => println!("{}", file.url),
+    }
+}
+
+/// print the details of a file from getFile to stdout, the same as print_file except for humans
+pub fn print_info(mode: &OutputMode, file: &FileResult) {
+    if !mode.is_human() {
+        return print_file(mode, file);
+    }
+    println!("File ID:        {}", file.file_id);
+    println!("File unique ID: {}", file.file_unique_id);
+    match file.size {
+        Some(size) => println!("Size:           {} ({} bytes)", DecimalBytes(size), size),
+        None => println!("Size:           unknown"),
+    }
+    println!("File path:      {}", file.file_path);
+    if let Some(entry) = &file.history {
+        println!("Name:           {}", entry.name);
+        println!("Uploaded:       {} (#{})", entry.uploaded_at.format("%Y-%m-%d %H:%M:%S"), entry.id);
+        println!("Chat:           {} (message {})", entry.chat_id, entry.message_id);
+        if let Some(path) = &entry.path {
+            println!("From:           {}", path);
+        }
+    }
+}
+
+/// print history entries as a table, or one json object per line
+pub fn print_history(mode: &OutputMode, entries: &[&Entry]) {
+    if !mode.is_human() {
+        for entry in entries {
+            println!("{}", serde_json::to_string(entry).unwrap());
+        }
+        return;
+    }
+    if entries.is_empty() {
+        eprintln!("No uploads found");
+        return;
+    }
+    let id_width = entries.iter().map(|e| e.id.to_string().len() + 1).max().unwrap_or(0).max(2);
+    let name_width = entries.iter().map(|e| e.name.chars().count()).max().unwrap_or(0).max(4);
+    println!(
+        "{:>id_width$}  {:<16}  {:>10}  {:<name_width$}  File ID",
+        "ID", "Uploaded", "Size", "Name"
+    );
+    for entry in entries {
+        println!(
+            "{:>id_width$}  {:<16}  {:>10}  {:<name_width$}  {}",
+            format!("#{}", entry.id),
+            entry.uploaded_at.format("%Y-%m-%d %H:%M").to_string(),
+            DecimalBytes(entry.size).to_string(),
+            entry.name,
+            entry.file_id
+        );
+    }
+}
+
+/// print the queued uploads as a table, or one json object per line
+pub fn print_queue(mode: &OutputMode, entries: &[crate::queue::Entry]) {
+    if !mode.is_human() {
+        for entry in entries {
+            println!("{}", serde_json::to_string(entry).unwrap());
+        }
+        return;
+    }
+    if entries.is_empty() {
+        eprintln!("Nothing is queued");
+        return;
+    }
+    let id_width = entries.iter().map(|e| e.id.to_string().len() + 1).max().unwrap_or(0).max(2);
+    let path_width = entries.iter().map(|e| e.path.display().to_string().chars().count()).max().unwrap_or(0).max(4);
+    println!("{:>id_width$}  {:<16}  {:>10}  {:<path_width$}  Chat", "ID", "Queued", "Size", "File");
+    for entry in entries {
+        // flush leaves these alone
+        let note = if std::fs::symlink_metadata(&entry.path).is_err() {
+            "  (gone)".red().to_string()
+        } else if !entry.unchanged() {
+            "  (changed)".yellow().to_string()
+        } else {
+            String::new()
+        };
+        println!(
+            "{:>id_width$}  {:<16}  {:>10}  {:<path_width$}  {}{}",
+            format!("#{}", entry.id),
+            entry.queued_at.format("%Y-%m-%d %H:%M").to_string(),
+            DecimalBytes(entry.size).to_string(),
+            entry.path.display().to_string(),
+            entry.chats.join(", "),
+            note
+        );
+    }
+}
+
+/// print the chat names as a table, or one json object per line
+pub fn print_chats(mode: &OutputMode, chats: &[ChatResult]) {
+    if !mode.is_human() {
+        for chat in chats {
+            println!("{}", serde_json::to_string(chat).unwrap());
+        }
+        return;
+    }
+    if chats.is_empty() {
+        eprintln!("No chats in the config, add a [chats] table like family = '-1001234567890'");
+        return;
+    }
+    let name_width = chats.iter().map(|c| c.name.chars().count()).max().unwrap_or(0).max(4);
+    let id_width = chats.iter().map(|c| c.chat_id.len()).max().unwrap_or(0).max(7);
+    println!("{:<name_width$}  {:<id_width$}  Title", "Name", "Chat ID");
+    for chat in chats {
+        println!("{:<name_width$}  {:<id_width$}  {}", chat.name, chat.chat_id, chat.title.as_deref().unwrap_or("-"));
+    }
+}
+
+/// print the details of a chat to stdout
+pub fn print_chat_info(mode: &OutputMode, chat: &ChatInfo) {
+    if !mode.is_human() {
+        println!("{}", serde_json::to_string(chat).unwrap());
+        return;
+    }
+    println!("Chat ID:  {}", chat.id);
+    if let Some(title) = &chat.title {
+        println!("Title:    {}", title);
+    }
+    println!("Type:     {}", chat.kind.as_deref().unwrap_or("unknown"));
+    if let Some(username) = &chat.username {
+        println!("Username: @{}", username);
+    }
+    if let Some(count) = chat.member_count {
+        println!("Members:  {}", count);
+    }
+    if chat.is_forum {
+        println!("Forum:    yes, messages go to topics, --topic picks one (the Bot API can't list them)");
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+/// how one file of a batch went, for the summary at the end
+#[derive(Debug, Serialize)]
+pub struct SummaryRow {
+    pub file: String,
+    pub size: Option<u64>,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    // why it failed or was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    summary: &'a [SummaryRow],
+    rate_limit_wait_secs: f64,
+}
+
+/// the summary of a batch on stderr, stdout has the results already
+/// `waited` is the time spent on the rate limit, pacing and 429s
+pub fn print_summary(mode: &OutputMode, rows: &[SummaryRow], waited: Duration) {
+    match mode {
+        OutputMode::Json => {
+            let summary = Summary {
+                summary: rows,
+                rate_limit_wait_secs: waited.as_secs_f64(),
+            };
+            eprintln!("{}", redact(&serde_json::to_string(&summary).unwrap()));
+        }
+        OutputMode::Quiet => {}
+        OutputMode::Human | OutputMode::Format(_) => {
+            let sizes: Vec<String> = rows.iter().map(|row| row.size.map(|size| DecimalBytes(size).to_string()).unwrap_or_default()).collect();
+            let file_width = rows.iter().map(|row| row.file.chars().count()).max().unwrap_or(0);
+            let size_width = sizes.iter().map(|size| size.len()).max().unwrap_or(0);
+            for (row, size) in rows.iter().zip(&sizes) {
+                // padded before coloring, the escape codes would count as width
+                let status = match row.status {
+                    Status::Ok => format!("{:<7}", "ok").green(),
+                    Status::Failed => format!("{:<7}", "failed").red(),
+                    Status::Skipped => format!("{:<7}", "skipped").yellow(),
+                };
+                let detail = row.file_id.as_deref().or(row.error.as_deref()).unwrap_or_default();
+                eprintln!("{:<file_width$}  {:>size_width$}  {}  {}", row.file, size, status, redact(detail));
+            }
+            if !waited.is_zero() {
+                eprintln!("Waited {:.0}s in total to respect the rate limit", waited.as_secs_f64());
+            }
+        }
+    }
+}
+
+/// what --dry-run would do with a file
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Upload,
+    Resend,
+    Split,
+    Skip,
+    Fail,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Upload => "upload",
+            Action::Resend => "resend",
+            Action::Split => "split",
+            Action::Skip => "skip",
+            Action::Fail => "fail",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Plan {
+    pub file: String,
+    pub action: Action,
+    pub name: Option<String>,
+    pub size: Option<u64>,
+    pub mime_type: Option<String>,
+    pub caption: Option<String>,
+    pub chat_id: String,
+    // the other chats it's sent to by its file_id
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub broadcast: Vec<String>,
+    // the earlier upload, the parts, why it's skipped or fails
+    pub note: Option<String>,
+}
+
+impl Plan {
+    /// an upload of the file with nothing known about it yet
+    pub fn new(file: String, chat_id: String) -> Self {
+        Plan {
+            file,
+            action: Action::Upload,
+            name: None,
+            size: None,
+            mime_type: None,
+            caption: None,
+            chat_id,
+            broadcast: Vec::new(),
+            note: None,
+        }
+    }
+}
+
+/// print what --dry-run would do with a file, to stdout like the results
+pub fn print_plan(mode: &OutputMode, plan: &Plan) {
+    if *mode == OutputMode::Json {
+        println!("{}", redact(&serde_json::to_string(plan).unwrap()));
+        return;
+    }
+    // padded before coloring, the escape codes would count as width
+    let action = format!("{:<7}", plan.action.as_str());
+    let action = match plan.action {
+        Action::Upload => action.green(),
+        Action::Resend | Action::Split => action.cyan(),
+        Action::Skip => action.yellow(),
+        Action::Fail => action.red(),
+    };
+    let mut details = Vec::new();
+    if let Some(name) = plan.name.as_deref().filter(|name| Some(*name) != Path::new(&plan.file).file_name().and_then(|n| n.to_str())) {
+        details.push(format!("as {}", name));
+    }
+    if let Some(size) = plan.size {
+        details.push(DecimalBytes(size).to_string());
+    }
+    details.extend(plan.mime_type.clone());
+    if details.is_empty() {
+        println!("{}{}", action, plan.file);
+    } else {
+        println!("{}{} ({})", action, plan.file, details.join(", "));
+    }
+    if let Some(caption) = &plan.caption {
+        println!("       caption: {}", redact(caption).replace('\n', "\n                "));
+    }
+    if let Some(note) = &plan.note {
+        let note = redact(note);
+        match plan.action {
+            Action::Fail => println!("       {}", note.red()),
+            _ => println!("       {}", note),
+        }
+    }
+}
+
+/// print an error to stderr, for a file or for the whole run
+pub fn print_error(mode: &OutputMode, file: Option<&str>, err: &str, exit_code: i32) {
+    let err = &redact(err);
+    match mode {
+        OutputMode::Human | OutputMode::Format(_) | OutputMode::Quiet => match file {
+            Some(file) => eprintln!("{} {}: {}", "✘".red(), file, err.red()),
+            None => eprintln!("{} {}", "✘".red(), err.red()),
+        },
+        OutputMode::Json => {
+            let result = ErrorResult {
+                file,
+                error: err.to_string(),
+                exit_code,
+            };
+            eprintln!("{}", serde_json::to_string(&result).unwrap());
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Spacing the messages sent to a chat, so a batch doesn't run into 429 Too Many Requests
+//! telegram allows a bot about one message a second in a private chat and 20 a minute in a group
+//! --rate or rate in the config sets the messages per minute, every chat is paced on its own
+//! the first message to a chat never waits, what the pacing and 429s waited is added up for the summary
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const RATE_PRIVATE: u32 = 60;
+pub const RATE_GROUP: u32 = 20;
+
+// when the next message may go to each chat
+static TURNS: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+static WAITED: Mutex<Duration> = Mutex::new(Duration::ZERO);
+
+/// messages a minute for a chat without --rate, groups and channels have IDs starting with -
+pub fn default_rate(chat_id: &str) -> u32 {
+    if chat_id.starts_with('-') {
+        RATE_GROUP
+    } else {
+        RATE_PRIVATE
+    }
+}
+
+/// wait for the turn of the next message to the chat, `rate` is in messages per minute
+/// a turn is taken before waiting for it, so uploads running at once (--jobs) line up
+pub fn wait(chat_id: &str, rate: u32) {
+    let now = Instant::now();
+    let turn = {
+        let mut turns = TURNS.lock().unwrap();
+        let turn = turns.get(chat_id).copied().filter(|turn| *turn > now).unwrap_or(now);
+        turns.insert(chat_id.to_string(), turn + Duration::from_secs(60) / rate.max(1));
+        turn
+    };
+    let wait = turn - now;
+    if wait.is_zero() {
+        return;
+    }
+    // a second between messages is the normal pace, not worth a line each
+    if wait >= Duration::from_secs(2) {
+        eprintln!("Waiting {}s to respect the rate limit of chat {}", wait.as_secs_f64().round(), chat_id);
+    }
+    std::thread::sleep(wait);
+    waited(wait);
+}
+
+/// add a wait to the total, e.g. one a 429 asked for
+pub fn waited(wait: Duration) {
+    *WAITED.lock().unwrap() += wait;
+}
+
+/// everything waited so far
+pub fn total() -> Duration {
+    *WAITED.lock().unwrap()
+}
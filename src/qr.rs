@@ -0,0 +1,42 @@
+//! QR code of the download url for --qr, drawn with unicode half blocks
+//! two rows of modules per line of text, so it fits in a normal terminal
+
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use terminal_size::{terminal_size_of, Height, Width};
+
+/// render the url for a terminal with the given size
+/// None when the code doesn't fit
+fn render(url: &str, size: (Width, Height)) -> Option<String> {
+    let code = QrCode::new(url.as_bytes()).ok()?;
+    // light and dark are swapped so the code reads on dark terminal backgrounds
+    let qr = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    let (Width(cols), Height(rows)) = size;
+    let width = qr.lines().next().map(|line| line.chars().count()).unwrap_or(0);
+    let height = qr.lines().count();
+    if width > cols as usize || height > rows as usize {
+        return None;
+    }
+    Some(qr)
+}
+
+/// print the QR code to stdout or stderr, if that is a terminal big enough for it
+pub fn print(url: &str, to_stderr: bool) -> Result<(), &'static str> {
+    let size = if to_stderr {
+        terminal_size_of(std::io::stderr())
+    } else {
+        terminal_size_of(std::io::stdout())
+    };
+    let size = size.ok_or("not a terminal")?;
+    let qr = render(url, size).ok_or("the terminal is too small")?;
+    if to_stderr {
+        eprintln!("{}", qr);
+    } else {
+        println!("{}", qr);
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! Uploads that failed because the network was down, kept to be sent later with `teledrop flush`
+//! stored as a json array in the data dir like the history, e.g. ~/.local/share/teledrop/queue.json
+//! the file stays where it is, an entry has its absolute path and the options it was uploaded with
+//! its size and modification time tell when it changed after it was queued, flush leaves those alone
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+const FILE_NAME: &str = "queue.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub queued_at: DateTime<Local>,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<DateTime<Local>>,
+    // the chat it goes to and the ones it's sent on to, only to list them
+    pub chats: Vec<String>,
+    // the upload options as given, without the files, run again from `dir`
+    pub args: Vec<String>,
+    pub dir: PathBuf,
+    // why it was queued
+    pub error: String,
+}
+
+impl Entry {
+    /// the file is still the one that was queued
+    pub fn unchanged(&self) -> bool {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return false;
+        };
+        // a directory is archived when it's sent, there is no telling if its files changed
+        metadata.is_dir() || (metadata.len() == self.size && metadata.modified().ok().map(DateTime::from) == self.modified)
+    }
+}
+
+pub fn path() -> Result<PathBuf, Error> {
+    let dir = dirs::data_dir().ok_or_else(|| Error::Other("No data directory to keep the queue in".into()))?;
+    Ok(dir.join(crate::APP_NAME).join(FILE_NAME))
+}
+
+/// all entries, oldest first, empty when nothing is queued
+pub fn load() -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+    let path = path()?;
+    let contents = match std::fs::read(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::File(format!("{}: {}", path.display(), err)).into()),
+    };
+    serde_json::from_slice(&contents)
+        .map_err(|err| Error::File(format!("Queue {} is damaged: {}", path.display(), err)).into())
+}
+
+/// write to a temp file next to the queue and rename it over the old one
+fn save(entries: &[Entry]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path()?;
+    let dir = path.parent().expect("queue path has a parent");
+    std::fs::create_dir_all(dir)?;
+    let _writing = crate::interrupt::writing();
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    serde_json::to_writer_pretty(&mut tmp, entries)?;
+    tmp.flush()?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(&path)?;
+    Ok(())
+}
+
+/// queue the file to be uploaded with `args` later, returns the id of the entry
+pub fn add(path: &Path, chats: Vec<String>, args: Vec<String>, error: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let metadata = std::fs::metadata(path).map_err(|err| Error::io(path.display(), &err))?;
+    let mut entries = load()?;
+    let id = entries.last().map_or(1, |last| last.id + 1);
+    entries.push(Entry {
+        id,
+        queued_at: Local::now(),
+        path: std::path::absolute(path)?,
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        modified: metadata.modified().ok().map(DateTime::from),
+        chats,
+        args,
+        dir: std::env::current_dir()?,
+        error: crate::output::redact(error),
+    });
+    save(&entries)?;
+    Ok(id)
+}
+
+/// take entries out of the queue, returns the ids that weren't in it
+pub fn remove(ids: &[u64]) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+    let mut entries = load()?;
+    let missing = ids.iter().copied().filter(|id| !entries.iter().any(|entry| entry.id == *id)).collect();
+    let before = entries.len();
+    entries.retain(|entry| !ids.contains(&entry.id));
+    if entries.len() != before {
+        save(&entries)?;
+    }
+    Ok(missing)
+}
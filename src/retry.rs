@@ -0,0 +1,183 @@
+//! Retrying calls that failed for a reason that may be gone a moment later
+//! connection errors, timeouts, 5xx and 429 (Too Many Requests) are retried, other 4xx are final
+//! the delay doubles with every attempt plus some jitter, 429 waits as long as telegram asks
+//! unless that is longer than max_retry_after, a terminal shows the wait counting down
+//! a 429 pauses every call until the wait is over, so parallel uploads (--jobs) don't keep hammering
+
+use std::future::Future;
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use colored::Colorize;
+use reqwest::StatusCode;
+
+use crate::error::{self, Error};
+use crate::{output, pace, trace};
+
+pub const RETRIES: u32 = 3;
+pub const RETRY_DELAY: u64 = 1;
+pub const MAX_RETRY_AFTER: u64 = 300;
+
+// until when telegram wants no more calls, from the latest 429
+static PAUSED_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// how often and how long to wait, from --retries, --retry-delay and --max-retry-after
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    pub retries: u32,
+    pub delay: Duration,
+    pub max_retry_after: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            retries: RETRIES,
+            delay: Duration::from_secs(RETRY_DELAY),
+            max_retry_after: Duration::from_secs(MAX_RETRY_AFTER),
+        }
+    }
+}
+
+/// telegram answered with a status that is worth another try
+#[derive(Debug)]
+pub struct ApiStatus {
+    pub status: u16,
+    // seconds from parameters.retry_after of a 429
+    pub retry_after: Option<u64>,
+    pub msg: String,
+}
+
+impl std::fmt::Display for ApiStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for ApiStatus {}
+
+/// an ApiStatus error for a 5xx or 429 response, the body is read for telegram's description
+pub fn check_status(status: StatusCode, content_type: Option<&str>, body: &str) -> Result<(), ApiStatus> {
+    if !status.is_server_error() && status != StatusCode::TOO_MANY_REQUESTS {
+        return Ok(());
+    }
+    let response: Option<crate::TelegramError> = serde_json::from_str(body).ok();
+    let retry_after = response.as_ref().and_then(|response| response.parameters.as_ref()?.retry_after);
+    // a proxy in between may answer with html
+    let msg = match response {
+        Some(response) if response.description.is_some() => response.message(Some(status.as_u16())),
+        Some(_) => format!("HTTP error {}", status),
+        None => trace::not_json(status, content_type, body),
+    };
+    Err(ApiStatus {
+        status: status.as_u16(),
+        retry_after,
+        msg,
+    })
+}
+
+fn is_transient(err: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+        return err.is_connect() || err.is_timeout() || err.is_request() || err.is_body();
+    }
+    if let Some(err) = err.downcast_ref::<ApiStatus>() {
+        return err.status >= 500 || err.status == 429;
+    }
+    // timeouts explained by trace::explain
+    matches!(err.downcast_ref::<Error>(), Some(Error::Network(_)))
+}
+
+/// delay before the retry `attempt`, counting from 1
+fn delay(policy: &Policy, attempt: u32, err: &(dyn std::error::Error + 'static)) -> Duration {
+    if let Some(secs) = err.downcast_ref::<ApiStatus>().and_then(|err| err.retry_after) {
+        return Duration::from_secs(secs);
+    }
+    let base = policy.delay * 2u32.saturating_pow(attempt - 1);
+    // up to a quarter more, so several clients that failed together don't come back together
+    let jitter = base.mul_f64(f64::from(OsRng.next_u32()) / f64::from(u32::MAX) / 4.0);
+    base + jitter
+}
+
+/// run the call until it succeeds, fails for good or the retries are used up
+/// `what` names the call in the messages, e.g. "Uploading log.txt"
+pub async fn run<T, F, Fut>(policy: Policy, what: &str, mut call: F) -> Result<T, Box<dyn std::error::Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, Box<dyn std::error::Error>>>,
+{
+    let attempts = policy.retries + 1;
+    let mut attempt = 1;
+    loop {
+        paused().await;
+        let err = match call().await {
+            Ok(result) => return Ok(result),
+            Err(err) => err,
+        };
+        if !is_transient(&*err) {
+            return Err(err);
+        }
+        if attempt == attempts {
+            if attempts == 1 {
+                return Err(err);
+            }
+            let msg = format!("{} (gave up after {} attempts)", err, attempts);
+            return Err(Error::with_code(error::exit_code(&*err), msg).into());
+        }
+        let wait = delay(&policy, attempt, &*err);
+        if wait > policy.max_retry_after {
+            let msg = format!(
+                "{}, Telegram asks to wait {}s which is more than --max-retry-after {}s",
+                err,
+                wait.as_secs(),
+                policy.max_retry_after.as_secs()
+            );
+            return Err(Error::with_code(error::exit_code(&*err), msg).into());
+        }
+        if err.downcast_ref::<ApiStatus>().is_some_and(|err| err.retry_after.is_some()) {
+            pause(wait);
+            pace::waited(wait);
+        }
+        attempt += 1;
+        tracing::warn!(what, error = %err, attempt, "retrying");
+        let msg = format!("{} failed: {}", what, err);
+        countdown(&msg, wait, attempt, attempts).await;
+    }
+}
+
+/// hold back every call for `wait`, unless an earlier 429 holds them back longer
+fn pause(wait: Duration) {
+    let until = Instant::now() + wait;
+    let mut paused_until = PAUSED_UNTIL.lock().unwrap();
+    if paused_until.is_none_or(|paused_until| paused_until < until) {
+        *paused_until = Some(until);
+    }
+}
+
+/// wait while the calls are paused
+async fn paused() {
+    let until = *PAUSED_UNTIL.lock().unwrap();
+    if let Some(wait) = until.and_then(|until| until.checked_duration_since(Instant::now())) {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// wait before the next attempt, counting down the seconds when stderr is a terminal
+async fn countdown(msg: &str, wait: Duration, attempt: u32, attempts: u32) {
+    let line = |secs: u64| format!("{}, retrying in {}s (attempt {}/{})…", msg, secs, attempt, attempts);
+    let secs = wait.as_secs_f64().round() as u64;
+    if !std::io::stderr().is_terminal() || secs < 2 {
+        output::print_warning(&line(secs));
+        tokio::time::sleep(wait).await;
+        return;
+    }
+    for left in (1..=secs).rev() {
+        // back to the start of the line and clear it, the message is shorter when the number is
+        eprint!("\r\x1b[K{}", output::redact(&line(left)).yellow());
+        let _ = std::io::stderr().flush();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    eprintln!();
+}
@@ -0,0 +1,44 @@
+//! Keeping the bot token in the keyring of the system instead of the config file
+//! the config then only says token_source = "keyring", for config files that are synced or committed
+//! needs the `keyring` feature, without it reading and storing always fail with an explanation
+
+#[cfg(feature = "keyring")]
+const SERVICE: &str = crate::APP_NAME;
+#[cfg(feature = "keyring")]
+const USER: &str = "bot_token";
+
+// what to do instead when there is no keyring, e.g. on a server without a secret service
+const FALLBACK: &str = "keep the token in the config file with `teledrop config set bot_token <TOKEN>` or set TELEDROP_BOT_TOKEN";
+
+#[cfg(feature = "keyring")]
+fn describe(err: keyring::Error) -> String {
+    match err {
+        keyring::Error::NoEntry => "There is no bot token in the keyring, store it with `teledrop config set bot_token --keyring <TOKEN>`".into(),
+        keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_) => {
+            format!("No keyring available ({}), {}", err, FALLBACK)
+        }
+        err => format!("Keyring error: {}", err),
+    }
+}
+
+#[cfg(feature = "keyring")]
+pub fn get_token() -> Result<String, String> {
+    let entry = keyring::Entry::new(SERVICE, USER).map_err(describe)?;
+    entry.get_password().map_err(describe)
+}
+
+#[cfg(feature = "keyring")]
+pub fn set_token(token: &str) -> Result<(), String> {
+    let entry = keyring::Entry::new(SERVICE, USER).map_err(describe)?;
+    entry.set_password(token).map_err(describe)
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn get_token() -> Result<String, String> {
+    Err(format!("The config says token_source = \"keyring\" but teledrop was built without keyring support, {}", FALLBACK))
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn set_token(_token: &str) -> Result<(), String> {
+    Err(format!("teledrop was built without keyring support (cargo feature keyring), {}", FALLBACK))
+}
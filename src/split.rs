@@ -0,0 +1,183 @@
+//! Splitting files over the upload limit into parts
+//! every part is sent as its own document, next to a small json manifest
+//! that lists the parts in order with their sizes and checksums, `teledrop join` puts them back together
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::hex;
+
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+    pub parts: Vec<ManifestPart>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManifestPart {
+    // 1-based, same as in the part name
+    pub index: usize,
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// name.part01, name.part02, ... padded so the names sort in order
+pub fn part_name(name: &str, index: usize, count: usize) -> String {
+    let width = count.to_string().len().max(2);
+    format!("{}.part{:0width$}", name, index, width = width)
+}
+
+pub fn manifest_name(name: &str) -> String {
+    format!("{}{}", name, MANIFEST_SUFFIX)
+}
+
+/// read the file once, checksumming the whole file and every part
+pub fn plan(path: &Path, name: &str, size: u64, part_size: u64) -> std::io::Result<Manifest> {
+    let count = size.div_ceil(part_size) as usize;
+    let mut file = File::open(path)?;
+    let mut whole = Sha256::new();
+    let mut parts = Vec::with_capacity(count);
+    let mut buf = vec![0; 64 * 1024];
+    for index in 1..=count {
+        let offset = (index as u64 - 1) * part_size;
+        let len = part_size.min(size - offset);
+        let mut part = Sha256::new();
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = remaining.min(buf.len() as u64) as usize;
+            let n = file.read(&mut buf[..chunk])?;
+            if n == 0 {
+                return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "file changed while reading"));
+            }
+            whole.update(&buf[..n]);
+            part.update(&buf[..n]);
+            remaining -= n as u64;
+        }
+        parts.push(ManifestPart {
+            index,
+            name: part_name(name, index, count),
+            offset,
+            size: len,
+            sha256: hex(&part.finalize()),
+        });
+    }
+    Ok(Manifest {
+        name: name.to_string(),
+        size,
+        sha256: hex(&whole.finalize()),
+        parts,
+    })
+}
+
+/// strip the .partNN suffix
+fn original_name(part: &str) -> Option<&str> {
+    let (name, suffix) = part.rsplit_once(".part")?;
+    if !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+fn copy_into(out: &mut File, path: &Path, whole: &mut Sha256) -> Result<String, Box<dyn std::error::Error>> {
+    let mut file = File::open(path).map_err(|err| Error::io(path.display(), &err))?;
+    let mut part = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        whole.update(&buf[..n]);
+        part.update(&buf[..n]);
+        out.write_all(&buf[..n])?;
+    }
+    Ok(hex(&part.finalize()))
+}
+
+/// join the parts listed in a manifest, or the given part files in name order
+/// when joining part files the manifest next to them is used for verification if there is one
+/// returns the path of the joined file and whether it was verified against a manifest
+pub fn join(inputs: &[String], output: Option<&str>) -> Result<(PathBuf, bool), Box<dyn std::error::Error>> {
+    let (manifest, dir, mut part_paths) = if inputs.len() == 1 && inputs[0].ends_with(MANIFEST_SUFFIX) {
+        let manifest_path = Path::new(&inputs[0]);
+        let manifest: Manifest = serde_json::from_reader(File::open(manifest_path)?)
+            .map_err(|err| Error::File(format!("Invalid manifest {}: {}", manifest_path.display(), err)))?;
+        let dir = manifest_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let paths = manifest.parts.iter().map(|p| dir.join(&p.name)).collect();
+        (Some(manifest), dir, paths)
+    } else {
+        let mut paths: Vec<PathBuf> = inputs.iter().map(PathBuf::from).collect();
+        paths.sort();
+        let first = paths.first().ok_or(Error::Usage("No parts to join".into()))?;
+        let first_name = first.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let name = original_name(&first_name).ok_or_else(|| Error::Usage(format!("{} is not a .partNN file", first.display())))?;
+        let dir = first.parent().unwrap_or(Path::new("")).to_path_buf();
+        let manifest_path = dir.join(manifest_name(name));
+        let manifest = match File::open(&manifest_path) {
+            Ok(file) => Some(serde_json::from_reader::<_, Manifest>(file)?),
+            Err(_) => None,
+        };
+        (manifest, dir, paths)
+    };
+
+    let name = match &manifest {
+        Some(manifest) => manifest.name.clone(),
+        None => {
+            let first = part_paths[0].file_name().unwrap_or_default().to_string_lossy().into_owned();
+            original_name(&first).unwrap_or(&first).to_string()
+        }
+    };
+    let output = output.map(PathBuf::from).unwrap_or_else(|| PathBuf::from(&name));
+    if output.exists() {
+        return Err(Error::File(format!("{} already exists", output.display())).into());
+    }
+    if let Some(manifest) = &manifest {
+        if part_paths.len() != manifest.parts.len() {
+            return Err(Error::Usage(format!("Expected {} parts, got {}", manifest.parts.len(), part_paths.len())).into());
+        }
+        // parts are always taken in manifest order
+        part_paths = manifest.parts.iter().map(|p| dir.join(&p.name)).collect();
+    }
+
+    // write into a temp name first, so a failed join never leaves a half written output behind
+    let tmp_output = output.with_extension("joining");
+    let mut out = File::create(&tmp_output)?;
+    let mut whole = Sha256::new();
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        for (i, path) in part_paths.iter().enumerate() {
+            let part_sha256 = copy_into(&mut out, path, &mut whole)?;
+            if let Some(manifest) = &manifest {
+                if part_sha256 != manifest.parts[i].sha256 {
+                    return Err(Error::File(format!("Checksum mismatch for part {}", path.display())).into());
+                }
+            }
+        }
+        out.flush()?;
+        Ok(())
+    })();
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&tmp_output);
+        return Err(err);
+    }
+    if let Some(manifest) = &manifest {
+        let sha256 = hex(&whole.finalize());
+        if sha256 != manifest.sha256 {
+            let _ = std::fs::remove_file(&tmp_output);
+            return Err(Error::File(format!("Checksum mismatch: expected {}, got {}", manifest.sha256, sha256)).into());
+        }
+    }
+    std::fs::rename(&tmp_output, &output)?;
+    Ok((output, manifest.is_some()))
+}
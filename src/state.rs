@@ -0,0 +1,156 @@
+//! What a batch uploaded so far, so an interrupted batch can go on where it stopped with --resume
+//! stored as json in the data dir, one file per batch like ~/.local/share/teledrop/batches/3f2a9c01d4e5b6a7.json,
+//! or wherever --state says. A batch is told apart by its chat and the files it was given
+//! the file is rewritten after every uploaded file and removed once the whole batch made it, unless --keep-state
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::output::UploadResult;
+
+const DIR_NAME: &str = "batches";
+
+/// how --resume tells that a file is still the one that was uploaded
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ResumeCheck {
+    /// Same size and modification time
+    #[default]
+    Mtime,
+    /// Also the same SHA-256, for files that were sent as they are
+    Hash,
+}
+
+/// a file of the batch that was uploaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Done {
+    pub size: u64,
+    pub modified: Option<DateTime<Local>>,
+    // of the file on disk, none when something else was sent, e.g. it was compressed or encrypted first
+    pub sha256: Option<String>,
+    pub file_id: String,
+    pub message_id: i64,
+}
+
+impl Done {
+    /// the file on disk is still the one that was uploaded
+    pub fn unchanged(&self, path: &Path, check: ResumeCheck) -> bool {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return false;
+        };
+        if metadata.len() != self.size || metadata.modified().ok().map(DateTime::from) != self.modified {
+            return false;
+        }
+        match (check, &self.sha256) {
+            (ResumeCheck::Hash, Some(sha256)) => crate::file_sha256(&path.to_string_lossy()).is_ok_and(|hash| hash == *sha256),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Contents {
+    // by absolute path
+    files: BTreeMap<String, Done>,
+}
+
+#[derive(Debug)]
+pub struct State {
+    path: PathBuf,
+    contents: Contents,
+}
+
+/// the paths are absolute so the state is found again from another working directory
+/// not canonical, a file missing in the first run would be another batch once it's there
+fn key(path: &Path) -> String {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()).to_string_lossy().into_owned()
+}
+
+/// where the state of the batch of these files to this chat is kept without --state
+pub fn default_path(chat_id: &str, inputs: &[PathBuf]) -> Result<PathBuf, Error> {
+    let dir = dirs::data_dir().ok_or_else(|| Error::Other("No data directory to keep the state of the batch in, use --state".into()))?;
+    let mut hasher = Sha256::new();
+    hasher.update(chat_id.as_bytes());
+    for input in inputs {
+        hasher.update(b"\n");
+        hasher.update(key(input).as_bytes());
+    }
+    let name = format!("{}.json", &crate::hex(&hasher.finalize())[..16]);
+    Ok(dir.join(crate::APP_NAME).join(DIR_NAME).join(name))
+}
+
+impl State {
+    /// no files done yet, an old state at the path is overwritten with the first one
+    pub fn new(path: PathBuf) -> Self {
+        State {
+            path,
+            contents: Contents::default(),
+        }
+    }
+
+    /// the state at the path, empty when there is none
+    pub fn load(path: PathBuf) -> Result<Self, Error> {
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(State::new(path)),
+            Err(err) => return Err(Error::io(path.display(), &err)),
+        };
+        let contents = serde_json::from_slice(&contents)
+            .map_err(|err| Error::File(format!("State file {} is damaged: {}", path.display(), err)))?;
+        Ok(State { path, contents })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.contents.files.len()
+    }
+
+    /// the upload of the file, if it was uploaded
+    pub fn done(&self, path: &Path) -> Option<&Done> {
+        self.contents.files.get(&key(path))
+    }
+
+    /// remember the file as uploaded and save right away
+    /// `plain` says the file was sent as it is, so the sha256 of the result is the one of the file
+    pub fn record(&mut self, path: &Path, result: &UploadResult, plain: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = std::fs::metadata(path).map_err(|err| Error::io(path.display(), &err))?;
+        let done = Done {
+            size: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::from),
+            sha256: result.sha256.clone().filter(|_| plain),
+            file_id: result.file_id.clone(),
+            message_id: result.message_id,
+        };
+        self.contents.files.insert(key(path), done);
+        self.save()
+    }
+
+    /// write to a temp file next to the state and rename it over the old one
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir)?;
+        let _writing = crate::interrupt::writing();
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+        serde_json::to_writer_pretty(&mut tmp, &self.contents)?;
+        tmp.flush()?;
+        tmp.as_file().sync_all()?;
+        tmp.persist(&self.path)?;
+        Ok(())
+    }
+
+    /// the batch is complete, nothing to resume
+    pub fn remove(&self) -> std::io::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Simple {placeholder} templates
+//! `{{` and `}}` can be used to write literal braces
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Text(String),
+    Placeholder(&'a str),
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            text.push(c);
+            rest = &rest[2..];
+        } else if c == '{' {
+            let end = rest.find('}').ok_or_else(|| format!("Unclosed placeholder in template: {}", template))?;
+            if !text.is_empty() {
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+            }
+            tokens.push(Token::Placeholder(&rest[1..end]));
+            rest = &rest[end + 1..];
+        } else {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+/// check that the template parses and uses only the known placeholders
+pub fn validate(template: &str, known: &[&str]) -> Result<(), String> {
+    for token in tokenize(template)? {
+        if let Token::Placeholder(name) = token {
+            if !known.contains(&name) {
+                return Err(format!(
+                    "Unknown placeholder {{{}}}, valid placeholders are: {}",
+                    name,
+                    known.iter().map(|k| format!("{{{}}}", k)).collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// render the template, the value callback is only called for placeholders that are used
+pub fn render<E: From<String>>(template: &str, mut value: impl FnMut(&str) -> Result<String, E>) -> Result<String, E> {
+    let mut out = String::new();
+    for token in tokenize(template)? {
+        match token {
+            Token::Text(text) => out.push_str(&text),
+            Token::Placeholder(name) => out.push_str(&value(name)?),
+        }
+    }
+    Ok(out)
+}
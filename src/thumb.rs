@@ -0,0 +1,37 @@
+//! --thumb, a JPEG the clients show instead of the generic icon of a document, video or audio file
+//! telegram only takes a JPEG of up to 200 kB and 320x320, so it's checked before anything is uploaded
+//! https://core.telegram.org/bots/api#senddocument
+
+use std::path::{Path, PathBuf};
+
+use indicatif::DecimalBytes;
+
+const SIZE_LIMIT: u64 = 200_000;
+const SIDE_LIMIT: usize = 320;
+
+/// the path of --thumb, once it's a thumbnail telegram takes
+pub fn parse(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    check(&path)?;
+    Ok(path)
+}
+
+fn check(path: &Path) -> Result<(), String> {
+    let metadata = std::fs::metadata(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    if metadata.len() > SIZE_LIMIT {
+        return Err(format!("{} is {}, a thumbnail can be up to {}", path.display(), DecimalBytes(metadata.len()), DecimalBytes(SIZE_LIMIT)));
+    }
+    let contents = std::fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    if !infer::image::is_jpeg(&contents) {
+        return Err(format!("{} is not a JPEG, Telegram only takes JPEG thumbnails", path.display()));
+    }
+    let size = imagesize::blob_size(&contents).map_err(|err| format!("Can't read the size of {}: {}", path.display(), err))?;
+    if size.width > SIDE_LIMIT || size.height > SIDE_LIMIT {
+        let msg = format!("{} is {}x{}, a thumbnail can be up to {}x{}", path.display(), size.width, size.height, SIDE_LIMIT, SIDE_LIMIT);
+        return Err(msg);
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! HTTP tracing for -v and -vv, everything goes to stderr with the bot token redacted
+//! -v: method and url of each request, the response status and the raw response body
+//! -vv: also the request headers, the parts of multipart forms and how long each phase took
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode, Url};
+
+use crate::error::Error;
+use crate::{output, retry};
+
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// set from the number of -v flags
+pub fn set_level(level: u8) {
+    LEVEL.store(level, Ordering::Relaxed);
+}
+
+pub fn enabled(level: u8) -> bool {
+    LEVEL.load(Ordering::Relaxed) >= level
+}
+
+/// print a trace line when the verbosity is at least `level`
+pub fn log(level: u8, msg: &str) {
+    if enabled(level) {
+        eprintln!("{}", output::redact(msg).dimmed());
+    }
+}
+
+/// a phase like read or upload is done, for -vv
+pub fn phase(name: &str, start: Instant) {
+    log(2, &format!("* {}: {}", name, elapsed(start.elapsed())));
+}
+
+fn elapsed(duration: Duration) -> String {
+    format!("{:.1?}", duration)
+}
+
+/// a part of a multipart form is about to be sent, for -vv
+/// only what describes it, never the contents
+pub fn part(name: &str, description: &str) {
+    log(2, &format!("> part {}: {}", name, description));
+}
+
+/// a timed out request as a network error that says which timeout it was and how to raise it
+/// `timeout` is the one of the request, none when it's the default of the client
+pub fn explain(err: reqwest::Error, timeout: Option<Duration>) -> Box<dyn std::error::Error> {
+    if !err.is_timeout() {
+        return err.into();
+    }
+    let msg = if err.is_connect() {
+        "Connecting to the server timed out, raise the limit with --connect-timeout or connect_timeout in the config".to_string()
+    } else {
+        let after = timeout.map(|timeout| format!(" after {}s", timeout.as_secs())).unwrap_or_default();
+        format!("The request timed out{}, raise the limit with --timeout or timeout in the config", after)
+    };
+    Error::Network(msg).into()
+}
+
+/// send the request and log it with the response status
+/// `request` has to be built from `client`
+pub async fn send(client: &Client, request: RequestBuilder) -> Result<Response, Box<dyn std::error::Error>> {
+    let request = request.build()?;
+    let (method, url, timeout) = (request.method().clone(), request.url().clone(), request.timeout().copied());
+    if !enabled(1) {
+        let start = Instant::now();
+        let response = client.execute(request).await;
+        log_request(&method, &url, &response, start);
+        return response.map_err(|err| explain(err, timeout));
+    }
+    log(1, &format!("> {} {}", request.method(), request.url()));
+    for (name, value) in request.headers() {
+        log(2, &format!("> {}: {}", name, value.to_str().unwrap_or("(binary)")));
+    }
+    let start = Instant::now();
+    let response = client.execute(request).await;
+    match &response {
+        Ok(response) => log(1, &format!("< {} after {}", response.status(), elapsed(start.elapsed()))),
+        Err(err) => log(1, &format!("< failed after {}: {}", elapsed(start.elapsed()), err)),
+    }
+    log_request(&method, &url, &response, start);
+    if let Ok(response) = &response {
+        for (name, value) in response.headers() {
+            log(2, &format!("< {}: {}", name, value.to_str().unwrap_or("(binary)")));
+        }
+    }
+    response.map_err(|err| explain(err, timeout))
+}
+
+/// for the log of tracing, the url is redacted when it's written
+fn log_request(method: &Method, url: &Url, response: &reqwest::Result<Response>, start: Instant) {
+    let elapsed = elapsed(start.elapsed());
+    match response {
+        Ok(response) => tracing::debug!(%method, %url, status = response.status().as_u16(), %elapsed, "request"),
+        Err(err) => tracing::debug!(%method, %url, error = %err, %elapsed, "request failed"),
+    }
+}
+
+/// read the whole response body as text, which is logged raw
+/// 5xx and 429 are an error of their own so they can be retried
+/// the bot api always answers with JSON, anything else came from something in between
+pub async fn text(response: Response, timeout: Option<Duration>) -> Result<String, Box<dyn std::error::Error>> {
+    let status = response.status();
+    let content_type = response.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let body = response.text().await.map_err(|err| explain(err, timeout))?;
+    log(1, &format!("< {}", body));
+    retry::check_status(status, content_type.as_deref(), &body)?;
+    if serde_json::from_str::<serde::de::IgnoredAny>(&body).is_err() {
+        return Err(Error::Api(not_json(status, content_type.as_deref(), &body)).into());
+    }
+    Ok(body)
+}
+
+/// what a response that isn't JSON looked like, e.g. the html page of a proxy or captive portal
+pub fn not_json(status: StatusCode, content_type: Option<&str>, body: &str) -> String {
+    let what = match content_type {
+        Some(content_type) => format!("with {}", content_type),
+        None => "with something".to_string(),
+    };
+    let body = match snippet(body) {
+        snippet if snippet.is_empty() => "The response was empty".to_string(),
+        snippet => format!("The response began with: {}", snippet),
+    };
+    format!(
+        "The server answered {} {} instead of JSON, check the proxy and network settings (HTTPS_PROXY) and api_base_url. {}",
+        status, what, body
+    )
+}
+
+const SNIPPET_LEN: usize = 300;
+
+/// the text of the body on one line without html tags and control characters, cut to SNIPPET_LEN
+fn snippet(body: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in body.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if in_tag => {}
+            c if c.is_whitespace() || c.is_control() => text.push(' '),
+            c => text.push(c),
+        }
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(SNIPPET_LEN) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// send the request and read the whole response body as text
+pub async fn send_text(client: &Client, request: RequestBuilder) -> Result<String, Box<dyn std::error::Error>> {
+    let response = send(client, request).await?;
+    text(response, None).await
+}
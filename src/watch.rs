@@ -0,0 +1,251 @@
+//! teledrop watch, every file that is created in or moved into the directories is uploaded once it's written
+//! a file is sent when its size and modification time stayed the same for --settle seconds, so half-written
+//! files wait. Uploads that failed for the network are queued again, SIGTERM stops after the upload under way
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::Local;
+use colored::Colorize;
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::runtime::Runtime;
+
+use crate::archive::{self, Walk};
+use crate::error::Error;
+use crate::output;
+
+// how often a pending file is looked at again when no events come in
+const POLL: Duration = Duration::from_millis(500);
+// the wait before the first try again after a network error, doubled up to RETRY_MAX
+const RETRY_FIRST: Duration = Duration::from_secs(30);
+const RETRY_MAX: Duration = Duration::from_secs(600);
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// a line of what watch does, with the time for logs of long runs, e.g. journalctl
+pub fn log(msg: &str) {
+    eprintln!("{} {}", Local::now().format("%Y-%m-%d %H:%M:%S").to_string().dimmed(), output::redact(msg));
+}
+
+/// stop once the upload under way is done on SIGTERM, a second one exits right away
+/// Ctrl+C is the one of uploads, it stops at once
+pub fn stop_on_sigterm(rt: &Runtime) {
+    #[cfg(unix)]
+    rt.spawn(async {
+        use tokio::signal::unix::{signal, SignalKind};
+        let Ok(mut terminate) = signal(SignalKind::terminate()) else {
+            return;
+        };
+        terminate.recv().await;
+        log("Stopping after the upload under way");
+        STOP.store(true, Ordering::Relaxed);
+        terminate.recv().await;
+        std::process::exit(crate::error::EXIT_INTERRUPTED);
+    });
+    #[cfg(not(unix))]
+    let _ = rt;
+}
+
+/// a file seen in a directory that wasn't sent yet
+struct Pending {
+    size: Option<u64>,
+    modified: Option<SystemTime>,
+    // the last time it was seen changing
+    changed: Instant,
+}
+
+impl Pending {
+    fn new(path: &Path) -> Self {
+        let metadata = std::fs::metadata(path).ok();
+        Pending {
+            size: metadata.as_ref().map(|metadata| metadata.len()),
+            modified: metadata.and_then(|metadata| metadata.modified().ok()),
+            changed: Instant::now(),
+        }
+    }
+}
+
+pub struct Watch {
+    dirs: Vec<PathBuf>,
+    recursive: bool,
+    walk: Walk,
+    // --move-to, what is moved there isn't new
+    ignore: Option<PathBuf>,
+    settle: Duration,
+    _watcher: RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<Event>>,
+    pending: BTreeMap<PathBuf, Pending>,
+    // failed for the network, when to try again and the wait after that
+    again: BTreeMap<PathBuf, (Instant, Duration)>,
+}
+
+impl Watch {
+    /// start watching, files that are in the directories already are left alone
+    pub fn new(dirs: &[PathBuf], recursive: bool, walk: Walk, ignore: Option<&Path>, settle: Duration) -> Result<Self, Error> {
+        // the events come with the path the directory was watched by
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let dirs: Vec<PathBuf> = dirs.iter().map(|dir| absolute(dir)).collect();
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(|err| Error::Other(format!("Can't watch for new files: {}", err)))?;
+        let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        for dir in &dirs {
+            watcher.watch(dir, mode).map_err(|err| Error::File(format!("Can't watch {}: {}", dir.display(), err)))?;
+        }
+        Ok(Watch {
+            dirs,
+            recursive,
+            walk,
+            ignore: ignore.map(absolute),
+            settle,
+            _watcher: watcher,
+            events,
+            pending: BTreeMap::new(),
+            again: BTreeMap::new(),
+        })
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// wait for the next file that is done being written, with its path below the watched directory for --recursive
+    /// none once SIGTERM came in
+    pub fn next(&mut self) -> Option<(PathBuf, Option<String>)> {
+        while !STOP.load(Ordering::Relaxed) {
+            if let Some(path) = self.ready() {
+                let relative = self.relative(&path);
+                return Some((path, relative));
+            }
+            match self.events.recv_timeout(POLL) {
+                Ok(Ok(event)) => self.event(event),
+                Ok(Err(err)) => log(&format!("Warning: watching failed: {}", err).yellow().to_string()),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+        None
+    }
+
+    /// try the file again later, the wait doubles each time, returns how long it is
+    pub fn again(&mut self, path: PathBuf) -> Duration {
+        let wait = self.again.get(&path).map(|(_, wait)| *wait).unwrap_or(RETRY_FIRST);
+        self.again.insert(path, (Instant::now() + wait, (wait * 2).min(RETRY_MAX)));
+        wait
+    }
+
+    /// the file went through, or failed for something trying again won't fix
+    pub fn done(&mut self, path: &Path) {
+        self.again.remove(path);
+    }
+
+    /// a file that didn't change for --settle, or one that is due to be tried again
+    fn ready(&mut self) -> Option<PathBuf> {
+        let now = Instant::now();
+        let due = self.again.iter().find(|(_, (at, _))| *at <= now).map(|(path, _)| path.clone());
+        if let Some(path) = due {
+            if path.is_file() {
+                // pushed back until it's tried, done or due again
+                if let Some((at, _)) = self.again.get_mut(&path) {
+                    *at = now + RETRY_MAX;
+                }
+                return Some(path);
+            }
+            self.again.remove(&path);
+        }
+        let mut ready = None;
+        self.pending.retain(|path, pending| {
+            if ready.is_some() {
+                return true;
+            }
+            let now = Pending::new(path);
+            if now.size.is_none() {
+                // gone again, e.g. a temp file that was renamed
+                return false;
+            }
+            if (now.size, now.modified) != (pending.size, pending.modified) {
+                *pending = now;
+                return true;
+            }
+            if pending.changed.elapsed() < self.settle {
+                return true;
+            }
+            ready = Some(path.clone());
+            false
+        });
+        ready
+    }
+
+    fn event(&mut self, event: Event) {
+        match event.kind {
+            // a rename comes with the old path, the new one or both, only the one that is there is new
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+                for path in event.paths {
+                    if std::fs::symlink_metadata(&path).is_err() {
+                        self.pending.remove(&path);
+                    } else {
+                        self.found(path);
+                    }
+                }
+            }
+            EventKind::Modify(_) => {
+                for path in event.paths {
+                    if let Some(pending) = self.pending.get_mut(&path) {
+                        pending.changed = Instant::now();
+                    }
+                }
+            }
+            EventKind::Remove(_) => {
+                for path in event.paths {
+                    self.pending.remove(&path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// a new file, or with --recursive a directory that was moved in with its files
+    fn found(&mut self, path: PathBuf) {
+        if self.ignore.as_ref().is_some_and(|ignore| path.starts_with(ignore)) {
+            return;
+        }
+        let Some(dir) = self.dir(&path) else {
+            return;
+        };
+        if archive::is_excluded(dir, &path, &self.walk) {
+            return;
+        }
+        if path.is_dir() {
+            if self.recursive {
+                let dir = dir.clone();
+                let files = archive::files(&path, &self.walk).unwrap_or_default();
+                for (file, _) in files.into_iter().filter(|(file, _)| !archive::is_excluded(&dir, file, &self.walk)) {
+                    let pending = Pending::new(&file);
+                    self.pending.entry(file).or_insert(pending);
+                }
+            }
+            return;
+        }
+        let pending = Pending::new(&path);
+        self.pending.insert(path, pending);
+    }
+
+    /// the watched directory the path is in
+    fn dir(&self, path: &Path) -> Option<&PathBuf> {
+        self.dirs.iter().filter(|dir| path.starts_with(dir)).max_by_key(|dir| dir.components().count())
+    }
+
+    /// like --recursive names the files of a directory, rooted at its name, e.g. Screenshots/2024/shot.png
+    fn relative(&self, path: &Path) -> Option<String> {
+        if !self.recursive {
+            return None;
+        }
+        let dir = self.dir(path)?;
+        let root = dir.file_name().map(PathBuf::from).unwrap_or_default();
+        let relative = root.join(path.strip_prefix(dir).ok()?);
+        Some(relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"))
+    }
+}
@@ -0,0 +1,42 @@
+//! an album over precheck_size checks the chat first, like a single file does
+
+mod common;
+
+use common::{stderr, Home, Mock, Reply};
+
+fn chat_not_found() -> Mock {
+    Mock::start(|request| match request.method.as_str() {
+        "getChat" => Reply::json(400, r#"{"ok":false,"error_code":400,"description":"Bad Request: chat not found"}"#),
+        _ => common::telegram(request),
+    })
+}
+
+/// a home whose config checks the chat before anything over 4 bytes
+fn home() -> Home {
+    let home = Home::new();
+    let dir = home.path().join(".config/teledrop");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("config.toml"), "precheck_size = 4\n").unwrap();
+    home
+}
+
+#[test]
+fn album_is_not_sent_when_the_precheck_fails() {
+    let (home, mock) = (home(), chat_not_found());
+    let (first, second) = (home.file("a.txt", b"hello"), home.file("b.txt", b"world"));
+    let output = home.teledrop(&mock).arg("--album").arg(&first).arg(&second).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(9), "{}", stderr(&output));
+    assert_eq!(mock.methods(), ["getChat"]);
+    assert!(stderr(&output).contains("--no-precheck skips this check"), "{}", stderr(&output));
+}
+
+#[test]
+fn no_precheck_sends_the_album_without_asking() {
+    let (home, mock) = (home(), chat_not_found());
+    let (first, second) = (home.file("a.txt", b"hello"), home.file("b.txt", b"world"));
+    home.teledrop(&mock).args(["--album", "--no-precheck"]).arg(&first).arg(&second).output().unwrap();
+
+    // the mock doesn't answer sendMediaGroup, only the requests are looked at
+    assert_eq!(mock.methods(), ["sendMediaGroup"]);
+}
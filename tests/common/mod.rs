@@ -0,0 +1,213 @@
+//! what the integration tests share: the teledrop binary in a home of its own and a bot api server in a thread
+//! the server answers every request with the handler of the test and keeps the requests to look at afterwards
+
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+
+pub const TOKEN: &str = "123456:TEST-token";
+
+/// a request as the server got it
+#[derive(Debug, Clone)]
+pub struct Request {
+    // the bot api method, e.g. sendDocument
+    pub method: String,
+    pub head: String,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn body_text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// the Content-Disposition lines of the parts of a multipart body
+    pub fn dispositions(&self) -> Vec<String> {
+        self.body_text().lines().filter(|line| line.starts_with("Content-Disposition:")).map(str::to_string).collect()
+    }
+}
+
+pub struct Reply {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Reply {
+    pub fn json(status: u16, body: &str) -> Reply {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+}
+
+type Handler = Box<dyn Fn(&Request) -> Reply + Send + Sync>;
+
+pub struct Mock {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Mock {
+    pub fn start(handler: impl Fn(&Request) -> Reply + Send + Sync + 'static) -> Mock {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind the mock server");
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (handler, kept): (Arc<Handler>, _) = (Arc::new(Box::new(handler)), requests.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (handler, kept) = (handler.clone(), kept.clone());
+                std::thread::spawn(move || serve(stream, &handler, &kept));
+            }
+        });
+        Mock { url, requests }
+    }
+
+    /// a server that answers like telegram when everything works
+    pub fn telegram() -> Mock {
+        Mock::start(telegram)
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn methods(&self) -> Vec<String> {
+        self.requests().into_iter().map(|request| request.method).collect()
+    }
+}
+
+fn serve(stream: TcpStream, handler: &Handler, kept: &Mutex<Vec<Request>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        if line == "\r\n" {
+            break;
+        }
+        head.push_str(&line);
+    }
+    let header = |name: &str| {
+        head.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+        })
+    };
+    let mut body = Vec::new();
+    if let Some(length) = header("content-length").and_then(|length| length.parse::<usize>().ok()) {
+        body.resize(length, 0);
+        reader.read_exact(&mut body).unwrap();
+    } else if header("transfer-encoding").is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked")) {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).unwrap();
+            let size = usize::from_str_radix(size.trim(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).unwrap();
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    }
+    let path = head.split_whitespace().nth(1).unwrap_or_default();
+    let method = path.split('?').next().unwrap_or_default().rsplit('/').next().unwrap_or_default().to_string();
+    let request = Request { method, head, body };
+    let reply = handler(&request);
+    kept.lock().unwrap().push(request);
+    let mut stream = stream;
+    let response = format!(
+        "HTTP/1.1 {} X\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.status,
+        reply.content_type,
+        reply.body.len(),
+        reply.body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// sendDocument and getFile the way telegram answers them
+pub fn telegram(request: &Request) -> Reply {
+    match request.method.as_str() {
+        "sendDocument" => Reply::json(
+            200,
+            r#"{"ok":true,"result":{"message_id":7,"from":{"id":42,"is_bot":true,"first_name":"Drop"},"chat":{"id":1,"type":"private","first_name":"Ann"},"date":1700000000,"document":{"file_name":"a.txt","mime_type":"text/plain","file_id":"BQACAgIAAxk","file_unique_id":"AgADx","file_size":5}}}"#,
+        ),
+        "getFile" => Reply::json(200, r#"{"ok":true,"result":{"file_id":"BQACAgIAAxk","file_unique_id":"AgADx","file_size":5,"file_path":"documents/file_1.txt"}}"#),
+        _ => Reply::json(400, r#"{"ok":false,"error_code":400,"description":"Bad Request: method not mocked"}"#),
+    }
+}
+
+/// a home directory of its own, so the config, history and queue of the user are never touched
+pub struct Home {
+    pub dir: tempfile::TempDir,
+}
+
+impl Home {
+    pub fn new() -> Home {
+        Home {
+            dir: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// a file in the home, for the tests to upload
+    pub fn file(&self, name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = self.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// teledrop without any config, run in the home
+    pub fn bare(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_teledrop"));
+        for (key, _) in std::env::vars_os() {
+            if key.to_string_lossy().starts_with("TELEDROP_") {
+                command.env_remove(key);
+            }
+        }
+        let home = self.path();
+        command
+            .current_dir(home)
+            .env("HOME", home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .env("XDG_DATA_HOME", home.join(".local/share"))
+            .env("XDG_CACHE_HOME", home.join(".cache"))
+            .env("NO_COLOR", "1");
+        command
+    }
+
+    /// teledrop with a token and chat that talk to the mock, for the subcommands
+    pub fn configured(&self, mock: &Mock) -> Command {
+        let mut command = self.bare();
+        command.env("TELEDROP_BOT_TOKEN", TOKEN).env("TELEDROP_CHAT_ID", "1").env("TELEDROP_API_BASE", &mock.url);
+        command
+    }
+
+    /// an upload to the mock, no retries so failures show at once
+    pub fn teledrop(&self, mock: &Mock) -> Command {
+        let mut command = self.configured(mock);
+        command.args(["--retries", "0", "--no-dedupe"]);
+        command
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
@@ -0,0 +1,40 @@
+//! a directory is never read as a file, it is packed into an archive named after it and that is sent
+
+mod common;
+
+use common::{stderr, Home, Mock, Request};
+
+fn document(request: &Request) -> String {
+    request.dispositions().into_iter().find(|line| line.contains("name=\"document\"")).expect("a document part")
+}
+
+#[test]
+fn directory_is_sent_as_an_archive() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let dir = home.path().join("screenshots");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("shot.png"), b"not really a png").unwrap();
+    let output = home.teledrop(&mock).arg(&dir).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    assert!(stderr(&output).contains("into screenshots.zip"), "{}", stderr(&output));
+    let requests = mock.requests();
+    assert_eq!(mock.methods(), ["sendDocument", "getFile"]);
+    let document = document(&requests[0]);
+    assert!(document.ends_with("filename=\"screenshots.zip\""), "{}", document);
+    // a zip, not the zero or garbage bytes of reading the directory
+    assert!(requests[0].body.windows(4).any(|bytes| bytes == b"PK\x03\x04"), "the part is a zip");
+}
+
+#[test]
+fn archive_format_names_the_archive() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let dir = home.path().join("screenshots");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("shot.png"), b"not really a png").unwrap();
+    let output = home.teledrop(&mock).args(["--archive-format", "tar.gz"]).arg(&dir).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let document = document(&mock.requests()[0]);
+    assert!(document.ends_with("filename=\"screenshots.tar.gz\""), "{}", document);
+}
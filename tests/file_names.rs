@@ -0,0 +1,79 @@
+//! names are sent as they are percent-encoded in filename*, filename="..." is their ascii fallback
+
+mod common;
+
+use common::{stderr, Home, Mock};
+
+/// the Content-Disposition of the file part when the file is called name
+fn disposition(name: &str) -> String {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let file = home.file(name, b"hello");
+    let output = home.teledrop(&mock).arg(&file).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let dispositions = mock.requests()[0].dispositions();
+    assert_eq!(dispositions.len(), 1, "{:?}", dispositions);
+    dispositions[0].clone()
+}
+
+#[test]
+fn cyrillic_and_spaces() {
+    assert_eq!(
+        disposition("отчёт за май.pdf"),
+        "Content-Disposition: form-data; name=\"document\"; filename*=UTF-8''%D0%BE%D1%82%D1%87%D1%91%D1%82%20%D0%B7%D0%B0%20%D0%BC%D0%B0%D0%B9.pdf; filename=\"_____ __ ___.pdf\""
+    );
+}
+
+#[test]
+fn cjk() {
+    assert_eq!(
+        disposition("日本語.zip"),
+        "Content-Disposition: form-data; name=\"document\"; filename*=UTF-8''%E6%97%A5%E6%9C%AC%E8%AA%9E.zip; filename=\"___.zip\""
+    );
+}
+
+#[test]
+fn emoji() {
+    assert_eq!(
+        disposition("🎉 party.txt"),
+        "Content-Disposition: form-data; name=\"document\"; filename*=UTF-8''%F0%9F%8E%89%20party.txt; filename=\"_ party.txt\""
+    );
+}
+
+#[test]
+fn quotes_are_escaped_not_replaced() {
+    assert_eq!(
+        disposition("say \"hi\".txt"),
+        "Content-Disposition: form-data; name=\"document\"; filename*=UTF-8''say%20%22hi%22.txt; filename=\"say \\\"hi\\\".txt\""
+    );
+}
+
+#[test]
+fn name_option_is_encoded_the_same() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let file = home.file("a.txt", b"hello");
+    let output = home.teledrop(&mock).args(["--name", "отчёт.txt"]).arg(&file).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        mock.requests()[0].dispositions(),
+        ["Content-Disposition: form-data; name=\"document\"; filename*=UTF-8''%D0%BE%D1%82%D1%87%D1%91%D1%82.txt; filename=\"_____.txt\""]
+    );
+}
+
+#[test]
+fn album_parts_are_encoded_the_same() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let first = home.file("первый.txt", b"one");
+    let second = home.file("第二.txt", b"two");
+    // the mock doesn't answer sendMediaGroup, only the request is looked at
+    home.teledrop(&mock).arg("--album").arg(&first).arg(&second).output().unwrap();
+
+    let request = mock.requests().into_iter().find(|request| request.method == "sendMediaGroup").expect("an album is sent");
+    let files: Vec<String> = request.dispositions().into_iter().filter(|line| line.contains("filename")).collect();
+    assert_eq!(
+        files,
+        [
+            "Content-Disposition: form-data; name=\"file0\"; filename*=UTF-8''%D0%BF%D0%B5%D1%80%D0%B2%D1%8B%D0%B9.txt; filename=\"______.txt\"",
+            "Content-Disposition: form-data; name=\"file1\"; filename*=UTF-8''%E7%AC%AC%E4%BA%8C.txt; filename=\"__.txt\"",
+        ]
+    );
+}
@@ -0,0 +1,36 @@
+//! the send defaults of the config go for forward too, like for an upload
+
+mod common;
+
+use common::{Home, Mock, Reply};
+
+fn forwarded() -> Mock {
+    Mock::start(|_| Reply::json(200, r#"{"ok":true,"result":{"message_id":8,"chat":{"id":2,"type":"private","first_name":"Bob"},"date":1700000000}}"#))
+}
+
+/// the json body of the forwardMessage request, with the config of the test
+fn forward_request(config: &str, args: &[&str]) -> serde_json::Value {
+    let (home, mock) = (Home::new(), forwarded());
+    let dir = home.path().join(".config/teledrop");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("config.toml"), config).unwrap();
+    let output = home.configured(&mock).args(["forward", "7", "--to", "2"]).args(args).output().unwrap();
+    assert!(output.status.success(), "{}", common::stderr(&output));
+    let requests = mock.requests();
+    assert_eq!(mock.methods(), ["forwardMessage"]);
+    serde_json::from_slice(&requests[0].body).unwrap()
+}
+
+#[test]
+fn config_defaults_apply_to_forward() {
+    let request = forward_request("disable_notification = true\nprotect_content = true\n", &[]);
+    assert_eq!(request["disable_notification"], true);
+    assert_eq!(request["protect_content"], true);
+}
+
+#[test]
+fn forward_without_defaults_is_not_silent() {
+    let request = forward_request("", &[]);
+    assert!(request.get("disable_notification").is_none(), "{}", request);
+    assert_eq!(forward_request("", &["--silent"])["disable_notification"], true);
+}
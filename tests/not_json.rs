@@ -0,0 +1,102 @@
+//! what a proxy or captive portal answers instead of the bot api is explained, on sendDocument and on getFile
+
+mod common;
+
+use common::{stderr, Home, Mock, Reply};
+
+// a block page with markup, a style and lines, longer than the part of it that is shown
+fn block_page() -> String {
+    let filler = "Access to this site is blocked by the policy of your organization. ".repeat(8);
+    format!(
+        "<!DOCTYPE html>\n<html><head><title>Blocked</title><style>body {{ color: red }}</style></head>\n<body>\n\t<h1>Request blocked</h1>\r\n<p>{}</p></body></html>",
+        filler
+    )
+}
+
+fn html(status: u16) -> Reply {
+    Reply {
+        status,
+        content_type: "text/html; charset=utf-8",
+        body: block_page(),
+    }
+}
+
+fn empty(status: u16) -> Reply {
+    Reply {
+        status,
+        content_type: "text/plain",
+        body: String::new(),
+    }
+}
+
+fn upload(mock: &Mock) -> String {
+    let home = Home::new();
+    let file = home.file("a.txt", b"hello");
+    let output = home.teledrop(mock).arg(&file).output().unwrap();
+    assert!(!output.status.success());
+    stderr(&output)
+}
+
+/// the part of the message after "began with: "
+fn excerpt(err: &str) -> &str {
+    let start = err.find("began with: ").expect("the message has the start of the body") + "began with: ".len();
+    err[start..].lines().next().unwrap()
+}
+
+fn assert_proxy_hint(err: &str) {
+    assert!(err.contains("instead of JSON, check the proxy and network settings (HTTPS_PROXY) and api_base_url"), "{}", err);
+}
+
+fn assert_sanitized_excerpt(err: &str) {
+    let excerpt = excerpt(err);
+    assert!(excerpt.starts_with("Blocked body { color: red } Request blocked Access to this site is blocked"), "{}", excerpt);
+    assert!(!excerpt.contains(['<', '>', '\t', '\r']), "{}", excerpt);
+    assert!(!excerpt.contains("  "), "{}", excerpt);
+    // cut to 300 characters and marked as cut
+    assert!(excerpt.ends_with('…'), "{}", excerpt);
+    assert_eq!(excerpt.chars().count(), 301, "{}", excerpt);
+}
+
+#[test]
+fn html_instead_of_send_document() {
+    let mock = Mock::start(|_| html(403));
+    let err = upload(&mock);
+    assert!(err.contains("The server answered 403 Forbidden with text/html; charset=utf-8"), "{}", err);
+    assert_proxy_hint(&err);
+    assert_sanitized_excerpt(&err);
+    assert_eq!(mock.methods(), ["sendDocument"]);
+}
+
+#[test]
+fn empty_body_instead_of_send_document() {
+    let mock = Mock::start(|_| empty(502));
+    let err = upload(&mock);
+    assert!(err.contains("The server answered 502 Bad Gateway with text/plain"), "{}", err);
+    assert_proxy_hint(&err);
+    assert!(err.contains("The response was empty"), "{}", err);
+}
+
+#[test]
+fn html_instead_of_get_file() {
+    let mock = Mock::start(|request| match request.method.as_str() {
+        "getFile" => html(200),
+        _ => common::telegram(request),
+    });
+    let err = upload(&mock);
+    assert!(err.contains("The server answered 200 OK with text/html; charset=utf-8"), "{}", err);
+    assert_proxy_hint(&err);
+    assert_sanitized_excerpt(&err);
+    assert_eq!(mock.methods(), ["sendDocument", "getFile"]);
+}
+
+#[test]
+fn empty_body_instead_of_get_file() {
+    let mock = Mock::start(|request| match request.method.as_str() {
+        "getFile" => empty(200),
+        _ => common::telegram(request),
+    });
+    let err = upload(&mock);
+    assert!(err.contains("The server answered 200 OK with text/plain"), "{}", err);
+    assert_proxy_hint(&err);
+    assert!(err.contains("The response was empty"), "{}", err);
+}
@@ -0,0 +1,78 @@
+//! sizes and limits are shown in the same decimal units, like the limits of the bot api docs
+
+mod common;
+
+use common::{stderr, stdout, Home, Mock};
+
+/// a sparse file of `size` bytes, nothing is written
+fn sized(home: &Home, name: &str, size: u64) -> std::path::PathBuf {
+    let path = home.file(name, b"");
+    std::fs::File::options().write(true).open(&path).unwrap().set_len(size).unwrap();
+    path
+}
+
+#[test]
+fn too_big_file_and_limit_in_the_same_units() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let big = sized(&home, "big.bin", 60_000_000);
+    // the mock is a local server to teledrop, give it the limit of api.telegram.org
+    let output = home.teledrop(&mock).env("TELEDROP_MAX_UPLOAD_SIZE", "50M").arg("--dry-run").arg(&big).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    // the plan is the result of a dry run, it's on stdout
+    assert!(stdout(&output).contains("File is too big: 60.00 MB, the upload limit is 50.00 MB"), "{}", stdout(&output));
+}
+
+#[test]
+fn dry_run_shows_the_size_in_the_same_units() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let file = sized(&home, "a.bin", 2_500_000);
+    let output = home.teledrop(&mock).arg("--dry-run").arg(&file).output().unwrap();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("2.50 MB"), "{}", stdout(&output));
+    assert!(mock.requests().is_empty());
+}
+
+/// teledrop with the limit of api.telegram.org, a 60 MB file over it, a 20 MB and a 5 byte one under it
+fn batch(home: &Home, mock: &Mock) -> (std::process::Command, [std::path::PathBuf; 3]) {
+    let files = [sized(home, "big.bin", 60_000_000), sized(home, "mid.bin", 20_000_000), home.file("small.txt", b"hello")];
+    let mut command = home.teledrop(mock);
+    command.env("TELEDROP_MAX_UPLOAD_SIZE", "50M").arg("--no-precheck");
+    (command, files)
+}
+
+#[test]
+fn max_size_over_the_upload_limit_does_not_let_a_file_through() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let (mut command, [big, _, small]) = batch(&home, &mock);
+    let output = command.args(["--dry-run", "--max-size", "100M"]).arg(&big).arg(&small).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(3), "{}", stdout(&output));
+    assert!(stdout(&output).contains("File is too big: 60.00 MB, the upload limit is 50.00 MB"), "{}", stdout(&output));
+}
+
+#[test]
+fn file_over_the_upload_limit_is_an_error_not_filtered() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let (mut command, [big, mid, small]) = batch(&home, &mock);
+    let output = command.args(["--max-size", "10M"]).arg(&big).arg(&mid).arg(&small).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    let err = stderr(&output);
+    assert!(err.contains("File is too big: 60.00 MB, the upload limit is 50.00 MB"), "{}", err);
+    // the one under the upload limit but over --max-size is only left out
+    assert!(err.contains("mid.bin (20.00 MB), over --max-size 10.00 MB"), "{}", err);
+    assert!(err.contains("Skipped 1 files (20.00 MB)"), "{}", err);
+    assert_eq!(mock.methods(), ["sendDocument", "getFile"]);
+}
+
+#[test]
+fn upload_limit_raises_the_limit() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let (mut command, [big, _, _]) = batch(&home, &mock);
+    let output = command.args(["--dry-run", "--upload-limit", "100M"]).arg(&big).output().unwrap();
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("big.bin (60.00 MB"), "{}", stdout(&output));
+}
@@ -0,0 +1,54 @@
+//! results go to stdout and everything else to stderr, so URL=$(teledrop f.bin) only gets the results
+
+mod common;
+
+use common::{stderr, stdout, Home, Mock};
+
+#[test]
+fn results_on_stdout_progress_and_warnings_on_stderr() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let file = home.file("a.txt", b"hello");
+    let output = home.teledrop(&mock).arg(&file).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let (out, err) = (stdout(&output), stderr(&output));
+    assert!(out.contains("File ID: BQACAgIAAxk"), "{}", out);
+    assert!(out.lines().last().unwrap().ends_with("/file/bot123456:TEST-token/documents/file_1.txt"), "{}", out);
+    assert!(!out.contains("Uploading"), "{}", out);
+    assert!(!out.contains("bot token"), "{}", out);
+    assert!(err.contains("Uploading a.txt"), "{}", err);
+    assert!(err.contains("The download URL contains your bot token"), "{}", err);
+}
+
+#[test]
+fn json_on_stdout_is_only_the_result() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let file = home.file("a.txt", b"hello");
+    let output = home.teledrop(&mock).arg("--json").arg(&file).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let result: serde_json::Value = serde_json::from_str(stdout(&output).trim()).expect("stdout is one json object");
+    assert_eq!(result["file_id"], "BQACAgIAAxk");
+}
+
+#[test]
+fn missing_file_is_reported_on_stderr_only() {
+    let (home, mock) = (Home::new(), Mock::telegram());
+    let output = home.teledrop(&mock).arg("missing.bin").output().unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("missing.bin"), "{}", stderr(&output));
+    assert!(mock.requests().is_empty());
+}
+
+#[test]
+fn missing_config_hints_are_on_stderr_only() {
+    let home = Home::new();
+    let file = home.file("a.txt", b"hello");
+    let output = home.bare().arg(&file).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("bot_token"), "{}", stderr(&output));
+}
@@ -0,0 +1,32 @@
+//! a failed upload ends the file right there, the URL of a file that was never sent is not asked for
+
+mod common;
+
+use common::{stderr, stdout, Home, Mock, Reply};
+
+#[test]
+fn failed_send_document_makes_no_get_file_request() {
+    let home = Home::new();
+    let mock = Mock::start(|_| Reply::json(400, r#"{"ok":false,"error_code":400,"description":"Bad Request: not enough rights to send documents to the chat"}"#));
+    let file = home.file("a.txt", b"hello");
+    let output = home.teledrop(&mock).arg(&file).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(mock.methods(), ["sendDocument"]);
+    assert_eq!(stdout(&output), "");
+    let err = stderr(&output);
+    assert!(err.contains("not enough rights to send documents"), "{}", err);
+    assert_eq!(err.matches("✘").count(), 1, "a single error: {}", err);
+}
+
+#[test]
+fn response_without_a_file_makes_no_get_file_request() {
+    let home = Home::new();
+    let mock = Mock::start(|_| Reply::json(200, r#"{"ok":true,"result":{"message_id":7,"chat":{"id":1,"type":"private","first_name":"Ann"},"date":1700000000}}"#));
+    let file = home.file("a.txt", b"hello");
+    let output = home.teledrop(&mock).arg(&file).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(mock.methods(), ["sendDocument"]);
+    assert!(stderr(&output).contains("no file_id"), "{}", stderr(&output));
+}